@@ -2,240 +2,267 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{anyhow, Result};
-use reqwest::Url;
-use select::document::Document;
-use select::predicate::Name;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::{Method, StatusCode, Url};
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
-use tokio::sync::OnceCell;
-
-use super::request::{LibraryPlaylistCreationRequest, Objects, SearchQuery};
-use super::response::{ListResponse, LibrarySong, LibraryPlaylist, SearchResponse, Response};
-
-fn char_windows<'a>(src: &'a str, win_size: usize) -> impl Iterator<Item = (usize, &'a str)> {
-    src.char_indices().flat_map(move |(from, _)| {
-        src[from..]
-            .char_indices()
-            .skip(win_size - 1)
-            .next()
-            .map(|(to, c)| (from, &src[from..from + to + c.len_utf8()]))
-    })
-}
+use tokio::sync::Mutex;
 
-fn str_byteindex_of_substr(haystack: &str, needle: &str) -> Option<usize> {
-    char_windows(haystack, needle.len()).find_map(|(pos, window)| (window == needle).then_some(pos))
-}
+use super::error::{Error, Result};
+use super::request::{CatalogSongsQuery, LibraryAddQuery, LibraryPlaylistCreationRequest, Objects, SearchQuery};
+use super::response::{ListResponse, LibrarySong, LibraryPlaylist, SearchResponse, Song};
+use super::token::{DeveloperToken, TokenProvider, WebPlayerTokenProvider};
 
-fn str_byteindex_of_predicate<P>(haystack: &str, predicate: P) -> Option<usize>
-where
-    P: Fn(char) -> bool,
-{
-    haystack
-        .char_indices()
-        .find_map(|(pos, c)| predicate(c).then_some(pos))
-}
+/// How long before it expires a developer token is replaced.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
 
-async fn get_token() -> Result<Arc<String>> {
-    // get HTML
-    let base_url = Url::from_str("https://music.apple.com")?;
-    let html = reqwest::get(base_url.clone()).await?.text().await?;
-    let js_url = {
-        let doc = Document::from(html.as_str());
-
-        // get JS URL from HTML
-        let js_relative_url = doc
-            .find(Name("script"))
-            .filter_map(|script| {
-                script
-                    .attr("src")
-                    .and_then(|src| src.starts_with("/assets/index-").then_some(src))
-            })
-            .next()
-            .ok_or(anyhow!("could not find JS in HTML"))?;
-
-        base_url.join(js_relative_url)?
-    };
-
-    // get JS
-    let js = reqwest::get(js_url).await?.text().await?;
-
-    // get position of JWT from JS
-    let start = str_byteindex_of_substr(
-        &js,
-        "eyJhbGciOiJFUzI1NiIsInR5cCI6IkpXVCIsImtpZCI6IldlYlBsYXlLaWQifQ.",
-    )
-    .ok_or(anyhow!("could not find start of token in JS"))?;
-    let len = str_byteindex_of_predicate(&js[start..], |c| c == '"' || c == '\'')
-        .ok_or(anyhow!("could not find end of token in JS"))?;
-    let end = start + len;
-
-    // return JWT
-    Ok(Arc::new(js[start..end].to_string()))
+/// Parses a base URL and joins an endpoint or relative URL to it.
+pub(super) fn join_url(base_url: &str, endpoint: &str) -> Result<Url> {
+    Url::from_str(base_url)
+        .and_then(|base_url| base_url.join(endpoint))
+        .map_err(|e| Error::InvalidUrl(format!("{}{}: {}", base_url, endpoint, e)))
 }
 
 #[derive(Debug, Clone)]
 pub struct ClientConfig {
     pub user_token: String,
+    /// Provider of developer tokens, reading the token of the web player at `web_base_url` if not set
+    pub token_provider: Option<Arc<dyn TokenProvider>>,
+    /// Base URL of the Apple Music API
+    pub api_base_url: String,
+    /// Base URL of the Apple Music web player, which the developer token is read from
+    pub web_base_url: String,
+    /// Number of times a request is retried after a server error, rate limit or connection error
     pub max_retries: usize,
+    /// Time to wait before the first retry, doubled for every retry after it
     pub retry_interval: Duration,
+    /// Longest time to wait between retries, unless the API asks for longer with a `Retry-After` header
+    pub max_retry_interval: Duration,
 }
 
 impl Default for ClientConfig {
     fn default() -> Self {
         Self {
             user_token: String::new(),
+            token_provider: None,
+            api_base_url: String::from("https://api.music.apple.com"),
+            web_base_url: String::from("https://music.apple.com"),
             max_retries: 30,
             retry_interval: Duration::from_secs(1),
+            max_retry_interval: Duration::from_secs(60),
         }
     }
 }
 
+/// Parses a `Retry-After` header, given either in seconds or as an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Connection errors and timeouts may be temporary, while other errors (such as invalid URLs) are not. Requests
+/// which are not idempotent are only retried if they could not connect, as they may have been received otherwise.
+fn is_retryable(e: &reqwest::Error, method: &Method) -> bool {
+    e.is_connect() || (method.is_idempotent() && (e.is_timeout() || e.is_request() || e.is_body()))
+}
+
 #[derive(Clone)]
 pub struct Client {
     config: Arc<ClientConfig>,
-    token: OnceCell<Arc<String>>,
+    token_provider: Arc<dyn TokenProvider>,
+    /// Developer token in use, shared between clones of the client
+    token: Arc<Mutex<Option<Arc<DeveloperToken>>>>,
     client: reqwest::Client,
 }
 
 impl Client {
-    async fn get_token(&self) -> Result<&str> {
-        self.token
-            .get_or_try_init(get_token)
-            .await
-            .map(|s| s.as_str())
+    /// Returns the developer token in use, getting a new one if there is none or it is about to expire.
+    async fn get_token(&self) -> Result<Arc<DeveloperToken>> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref().filter(|token| !token.expires_within(TOKEN_REFRESH_MARGIN)) {
+            return Ok(token.clone());
+        }
+
+        let new_token = Arc::new(self.token_provider.token().await?);
+        log::debug!("Apple Music: got developer token expiring at {:?}", new_token.expires_at);
+        *token = Some(new_token.clone());
+        Ok(new_token)
     }
 
-    pub fn new(user_token: &str) -> Self {
-        Self::new_with_config(ClientConfig {
-            user_token: user_token.to_string(),
-            ..Default::default()
-        })
+    /// Forgets a developer token which was rejected, unless it has been replaced already.
+    async fn invalidate_token(&self, rejected: &DeveloperToken) {
+        let mut token = self.token.lock().await;
+        if token.as_ref().is_some_and(|token| token.token == rejected.token) {
+            *token = None;
+        }
     }
 
     pub fn new_with_config(config: ClientConfig) -> Self {
+        let token_provider = config
+            .token_provider
+            .clone()
+            .unwrap_or_else(|| Arc::new(WebPlayerTokenProvider::new(&config.web_base_url)));
         Self {
             config: Arc::new(config),
-            token: OnceCell::new(),
+            token_provider,
+            token: Arc::new(Mutex::new(None)),
             client: reqwest::Client::new(),
         }
     }
 
-    pub async fn get_with_query<Q: Serialize + ?Sized, O: DeserializeOwned>(&self, endpoint: &str, query: Option<&Q>) -> Result<O> {
-        let base_url = Url::from_str("https://api.music.apple.com")?;
-        let url = base_url.join(endpoint)?;
-
-        let apple_music_token = self.get_token().await?;
+    /// Time to wait before the given retry (starting at 0): exponential backoff with jitter, so that concurrent
+    /// clients do not retry in lockstep.
+    fn backoff(&self, retry: usize) -> Duration {
+        let interval = self
+            .config
+            .retry_interval
+            .saturating_mul(1 << retry.min(16))
+            .min(self.config.max_retry_interval);
+        interval.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
 
-        let mut text = String::new();
-        for retry in 0..self.config.max_retries {
-            if retry > 0 {
-                tokio::time::sleep(self.config.retry_interval).await;
+    /// Sends a request, retrying on server errors, rate limits and connection errors. Returns the body of the first
+    /// successful response, or the error of the first response which is not retried.
+    async fn request<Q: Serialize + ?Sized, I: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        query: Option<&Q>,
+        data: Option<&I>,
+    ) -> Result<String> {
+        let url = join_url(&self.config.api_base_url, endpoint)?;
+
+        let mut retry = 0;
+        let mut refreshed_token = false;
+        let mut rejected: Option<(Arc<DeveloperToken>, Error)> = None;
+        loop {
+            let apple_music_token = self.get_token().await?;
+            if let Some((rejected_token, error)) = rejected.take() {
+                // the token was rejected and getting a new one gave the same, so it would be rejected again
+                if rejected_token.token == apple_music_token.token {
+                    return Err(error);
+                }
             }
 
-            log::debug!("Apple Music: GET {}", &url);
-            let mut builder = self.client.get(url.clone())
+            log::debug!("Apple Music: {} {}", &method, &url);
+            let mut builder = self
+                .client
+                .request(method.clone(), url.clone())
                 .header("Music-User-Token", &self.config.user_token)
-                .header("Origin", "https://music.apple.com")
-                .bearer_auth(&apple_music_token);
+                .header("Origin", &self.config.web_base_url)
+                .bearer_auth(&apple_music_token.token);
 
             if let Some(query) = query {
                 builder = builder.query(query);
             }
+            if let Some(data) = data {
+                builder = builder.json(data);
+            }
 
-            let res = builder.send().await?;
-            let status = res.status();
-            let version = res.version();
-            text = res.text().await?;
-            log::debug!("Apple Music: {:?} {}: {}", version, status, text);
-
-            if !status.is_server_error() {
-                break;
+            let (error, retry_after) = match builder.send().await {
+                Ok(res) => {
+                    let status = res.status();
+                    let version = res.version();
+                    let retry_after = res
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(parse_retry_after);
+                    match res.text().await {
+                        Ok(text) => {
+                            log::debug!("Apple Music: {:?} {}: {}", version, status, text);
+                            if status.is_success() {
+                                return Ok(text);
+                            }
+                            let error = Error::from_response(status, &text, retry_after);
+                            if status == StatusCode::UNAUTHORIZED && !refreshed_token {
+                                // the developer token may have expired early or been revoked, so retry once with a new one
+                                log::debug!("Apple Music: unauthorized, getting a new developer token");
+                                self.invalidate_token(&apple_music_token).await;
+                                refreshed_token = true;
+                                rejected = Some((apple_music_token, error));
+                                continue;
+                            }
+                            if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                                return Err(error);
+                            }
+                            (error, retry_after)
+                        }
+                        Err(e) if is_retryable(&e, &method) => (Error::Transport(e), None),
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Err(e) if is_retryable(&e, &method) => (Error::Transport(e), None),
+                Err(e) => return Err(e.into()),
+            };
+
+            if retry >= self.config.max_retries {
+                log::debug!("Apple Music: {} {} failed after {} attempts", &method, &url, retry + 1);
+                return Err(error);
             }
-        }
 
-        let json: Response<O> = serde_json::from_str(&text)?;
-        match json {
-            Response::Success(data) => Ok(data),
-            Response::Error(e) => Err(anyhow!(e)),
+            let wait = retry_after.unwrap_or_else(|| self.backoff(retry));
+            log::debug!("Apple Music: {}, retrying in {:?}", error, wait);
+            tokio::time::sleep(wait).await;
+            retry += 1;
         }
     }
 
-    pub async fn get<O: DeserializeOwned>(&self, endpoint: &str) -> Result<O> {
-        self.get_with_query::<(), O>(endpoint, None).await
+    /// Sends a request and decodes the JSON response body.
+    async fn request_json<Q: Serialize + ?Sized, I: Serialize + ?Sized, O: DeserializeOwned>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        query: Option<&Q>,
+        data: Option<&I>,
+    ) -> Result<O> {
+        let text = self.request(method, endpoint, query, data).await?;
+        serde_json::from_str(&text).map_err(|e| Error::decode(e, &text))
     }
 
-    pub async fn post<I: Serialize + ?Sized, O: DeserializeOwned>(&self, endpoint: &str, data: &I) -> Result<O> {
-        let base_url = Url::from_str("https://api.music.apple.com")?;
-        let url = base_url.join(endpoint)?;
+    /// Sends a request which has no response body when successful.
+    async fn request_no_content<Q: Serialize + ?Sized, I: Serialize + ?Sized>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        query: Option<&Q>,
+        data: Option<&I>,
+    ) -> Result<()> {
+        self.request(method, endpoint, query, data).await.map(|_| ())
+    }
 
-        let apple_music_token = self.get_token().await?;
+    pub async fn get_with_query<Q: Serialize + ?Sized, O: DeserializeOwned>(&self, endpoint: &str, query: Option<&Q>) -> Result<O> {
+        self.request_json::<Q, (), O>(Method::GET, endpoint, query, None).await
+    }
 
-        let mut text = String::new();
-        for retry in 0..self.config.max_retries {
-            if retry > 0 {
-                tokio::time::sleep(self.config.retry_interval).await;
-            }
+    pub async fn get<O: DeserializeOwned>(&self, endpoint: &str) -> Result<O> {
+        self.get_with_query::<(), O>(endpoint, None).await
+    }
 
-            log::debug!("Apple Music: POST {}", &url);
-            let res = self.client
-                .post(url.clone())
-                .json(data)
-                .header("Music-User-Token", &self.config.user_token)
-                .header("Origin", "https://music.apple.com")
-                .bearer_auth(&apple_music_token)
-                .send()
-                .await?;
-
-            let status = res.status();
-            let version = res.version();
-            text = res.text().await?;
-            log::debug!("Apple Music: {:?} {}: {}", version, status, text);
-
-            if !status.is_server_error() {
-                break;
-            }
-        }
+    pub async fn post<I: Serialize + ?Sized, O: DeserializeOwned>(&self, endpoint: &str, data: &I) -> Result<O> {
+        self.request_json::<(), I, O>(Method::POST, endpoint, None, Some(data)).await
+    }
 
-        let json: Response<O> = serde_json::from_str(&text)?;
-        match json {
-            Response::Success(data) => Ok(data),
-            Response::Error(e) => Err(anyhow!(e)),
-        }
+    pub async fn post_no_content_with_query<Q: Serialize + ?Sized, I: Serialize + ?Sized>(&self, endpoint: &str, query: Option<&Q>, data: Option<&I>) -> Result<()> {
+        self.request_no_content(Method::POST, endpoint, query, data).await
     }
 
-    #[allow(unused)]
     pub async fn post_no_content<I: Serialize + ?Sized>(&self, endpoint: &str, data: &I) -> Result<()> {
-        let base_url = Url::from_str("https://api.music.apple.com")?;
-        let url = base_url.join(endpoint)?;
-
-        let apple_music_token = self.get_token().await?;
-
-        for retry in 0..self.config.max_retries {
-            if retry > 0 {
-                tokio::time::sleep(self.config.retry_interval).await;
-            }
-
-            log::debug!("Apple Music: POST {}", &url);
-            let res = self.client
-                .post(url.clone())
-                .json(data)
-                .header("Music-User-Token", &self.config.user_token)
-                .header("Origin", "https://music.apple.com")
-                .bearer_auth(&apple_music_token)
-                .send()
-                .await?;
-
-            let status = res.status();
-            let version = res.version();
-            log::debug!("Apple Music: {:?} {}", version, status);
+        self.post_no_content_with_query::<(), I>(endpoint, None, Some(data)).await
+    }
 
-            if !status.is_server_error() {
-                break;
-            }
-        }
+    #[allow(unused)]
+    pub async fn put<I: Serialize + ?Sized, O: DeserializeOwned>(&self, endpoint: &str, data: &I) -> Result<O> {
+        self.request_json::<(), I, O>(Method::PUT, endpoint, None, Some(data)).await
+    }
 
-        Ok(())
+    #[allow(unused)]
+    pub async fn delete(&self, endpoint: &str) -> Result<()> {
+        self.request_no_content::<(), ()>(Method::DELETE, endpoint, None, None).await
     }
 
     #[allow(unused)]
@@ -255,12 +282,319 @@ impl Client {
         self.get(&format!("/v1/me/library/playlists/{}/tracks", playlist_id)).await
     }
 
-    #[allow(unused)]
     pub async fn add_library_playlist_tracks(&self, playlist_id: &str, tracks: &Objects) -> Result<()> {
         self.post_no_content(&format!("/v1/me/library/playlists/{}/tracks", playlist_id), tracks).await
     }
 
+    pub async fn add_to_library(&self, query: &LibraryAddQuery) -> Result<()> {
+        self.post_no_content_with_query::<_, ()>("/v1/me/library", Some(query), None).await
+    }
+
     pub async fn search_catalog(&self, storefront: &str, query: &SearchQuery<'_>) -> Result<SearchResponse> {
         self.get_with_query(&format!("/v1/catalog/{}/search", storefront), Some(query)).await
     }
+
+    pub async fn get_catalog_songs(&self, storefront: &str, query: &CatalogSongsQuery<'_>) -> Result<ListResponse<Song>> {
+        self.get_with_query(&format!("/v1/catalog/{}/songs", storefront), Some(query)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::apple_music::request::Object;
+    use crate::apple_music::token::StaticTokenProvider;
+    use crate::apple_music::ObjectType;
+    use crate::mock_server::{self, MockResponse, MockServer};
+
+    fn client(server: &MockServer, max_retries: usize) -> Client {
+        Client::new_with_config(ClientConfig {
+            user_token: String::from("user-token"),
+            api_base_url: server.url(),
+            web_base_url: server.url(),
+            max_retries,
+            retry_interval: Duration::from_millis(1),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn parses_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn backoff_grows_up_to_max_interval() {
+        let client = Client::new_with_config(ClientConfig::default());
+        let first = client.backoff(0);
+        assert!(first >= Duration::from_millis(500) && first <= Duration::from_secs(1));
+        let third = client.backoff(2);
+        assert!(third >= Duration::from_secs(2) && third <= Duration::from_secs(4));
+        let late = client.backoff(100);
+        assert!(late >= Duration::from_secs(30) && late <= Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_and_rate_limits() {
+        let apple_music = mock_server::apple_music(Vec::new(), vec![mock_server::apple_music_library_playlist("p.1", "Chill")]);
+        let count = AtomicUsize::new(0);
+        let server = MockServer::start(move |request| {
+            if !request.path.starts_with("/v1/") {
+                return apple_music(request);
+            }
+            match count.fetch_add(1, Ordering::SeqCst) {
+                0 => MockResponse::json(503, json!({})),
+                1 => MockResponse::json(429, json!({})).with_header("Retry-After", "0"),
+                _ => apple_music(request),
+            }
+        })
+        .await;
+
+        let playlists = client(&server, 2).get_library_playlists().await.unwrap();
+        assert_eq!(playlists.data[0].attributes.name, "Chill");
+        assert_eq!(server.requests().iter().filter(|r| r.path.starts_with("/v1/")).count(), 3);
+    }
+
+    #[tokio::test]
+    async fn returns_error_when_retries_are_exhausted() {
+        let apple_music = mock_server::apple_music(Vec::new(), Vec::new());
+        let server = MockServer::start(move |request| {
+            if request.path.starts_with("/v1/") {
+                MockResponse::json(
+                    500,
+                    json!({ "errors": [{ "id": "1", "title": "Upstream Error", "detail": "Try again", "status": "500", "code": "50001" }] }),
+                )
+            } else {
+                apple_music(request)
+            }
+        })
+        .await;
+
+        let tracks = Objects {
+            data: vec![Object {
+                id: String::from("1"),
+                object_type: ObjectType::Songs,
+            }],
+        };
+        let e = client(&server, 2)
+            .add_library_playlist_tracks("p.1", &tracks)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            &e,
+            Error::Server {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                response: Some(_)
+            }
+        ));
+        assert!(e.to_string().contains("Upstream Error"));
+        let attempts = server.requests().iter().filter(|r| r.method == "POST").count();
+        assert_eq!(attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn returns_error_response_of_request_without_content() {
+        let server = MockServer::start(mock_server::apple_music(Vec::new(), Vec::new())).await;
+        let tracks = Objects { data: Vec::new() };
+        let e = client(&server, 0)
+            .post_no_content("/v1/me/library/unknown", &tracks)
+            .await
+            .unwrap_err();
+        assert!(matches!(&e, Error::NotFound(Some(response)) if response.errors[0].status == "404"));
+    }
+
+    #[tokio::test]
+    async fn returns_typed_errors() {
+        let server = MockServer::start(mock_server::apple_music(Vec::new(), Vec::new())).await;
+        let e = Client::new_with_config(ClientConfig {
+            user_token: String::new(),
+            token_provider: None,
+            api_base_url: server.url(),
+            web_base_url: server.url(),
+            ..Default::default()
+        })
+        .get_library_playlists()
+        .await
+        .unwrap_err();
+        assert!(e.is_auth());
+        assert!(matches!(e, Error::Forbidden(Some(_))));
+        assert!(e.to_string().contains("appleMusicUserToken"), "{}", e);
+
+        let e = Client::new_with_config(ClientConfig {
+            user_token: String::from("user-token"),
+            token_provider: Some(Arc::new(StaticTokenProvider::new(&mock_server::apple_music_web_token(json!({}))).unwrap())),
+            api_base_url: server.url(),
+            web_base_url: server.url(),
+            ..Default::default()
+        })
+        .get_library_playlists()
+        .await
+        .unwrap_err();
+        assert!(e.is_auth());
+        assert!(matches!(e, Error::Unauthorized(Some(_))));
+        assert!(e.to_string().contains("developer token"), "{}", e);
+
+        let e = client(&server, 0).get::<ListResponse<LibraryPlaylist>>("/").await;
+        assert!(matches!(e, Err(Error::Decode { body, .. }) if body.starts_with("<html>") && body.chars().count() <= 200));
+    }
+
+    fn js_requests(server: &MockServer) -> usize {
+        server.requests().iter().filter(|r| r.path == "/assets/index-mock.js").count()
+    }
+
+    #[tokio::test]
+    async fn refreshes_rejected_developer_token() {
+        let tokens = [
+            mock_server::apple_music_web_token(json!({ "iss": "AMPWebPlay", "n": 1 })),
+            mock_server::apple_music_web_token(json!({ "iss": "AMPWebPlay", "n": 2 })),
+        ];
+        let count = AtomicUsize::new(0);
+        let server = MockServer::start(move |request| {
+            if request.path == "/assets/index-mock.js" {
+                let token = &tokens[count.fetch_add(1, Ordering::SeqCst).min(1)];
+                return mock_server::apple_music_web_player(request, token).unwrap();
+            }
+            if let Some(response) = mock_server::apple_music_web_player(request, "") {
+                return response;
+            }
+            // only the second token is accepted
+            if request.header("authorization") == Some(&format!("Bearer {}", &tokens[1])) {
+                MockResponse::json(200, json!({ "data": [] }))
+            } else {
+                mock_server::apple_music_error(401, "Unauthorized", "Authentication Failed")
+            }
+        })
+        .await;
+
+        let client = client(&server, 0);
+        client.get_library_playlists().await.unwrap();
+        client.get_library_playlists().await.unwrap();
+        assert_eq!(js_requests(&server), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_rejected_tokens() {
+        let rejected = mock_server::apple_music_web_token(json!({ "iss": "AMPWebPlay" }));
+        let server = MockServer::start(move |request| {
+            mock_server::apple_music_web_player(request, &rejected)
+                .unwrap_or_else(|| mock_server::apple_music_error(401, "Unauthorized", "Authentication Failed"))
+        })
+        .await;
+        let e = client(&server, 0).get_library_playlists().await.unwrap_err();
+        assert!(matches!(e, Error::Unauthorized(_)));
+        // the same token was scraped again after the first 401, so the request was not sent again
+        assert_eq!(js_requests(&server), 2);
+        assert_eq!(server.requests().iter().filter(|r| r.path.starts_with("/v1/")).count(), 1);
+
+        // a rejected Music-User-Token is not fixed by a new developer token
+        let server = MockServer::start(mock_server::apple_music(Vec::new(), Vec::new())).await;
+        let e = Client::new_with_config(ClientConfig {
+            api_base_url: server.url(),
+            web_base_url: server.url(),
+            ..Default::default()
+        })
+        .get_library_playlists()
+        .await
+        .unwrap_err();
+        assert!(matches!(e, Error::Forbidden(_)));
+        assert_eq!(js_requests(&server), 1);
+        assert_eq!(server.requests().iter().filter(|r| r.path.starts_with("/v1/")).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn refreshes_developer_token_before_it_expires() {
+        let expiring = mock_server::apple_music_web_token(json!({ "exp": Utc::now().timestamp() + 60 }));
+        let server = MockServer::start(move |request| {
+            mock_server::apple_music_web_player(request, &expiring)
+                .unwrap_or_else(|| MockResponse::json(200, json!({ "data": [] })))
+        })
+        .await;
+        let expiring_client = client(&server, 0);
+        expiring_client.get_library_playlists().await.unwrap();
+        expiring_client.get_library_playlists().await.unwrap();
+        assert_eq!(js_requests(&server), 2);
+
+        let valid = mock_server::apple_music_web_token(json!({ "exp": Utc::now().timestamp() + 3600 }));
+        let server = MockServer::start(move |request| {
+            mock_server::apple_music_web_player(request, &valid)
+                .unwrap_or_else(|| MockResponse::json(200, json!({ "data": [] })))
+        })
+        .await;
+        let valid_client = client(&server, 0);
+        valid_client.get_library_playlists().await.unwrap();
+        // clones share the token
+        valid_client.clone().get_library_playlists().await.unwrap();
+        assert_eq!(js_requests(&server), 1);
+    }
+
+    /// Accepts connections and closes them without responding, returning the address and the number of connections.
+    async fn closing_server() -> (std::net::SocketAddr, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let count = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                count.fetch_add(1, Ordering::SeqCst);
+                let mut buf = [0; 4096];
+                let _ = tokio::io::AsyncReadExt::read(&mut stream, &mut buf).await;
+            }
+        });
+        (addr, connections)
+    }
+
+    #[tokio::test]
+    async fn retries_transport_errors_only_for_idempotent_requests() {
+        let (addr, connections) = closing_server().await;
+        let client = Client::new_with_config(ClientConfig {
+            user_token: String::from("user-token"),
+            token_provider: Some(Arc::new(StaticTokenProvider::new(mock_server::APPLE_MUSIC_TOKEN).unwrap())),
+            api_base_url: format!("http://{}", addr),
+            max_retries: 2,
+            retry_interval: Duration::from_millis(1),
+            ..Default::default()
+        });
+
+        let e = client.get_library_playlists().await.unwrap_err();
+        assert!(matches!(e, Error::Transport(_)), "{}", e);
+        assert_eq!(connections.swap(0, Ordering::SeqCst), 3);
+
+        // the playlist may have been created even though no response was received
+        let e = client
+            .post::<_, serde_json::Value>("/v1/me/library/playlists", &json!({ "attributes": { "name": "Road trip" } }))
+            .await
+            .unwrap_err();
+        assert!(matches!(e, Error::Transport(_)), "{}", e);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn uses_configured_developer_token() {
+        let server = MockServer::start(mock_server::apple_music(Vec::new(), Vec::new())).await;
+        let config = ClientConfig {
+            user_token: String::from("user-token"),
+            token_provider: Some(Arc::new(StaticTokenProvider::new(mock_server::APPLE_MUSIC_TOKEN).unwrap())),
+            api_base_url: server.url(),
+            web_base_url: server.url(),
+            ..Default::default()
+        };
+        Client::new_with_config(config.clone()).get_library_playlists().await.unwrap();
+        assert_eq!(js_requests(&server), 0);
+
+        let expired = mock_server::apple_music_web_token(json!({ "exp": 1700000000 }));
+        let e = Client::new_with_config(ClientConfig {
+            token_provider: Some(Arc::new(StaticTokenProvider::new(&expired).unwrap())),
+            ..config
+        })
+        .get_library_playlists()
+        .await
+        .unwrap_err();
+        assert!(matches!(e, Error::Token(_)));
+    }
 }
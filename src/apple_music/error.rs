@@ -0,0 +1,133 @@
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use super::response::ErrorResponse;
+
+/// Number of characters of an undecodable response body kept in [`Error::Decode`].
+const BODY_SNIPPET_LEN: usize = 200;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The developer token is invalid or has expired (401)
+    Unauthorized(Option<ErrorResponse>),
+    /// The Music-User-Token is invalid, has expired or does not give access, for example without an Apple Music
+    /// subscription (403)
+    Forbidden(Option<ErrorResponse>),
+    /// The resource does not exist (404)
+    NotFound(Option<ErrorResponse>),
+    /// Still rate limited after all retries (429)
+    RateLimited {
+        retry_after: Option<Duration>,
+        response: Option<ErrorResponse>,
+    },
+    /// Still failing with a server error after all retries (5xx)
+    Server {
+        status: StatusCode,
+        response: Option<ErrorResponse>,
+    },
+    /// Any other unsuccessful status, such as 400 for an invalid request
+    Status {
+        status: StatusCode,
+        response: Option<ErrorResponse>,
+    },
+    /// The response body could not be decoded
+    Decode {
+        source: serde_json::Error,
+        /// Start of the response body
+        body: String,
+    },
+    /// The request could not be sent, or the response could not be read
+    Transport(reqwest::Error),
+    /// No developer token could be found
+    Token(String),
+    /// A base URL or endpoint is not a valid URL
+    InvalidUrl(String),
+}
+
+impl Error {
+    /// Creates the error for an unsuccessful response, keeping the error details in the body if there are any.
+    pub fn from_response(status: StatusCode, body: &str, retry_after: Option<Duration>) -> Self {
+        let response = serde_json::from_str::<ErrorResponse>(body).ok();
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized(response),
+            StatusCode::FORBIDDEN => Self::Forbidden(response),
+            StatusCode::NOT_FOUND => Self::NotFound(response),
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited { retry_after, response },
+            status if status.is_server_error() => Self::Server { status, response },
+            status => Self::Status { status, response },
+        }
+    }
+
+    pub fn decode(source: serde_json::Error, body: &str) -> Self {
+        Self::Decode {
+            source,
+            body: body.chars().take(BODY_SNIPPET_LEN).collect(),
+        }
+    }
+
+    /// Whether the tokens were not accepted, in which case every following request will fail the same way.
+    pub fn is_auth(&self) -> bool {
+        matches!(self, Self::Unauthorized(_) | Self::Forbidden(_))
+    }
+
+    /// Error details from the response, if the API sent any.
+    pub fn response(&self) -> Option<&ErrorResponse> {
+        match self {
+            Self::Unauthorized(response)
+            | Self::Forbidden(response)
+            | Self::NotFound(response)
+            | Self::RateLimited { response, .. }
+            | Self::Server { response, .. }
+            | Self::Status { response, .. } => response.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unauthorized(_) => write!(f, "unauthorized, the developer token may be invalid or expired")?,
+            Self::Forbidden(_) => write!(
+                f,
+                "forbidden, the Music-User-Token (appleMusicUserToken in the config file) may be invalid, expired or \
+                 not have access to Apple Music"
+            )?,
+            Self::NotFound(_) => write!(f, "not found")?,
+            Self::RateLimited {
+                retry_after: Some(retry_after),
+                ..
+            } => write!(f, "rate limited, retry after {} seconds", retry_after.as_secs())?,
+            Self::RateLimited { .. } => write!(f, "rate limited")?,
+            Self::Server { status, .. } | Self::Status { status, .. } => write!(f, "{}", status)?,
+            Self::Decode { source, body } => return write!(f, "could not decode response: {}: {}", source, body),
+            Self::Transport(e) => return write!(f, "{}", e),
+            Self::Token(message) => return write!(f, "could not get developer token: {}", message),
+            Self::InvalidUrl(message) => return write!(f, "invalid URL: {}", message),
+        }
+        match self.response() {
+            Some(response) if !response.errors.is_empty() => write!(f, " ({})", response),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode { source, .. } => Some(source),
+            Self::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Transport(e)
+    }
+}
@@ -3,10 +3,15 @@ use std::fmt;
 use serde::{Deserialize, Serialize};
 
 mod client;
-pub use client::Client;
+pub use client::{Client, ClientConfig};
+mod error;
+pub use error::{Error, Result};
 
 pub mod request;
+#[allow(dead_code)]
 pub mod response;
+mod token;
+pub use token::{MusicKitTokenProvider, StaticTokenProvider, TokenProvider, MUSICKIT_TOKEN_LIFETIME};
 
 pub trait ToRequestObject {
     fn id(&self) -> &str;
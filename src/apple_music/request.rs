@@ -66,4 +66,34 @@ pub struct SearchQuery<'a> {
 	/// - Value: topResults
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub with: Option<&'a str>,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct CatalogSongsQuery<'a> {
+	/// The unique identifiers for the songs, as a comma-separated list. The maximum fetch limit is 300.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub ids: Option<&'a str>,
+
+	/// The ISRC values for the songs, as a comma-separated list (for example filter[isrc]=USUM71703861).
+	#[serde(rename = "filter[isrc]", skip_serializing_if = "Option::is_none")]
+	pub isrc: Option<&'a str>,
+
+	/// The localization to use, specified by a language tag. The possible values are in the supportedLanguageTags array belonging to the Storefront object specified by storefront. Otherwise, the default is defaultLanguageTag in Storefront.
+	#[serde(rename = "l", skip_serializing_if = "Option::is_none")]
+	pub localization: Option<&'a str>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct LibraryAddQuery {
+	/// Catalog song IDs to add, as a comma-separated list.
+	#[serde(rename = "ids[songs]", skip_serializing_if = "Option::is_none")]
+	pub songs: Option<String>,
+
+	/// Catalog album IDs to add, as a comma-separated list.
+	#[serde(rename = "ids[albums]", skip_serializing_if = "Option::is_none")]
+	pub albums: Option<String>,
+
+	/// Catalog playlist IDs to add, as a comma-separated list.
+	#[serde(rename = "ids[playlists]", skip_serializing_if = "Option::is_none")]
+	pub playlists: Option<String>,
+}
@@ -1,12 +1,11 @@
 use std::fmt;
 
-use anyhow::Result;
 use async_trait::async_trait;
 use chrono::{DateTime, NaiveDate, Utc};
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 
-use super::{AudioVariant, Client, ContentRating, ObjectType, PlaylistType, ToRequestObject};
+use super::{AudioVariant, Client, Result, ContentRating, ObjectType, PlaylistType, ToRequestObject};
 
 #[async_trait]
 pub trait PaginatedResponse<T> {
@@ -91,13 +90,6 @@ impl fmt::Display for ErrorResponse {
 
 impl std::error::Error for ErrorResponse {}
 
-#[derive(Debug, Clone, Deserialize)]
-#[serde(untagged)]
-pub enum Response<T> {
-    Success(T),
-    Error(ErrorResponse),
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct ListResponse<T> {
     #[serde(default)]
@@ -124,7 +116,6 @@ pub struct Artwork {
 }
 
 impl Artwork {
-    #[allow(unused)]
     pub fn url_with_dimensions(&self, fallback_width: u64, fallback_height: u64) -> String {
         self.url
             .replace(
@@ -195,6 +186,16 @@ pub struct LibrarySong {
     pub attributes: LibrarySongAttributes,
 }
 
+impl LibrarySong {
+    /// ID of the catalog song this library song was added from, if any.
+    pub fn catalog_id(&self) -> Option<&str> {
+        self.attributes
+            .play_params
+            .as_ref()
+            .and_then(|p| p.catalog_id.as_deref())
+    }
+}
+
 impl ToRequestObject for LibrarySong {
     fn id(&self) -> &str {
         &self.id
@@ -267,6 +268,53 @@ impl ToRequestObject for Song {
     }
 }
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumAttributes {
+    pub artist_name: String,
+    #[serde(default)]
+    pub artist_url: Option<String>,
+    pub artwork: Artwork,
+    #[serde(default)]
+    pub content_rating: ContentRating,
+    #[serde(default)]
+    pub copyright: Option<String>,
+    #[serde(default)]
+    pub genre_names: Vec<String>,
+    pub is_compilation: bool,
+    pub is_complete: bool,
+    pub is_single: bool,
+    pub name: String,
+    #[serde(default)]
+    pub play_params: Option<PlayParams>,
+    #[serde(default)]
+    pub record_label: Option<String>,
+    #[serde(default)]
+    pub release_date: Option<NaiveDate>,
+    pub track_count: u64,
+    #[serde(default)]
+    pub upc: Option<String>,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Album {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub object_type: ObjectType,
+    pub href: String,
+    pub attributes: AlbumAttributes,
+}
+
+impl ToRequestObject for Album {
+    fn id(&self) -> &str {
+        &self.id
+    }
+    fn object_type(&self) -> &ObjectType {
+        &self.object_type
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Description {
     pub standard: String,
@@ -378,6 +426,27 @@ impl PaginatedResponse<Song> for SearchResult<Song> {
     }
 }
 
+#[async_trait]
+impl PaginatedResponse<Album> for SearchResult<Album> {
+    fn data_mut(&mut self) -> &mut Vec<Album> {
+        &mut self.data
+    }
+
+    fn next_url(&self) -> Option<&str> {
+        self.next.as_deref()
+    }
+
+    #[allow(unused)]
+    async fn next(&self, client: &Client) -> Result<Option<Self>> {
+        if let Some(next_url) = &self.next {
+            let res: SearchResponse = client.get(next_url).await?;
+            Ok(res.results.albums)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 #[async_trait]
 impl PaginatedResponse<Playlist> for SearchResult<Playlist> {
     fn data_mut(&mut self) -> &mut Vec<Playlist> {
@@ -404,8 +473,8 @@ impl PaginatedResponse<Playlist> for SearchResult<Playlist> {
 pub struct SearchResults {
     // #[serde(default)]
     // pub activities: Option<SearchResult<Activity>>,
-    // #[serde(default)]
-    // pub albums: Option<SearchResult<Album>>,
+    #[serde(default)]
+    pub albums: Option<SearchResult<Album>>,
     // #[serde(default)]
     // pub apple_curators: Option<SearchResult<AppleCurator>>,
     // #[serde(default)]
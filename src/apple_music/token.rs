@@ -0,0 +1,331 @@
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, TimeZone, Utc};
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::pkcs8::DecodePrivateKey;
+use select::document::Document;
+use select::predicate::Name;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::client::join_url;
+use super::error::{Error, Result};
+
+/// Environment variable a static developer token is read from, if it is not in the config.
+pub const DEVELOPER_TOKEN_ENV: &str = "APPLE_MUSIC_DEVELOPER_TOKEN";
+
+/// Lifetime of signed MusicKit developer tokens. They are signed again when about to expire, so it can be short.
+pub const MUSICKIT_TOKEN_LIFETIME: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Longest lifetime of a MusicKit developer token allowed by Apple (6 months).
+const MAX_MUSICKIT_TOKEN_LIFETIME: Duration = Duration::from_secs(15777000);
+
+#[derive(Debug, Clone, Deserialize)]
+struct Claims {
+    /// Expiry, in seconds since the Unix epoch
+    #[serde(default)]
+    exp: Option<i64>,
+}
+
+/// A developer token, which is a JWT signed by Apple or by the owner of a MusicKit key.
+#[derive(Debug, Clone)]
+pub struct DeveloperToken {
+    pub token: String,
+    /// When the token expires, if it has an `exp` claim
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl DeveloperToken {
+    /// Reads the expiry of a JWT. The signature is not verified, as only the API can check it.
+    pub fn parse(token: &str) -> Result<Self> {
+        let token = token.trim();
+        let payload = token
+            .split('.')
+            .nth(1)
+            .ok_or(Error::Token(String::from("developer token is not a JWT")))?;
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .map_err(|e| Error::Token(format!("could not decode developer token: {}", e)))?;
+        let claims: Claims = serde_json::from_slice(&payload)
+            .map_err(|e| Error::Token(format!("could not decode developer token claims: {}", e)))?;
+
+        Ok(Self {
+            token: token.to_string(),
+            expires_at: claims.exp.and_then(|exp| Utc.timestamp_opt(exp, 0).single()),
+        })
+    }
+
+    /// Whether the token expires within the given time, or has already expired.
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at.timestamp() - Utc::now().timestamp() <= margin.as_secs() as i64)
+    }
+}
+
+/// A source of developer tokens. Asked for a new token when the one in use is about to expire or was rejected.
+#[async_trait]
+pub trait TokenProvider: fmt::Debug + Send + Sync {
+    async fn token(&self) -> Result<DeveloperToken>;
+}
+
+fn char_windows(src: &str, win_size: usize) -> impl Iterator<Item = (usize, &str)> {
+    src.char_indices().flat_map(move |(from, _)| {
+        src[from..]
+            .char_indices()
+            .nth(win_size - 1)
+            .map(|(to, c)| (from, &src[from..from + to + c.len_utf8()]))
+    })
+}
+
+fn str_byteindex_of_substr(haystack: &str, needle: &str) -> Option<usize> {
+    char_windows(haystack, needle.len()).find_map(|(pos, window)| (window == needle).then_some(pos))
+}
+
+fn str_byteindex_of_predicate<P>(haystack: &str, predicate: P) -> Option<usize>
+where
+    P: Fn(char) -> bool,
+{
+    haystack
+        .char_indices()
+        .find_map(|(pos, c)| predicate(c).then_some(pos))
+}
+
+/// Reads the developer token embedded in the JS of the Apple Music web player, which does not require an Apple
+/// Developer account.
+#[derive(Debug, Clone)]
+pub struct WebPlayerTokenProvider {
+    web_base_url: String,
+    client: reqwest::Client,
+}
+
+impl WebPlayerTokenProvider {
+    pub fn new(web_base_url: &str) -> Self {
+        Self {
+            web_base_url: web_base_url.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenProvider for WebPlayerTokenProvider {
+    async fn token(&self) -> Result<DeveloperToken> {
+        // get HTML
+        let base_url = join_url(&self.web_base_url, "/")?;
+        let html = self.client.get(base_url.clone()).send().await?.text().await?;
+        let js_url = {
+            let doc = Document::from(html.as_str());
+
+            // get JS URL from HTML
+            let js_relative_url = doc
+                .find(Name("script"))
+                .filter_map(|script| {
+                    script
+                        .attr("src")
+                        .and_then(|src| src.starts_with("/assets/index-").then_some(src))
+                })
+                .next()
+                .ok_or(Error::Token(String::from("could not find JS in HTML")))?;
+
+            join_url(base_url.as_str(), js_relative_url)?
+        };
+
+        // get JS
+        let js = self.client.get(js_url).send().await?.text().await?;
+
+        // get position of JWT from JS
+        let start = str_byteindex_of_substr(
+            &js,
+            "eyJhbGciOiJFUzI1NiIsInR5cCI6IkpXVCIsImtpZCI6IldlYlBsYXlLaWQifQ.",
+        )
+        .ok_or(Error::Token(String::from("could not find start of token in JS")))?;
+        let len = str_byteindex_of_predicate(&js[start..], |c| c == '"' || c == '\'')
+            .ok_or(Error::Token(String::from("could not find end of token in JS")))?;
+        let end = start + len;
+
+        // return JWT
+        DeveloperToken::parse(&js[start..end])
+    }
+}
+
+/// A developer token given by the user, which can not be replaced once it expires.
+#[derive(Debug, Clone)]
+pub struct StaticTokenProvider {
+    token: DeveloperToken,
+}
+
+impl StaticTokenProvider {
+    pub fn new(token: &str) -> Result<Self> {
+        Ok(Self {
+            token: DeveloperToken::parse(token)?,
+        })
+    }
+
+    /// Reads the token from the `APPLE_MUSIC_DEVELOPER_TOKEN` environment variable.
+    pub fn from_env() -> Result<Self> {
+        let token = std::env::var(DEVELOPER_TOKEN_ENV)
+            .map_err(|e| Error::Token(format!("could not read {}: {}", DEVELOPER_TOKEN_ENV, e)))?;
+        Self::new(&token)
+    }
+}
+
+#[async_trait]
+impl TokenProvider for StaticTokenProvider {
+    async fn token(&self) -> Result<DeveloperToken> {
+        match self.token.expires_at {
+            Some(expires_at) if self.token.expires_within(Duration::ZERO) => Err(Error::Token(format!(
+                "the configured developer token expired at {}",
+                expires_at
+            ))),
+            _ => Ok(self.token.clone()),
+        }
+    }
+}
+
+/// Signs developer tokens with a MusicKit private key from an Apple Developer account.
+pub struct MusicKitTokenProvider {
+    team_id: String,
+    key_id: String,
+    signing_key: SigningKey,
+    /// How long generated tokens are valid
+    lifetime: Duration,
+}
+
+impl fmt::Debug for MusicKitTokenProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // leave out the private key
+        f.debug_struct("MusicKitTokenProvider")
+            .field("team_id", &self.team_id)
+            .field("key_id", &self.key_id)
+            .field("lifetime", &self.lifetime)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MusicKitTokenProvider {
+    /// Creates a provider from the PEM encoded contents of a `.p8` private key file.
+    pub fn from_pem(team_id: &str, key_id: &str, private_key: &str, lifetime: Duration) -> Result<Self> {
+        let signing_key = SigningKey::from_pkcs8_pem(private_key)
+            .map_err(|e| Error::Token(format!("could not read MusicKit private key: {}", e)))?;
+        Ok(Self {
+            team_id: team_id.to_string(),
+            key_id: key_id.to_string(),
+            signing_key,
+            lifetime: lifetime.min(MAX_MUSICKIT_TOKEN_LIFETIME),
+        })
+    }
+
+    /// Creates a provider from a `.p8` private key file downloaded from the Apple Developer account.
+    pub fn load<P: AsRef<Path>>(team_id: &str, key_id: &str, private_key_path: P, lifetime: Duration) -> Result<Self> {
+        let private_key = std::fs::read_to_string(&private_key_path).map_err(|e| {
+            Error::Token(format!(
+                "could not read MusicKit private key {}: {}",
+                private_key_path.as_ref().display(),
+                e
+            ))
+        })?;
+        Self::from_pem(team_id, key_id, &private_key, lifetime)
+    }
+
+    /// Signs a token issued at the given time, as a JWT with the ES256 algorithm.
+    fn sign(&self, issued_at: DateTime<Utc>) -> DeveloperToken {
+        let expires_at = issued_at + chrono::Duration::seconds(self.lifetime.as_secs() as i64);
+        let header = json!({ "alg": "ES256", "kid": &self.key_id });
+        let claims = json!({ "iss": &self.team_id, "iat": issued_at.timestamp(), "exp": expires_at.timestamp() });
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+
+        DeveloperToken {
+            token: format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature.to_bytes())),
+            expires_at: Some(expires_at),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenProvider for MusicKitTokenProvider {
+    async fn token(&self) -> Result<DeveloperToken> {
+        Ok(self.sign(Utc::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::signature::Verifier;
+    use p256::pkcs8::{EncodePrivateKey, LineEnding};
+
+    use super::*;
+
+    fn jwt(claims: &str) -> String {
+        format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"ES256","typ":"JWT"}"#),
+            URL_SAFE_NO_PAD.encode(claims)
+        )
+    }
+
+    #[test]
+    fn parses_expiry() {
+        let token = DeveloperToken::parse(&jwt(r#"{"iss":"AMPWebPlay","iat":1700000000,"exp":1715000000}"#)).unwrap();
+        assert_eq!(token.expires_at, Utc.timestamp_opt(1715000000, 0).single());
+        assert!(token.expires_within(Duration::ZERO));
+
+        let token = DeveloperToken::parse(&jwt(r#"{"iss":"AMPWebPlay"}"#)).unwrap();
+        assert_eq!(token.expires_at, None);
+        assert!(!token.expires_within(Duration::from_secs(3600)));
+
+        let expires_at = Utc::now() + chrono::Duration::hours(1);
+        let token = DeveloperToken::parse(&jwt(&format!(r#"{{"exp":{}}}"#, expires_at.timestamp()))).unwrap();
+        assert!(!token.expires_within(Duration::from_secs(60)));
+        assert!(token.expires_within(Duration::from_secs(2 * 3600)));
+    }
+
+    #[test]
+    fn rejects_invalid_tokens() {
+        assert!(matches!(DeveloperToken::parse("not a token"), Err(Error::Token(_))));
+        assert!(matches!(DeveloperToken::parse("a.b!c.d"), Err(Error::Token(_))));
+    }
+
+    #[tokio::test]
+    async fn signs_musickit_tokens() {
+        let signing_key = SigningKey::random(&mut rand::rngs::OsRng);
+        let private_key = signing_key.to_pkcs8_pem(LineEnding::LF).unwrap();
+        let provider = MusicKitTokenProvider::from_pem("TEAM123456", "KEY1234567", &private_key, MUSICKIT_TOKEN_LIFETIME).unwrap();
+        assert!(!format!("{:?}", provider).contains("PRIVATE KEY"));
+
+        let issued_at = Utc.timestamp_opt(1700000000, 0).unwrap();
+        let token = provider.sign(issued_at);
+        assert_eq!(token.expires_at, Some(issued_at + chrono::Duration::hours(12)));
+
+        let parts: Vec<&str> = token.token.split('.').collect();
+        let decode = |part: &str| serde_json::from_slice::<serde_json::Value>(&URL_SAFE_NO_PAD.decode(part).unwrap()).unwrap();
+        assert_eq!(decode(parts[0]), json!({ "alg": "ES256", "kid": "KEY1234567" }));
+        assert_eq!(decode(parts[1]), json!({ "iss": "TEAM123456", "iat": 1700000000, "exp": 1700043200 }));
+
+        let signature = Signature::from_slice(&URL_SAFE_NO_PAD.decode(parts[2]).unwrap()).unwrap();
+        let signing_input = format!("{}.{}", parts[0], parts[1]);
+        signing_key
+            .verifying_key()
+            .verify(signing_input.as_bytes(), &signature)
+            .unwrap();
+
+        // a new token is signed whenever one is needed
+        assert!(!provider.token().await.unwrap().expires_within(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn rejects_invalid_private_keys() {
+        let e = MusicKitTokenProvider::from_pem("TEAM123456", "KEY1234567", "not a key", MUSICKIT_TOKEN_LIFETIME).unwrap_err();
+        assert!(matches!(e, Error::Token(_)));
+    }
+}
@@ -1,7 +1,81 @@
 use std::path::PathBuf;
 
+use chrono::NaiveDate;
 use clap::{Parser, Subcommand};
 
+use crate::history_playlist::RankBy;
+use crate::library_import::LibraryItemKind;
+use crate::playlist_files::PlaylistFormat;
+use crate::report::ReportFormat;
+
+/// Options for finding tracks in the Apple Music catalog
+#[derive(Debug, clap::Args)]
+pub struct MatchArgs {
+	/// Minimum score for a match (between 0.0 and 3.0)
+	#[clap(long, default_value = "0.8")]
+	pub min_score: f64,
+
+	/// Limit of possible songs per search result
+	#[clap(long, default_value = "10")]
+	pub limit: usize,
+
+	/// Path to JSON file mapping Spotify track URIs to ISRCs, used to look up tracks by ISRC before searching
+	#[clap(long)]
+	pub isrc_mapping: Option<PathBuf>,
+
+	/// Path to JSON file with metadata (duration, ISRC) of Spotify tracks, keyed by track URI
+	#[clap(long)]
+	pub track_metadata: Option<PathBuf>,
+
+	/// Path to JSON file caching matched songs by Spotify track URI (created if it does not exist)
+	#[clap(long)]
+	pub match_cache: Option<PathBuf>,
+
+	/// If set, will ask which song to use when a match is uncertain or ambiguous
+	#[clap(long)]
+	pub review: bool,
+
+	/// Matches with a score below this are reviewed (with --review)
+	#[clap(long, default_value = "2.5")]
+	pub review_below: f64,
+
+	/// Matches where the second best song scores within this of the best song are reviewed (with --review)
+	#[clap(long, default_value = "0.05")]
+	pub review_margin: f64,
+}
+
+/// Options for exporting podcast episodes found in playlists
+#[derive(Debug, clap::Args)]
+pub struct PodcastArgs {
+	/// Path to write an OPML file of the podcast shows of episodes in the playlists to, for importing into a podcast app
+	#[clap(long)]
+	pub podcast_opml: Option<PathBuf>,
+
+	/// Path to write a CSV list of the podcast episodes in the playlists to
+	#[clap(long)]
+	pub podcast_episodes: Option<PathBuf>,
+
+	/// If set, will look up the RSS feeds of podcast shows in Apple Podcasts (needed for the OPML file to be importable)
+	#[clap(long)]
+	pub search_apple_podcasts: bool,
+}
+
+/// Options for resolving local tracks in playlists
+#[derive(Debug, clap::Args)]
+pub struct LocalArgs {
+	/// Path to local music directory to find local tracks in, by the tags of MP3, FLAC, M4A and OGG files
+	#[clap(long)]
+	pub music_dir: Option<PathBuf>,
+
+	/// Path to directory to write an M3U8 playlist of the local files found for each playlist to (with --music-dir)
+	#[clap(long)]
+	pub local_m3u8_dir: Option<PathBuf>,
+
+	/// If set, will search for local tracks in the Apple Music catalog by the artist, album, title and duration in their URI
+	#[clap(long)]
+	pub match_local_tracks: bool,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Command {
 	/// Import Spotify GDPR data dump (my_spotify_data / MyData) playlists to Apple Music via API.
@@ -18,14 +92,259 @@ pub enum Command {
 		#[clap(long)]
 		dry: bool,
 
+		/// If set, will add missing tracks to playlists which already exist in Apple Music instead of skipping them
+		#[clap(long)]
+		sync: bool,
+
+		/// If set, will list tracks in existing Apple Music playlists which are not in the Spotify playlist (with --sync)
+		#[clap(long, requires = "sync")]
+		show_extras: bool,
+
+		/// Path to file recording import progress, so it can be continued with --resume [default with --resume: import_state.json]
+		#[clap(long)]
+		state_file: Option<PathBuf>,
+
+		/// If set, will continue the import recorded in the state file
+		#[clap(long)]
+		resume: bool,
+
+		/// Path to write a report of matched and skipped tracks to
+		#[clap(long)]
+		report: Option<PathBuf>,
+
+		/// Format of the report (default: guessed from the file extension)
+		#[clap(long, value_enum)]
+		report_format: Option<ReportFormat>,
+
+		#[clap(flatten)]
+		podcasts: PodcastArgs,
+
+		#[clap(flatten)]
+		local: LocalArgs,
+
+		#[clap(flatten)]
+		match_args: MatchArgs,
+	},
+
+	/// Import Spotify GDPR data dump (my_spotify_data / MyData) liked songs and saved albums to the Apple Music library via API.
+	#[clap(aliases = &["spotify-library-to-apple", "slta"])]
+	ImportSpotifyGdprLibraryToAppleMusicApi {
+		/// Path to MyData/YourLibrary.json file from Spotify GDPR export
+		library_file: PathBuf,
+
+		/// Kinds of saved items to include
+		#[clap(long, value_enum, num_args = 0.., default_values = ["tracks", "albums"])]
+		include: Vec<LibraryItemKind>,
+
+		/// If set, will not add songs or albums to the library
+		#[clap(long)]
+		dry: bool,
+
+		#[clap(flatten)]
+		match_args: MatchArgs,
+	},
+
+	/// Create an Apple Music playlist of the top tracks in the streaming history in a Spotify GDPR data dump.
+	///
+	/// If the directory contains extended streaming history, it is used instead, as it includes track URIs, album names and skips.
+	#[clap(aliases = &["history-playlist-to-apple", "hpta"])]
+	CreateStreamingHistoryPlaylistInAppleMusicApi {
+		/// Path to MyData or extended streaming history directory from Spotify GDPR export
+		data_dir: PathBuf,
+
+		/// Name of the playlist (default: "Top <TOP> of <YEAR>" or similar)
+		#[clap(long)]
+		name: Option<String>,
+
+		/// Description of the playlist
+		#[clap(long)]
+		description: Option<String>,
+
+		/// Year to include, in place of --from and --to
+		#[clap(long, conflicts_with_all = ["from", "to"])]
+		year: Option<i32>,
+
+		/// First day to include (YYYY-MM-DD)
+		#[clap(long)]
+		from: Option<NaiveDate>,
+
+		/// Last day to include (YYYY-MM-DD)
+		#[clap(long)]
+		to: Option<NaiveDate>,
+
+		/// How to rank tracks
+		#[clap(long, value_enum, default_value = "plays")]
+		rank_by: RankBy,
+
+		/// Number of tracks in the playlist
+		#[clap(long, default_value = "100")]
+		top: usize,
+
+		/// List of artist names to include (default: all artists)
+		#[clap(long, num_args = 0..)]
+		artists: Option<Vec<String>>,
+
+		/// Minimum number of streams of a track to include it
+		#[clap(long, default_value = "1")]
+		min_plays: usize,
+
+		/// If set, skipped streams are not counted. Without extended streaming history, streams shorter than 30 seconds count as skipped
+		#[clap(long)]
+		exclude_skips: bool,
+
+		/// If set, will not create playlist
+		#[clap(long)]
+		dry: bool,
+
+		#[clap(flatten)]
+		match_args: MatchArgs,
+	},
+
+	/// Import Apple Music library playlists to Spotify via the Spotify Web API.
+	///
+	/// Authorizes with Spotify in a browser, using the client ID of an app registered in the Spotify developer dashboard
+	/// with the redirect URI http://127.0.0.1:<REDIRECT_PORT>/callback.
+	#[clap(aliases = &["apple-playlist-to-spotify", "apts"])]
+	ImportAppleMusicPlaylistsToSpotifyApi {
+		/// List of playlist names to include (default: all library playlists)
+		#[clap(long, num_args = 0..)]
+		playlists: Option<Vec<String>>,
+
+		/// If set, will not create playlists
+		#[clap(long)]
+		dry: bool,
+
+		/// If set, created playlists are public
+		#[clap(long)]
+		public: bool,
+
 		/// Minimum score for a match (between 0.0 and 3.0)
 		#[clap(long, default_value = "0.8")]
 		min_score: f64,
 
-		/// Limit of possible songs per search result
+		/// Limit of possible tracks per search result
 		#[clap(long, default_value = "10")]
 		limit: usize,
-	}
+
+		/// Client ID of a Spotify app (default: spotifyClientId in the config file)
+		#[clap(long)]
+		spotify_client_id: Option<String>,
+
+		/// Spotify access token to use instead of authorizing in a browser
+		#[clap(long)]
+		spotify_access_token: Option<String>,
+
+		/// Port of the local redirect listener used when authorizing
+		#[clap(long, default_value = "8888")]
+		redirect_port: u16,
+	},
+
+	/// Export Spotify GDPR data dump (my_spotify_data / MyData) playlists to M3U8, XSPF or JSPF files, one file per playlist.
+	Export {
+		/// Path to MyData/Playlistsn.json file from Spotify GDPR export
+		playlist_file: PathBuf,
+
+		/// List of playlist names to include
+		#[clap(long, num_args = 0..)]
+		playlists: Option<Vec<String>>,
+
+		/// Playlist file formats to write
+		#[clap(long, value_enum, num_args = 1.., default_values = ["m3u8"])]
+		format: Vec<PlaylistFormat>,
+
+		/// Directory to write playlist files to
+		#[clap(long, short = 'o', default_value = ".")]
+		output_dir: PathBuf,
+	},
+
+	/// Back up Apple Music library playlists and their tracks to a JSON snapshot.
+	///
+	/// Catalog songs are looked up in the configured storefront to include their ISRCs, which identify them in other storefronts and services.
+	BackupAppleMusic {
+		/// Path to write the JSON snapshot to
+		snapshot_file: PathBuf,
+
+		/// List of playlist names to include (default: all library playlists)
+		#[clap(long, num_args = 0..)]
+		playlists: Option<Vec<String>>,
+
+		/// If set, will not look up ISRCs of catalog songs
+		#[clap(long)]
+		skip_isrcs: bool,
+
+		/// Path to directory to write a playlist file of each playlist to, linking to catalog songs
+		#[clap(long)]
+		playlist_dir: Option<PathBuf>,
+
+		/// Playlist file formats to write (with --playlist-dir)
+		#[clap(long, value_enum, num_args = 1.., default_values = ["m3u8"])]
+		playlist_format: Vec<PlaylistFormat>,
+
+		/// Path to write a CSV list of the tracks in all playlists to
+		#[clap(long)]
+		csv: Option<PathBuf>,
+	},
+
+	/// Restore Apple Music library playlists from a JSON snapshot written with backup-apple-music.
+	///
+	/// Songs which are not available in the configured storefront, such as when moving to an account in another region,
+	/// are looked up by ISRC, falling back to searching the catalog.
+	Restore {
+		/// Path to JSON snapshot file
+		snapshot_file: PathBuf,
+
+		/// List of playlist names to include (default: all playlists in the snapshot)
+		#[clap(long, num_args = 0..)]
+		playlists: Option<Vec<String>>,
+
+		/// If set, will not create playlists
+		#[clap(long)]
+		dry: bool,
+
+		#[clap(flatten)]
+		match_args: MatchArgs,
+	},
+
+	/// Create Apple Music playlists from a JSON report written with spta --report, without searching the catalog.
+	///
+	/// Song IDs in the report can be edited by hand before applying it. Tracks without a song ID are left out.
+	ApplyMapping {
+		/// Path to JSON report file
+		mapping_file: PathBuf,
+
+		/// List of playlist names to include (default: all playlists in the report)
+		#[clap(long, num_args = 0..)]
+		playlists: Option<Vec<String>>,
+
+		/// If set, will not create playlists
+		#[clap(long)]
+		dry: bool,
+	},
+
+	/// Print listening statistics from the streaming history in a Spotify GDPR data dump.
+	///
+	/// If the directory contains extended streaming history, it is used instead, adding skip and completion rates and
+	/// platform breakdown.
+	Stats {
+		/// Path to MyData or extended streaming history directory from Spotify GDPR export
+		data_dir: PathBuf,
+
+		/// First day to include (YYYY-MM-DD)
+		#[clap(long)]
+		from: Option<NaiveDate>,
+
+		/// Last day to include (YYYY-MM-DD)
+		#[clap(long)]
+		to: Option<NaiveDate>,
+
+		/// Number of top artists and tracks to list
+		#[clap(long, default_value = "10")]
+		top: usize,
+
+		/// Minimum number of streams of a track to list its skip and completion rates (extended streaming history only)
+		#[clap(long, default_value = "5")]
+		min_streams: usize,
+	},
 }
 
 #[derive(Debug, Parser)]
@@ -47,4 +366,15 @@ impl Args {
 	pub fn parse() -> Self {
 		Parser::parse()
 	}
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn show_extras_requires_sync() {
+		let e = Args::try_parse_from(["music-data-tools", "spta", "Playlist1.json", "--show-extras"]).unwrap_err();
+		assert_eq!(e.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+		assert!(Args::try_parse_from(["music-data-tools", "spta", "Playlist1.json", "--sync", "--show-extras"]).is_ok());
+	}
+}
@@ -0,0 +1,74 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as ErrorContext, Result};
+use serde::Serialize;
+
+/// Path of the temporary file written next to `path` before it is renamed over it.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(".tmp");
+    path.with_file_name(file_name)
+}
+
+/// Writes a file by writing a temporary file next to it and renaming it over the file.
+///
+/// The file keeps its previous contents if writing is interrupted, for example by a crash or Ctrl-C.
+pub fn write_with<P, F>(path: P, write: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+    let path = path.as_ref();
+    let temp_path = temp_path(path);
+
+    let result = File::create(&temp_path)
+        .with_context(|| format!("could not create {}", temp_path.display()))
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            write(&mut writer)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+            Ok(())
+        })
+        .and_then(|()| {
+            std::fs::rename(&temp_path, path).with_context(|| format!("could not replace {}", path.display()))
+        });
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Writes a value as pretty-printed JSON, see [`write_with`].
+pub fn write_json<P: AsRef<Path>, T: Serialize + ?Sized>(path: P, value: &T) -> Result<()> {
+    write_with(path, |writer| Ok(serde_json::to_writer_pretty(writer, value)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn replaces_file_and_keeps_it_on_failure() {
+        let dir = TestDir::new("atomic");
+        let path = dir.join("data.json");
+
+        write_json(&path, &[1, 2, 3]).unwrap();
+        write_json(&path, &[4]).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<u32>>(&std::fs::read_to_string(&path).unwrap()).unwrap(), [4]);
+
+        let e = write_with(&path, |writer| {
+            writer.write_all(b"[5, ")?;
+            anyhow::bail!("interrupted")
+        })
+        .unwrap_err();
+        assert_eq!(e.to_string(), "interrupted");
+        assert_eq!(serde_json::from_str::<Vec<u32>>(&std::fs::read_to_string(&path).unwrap()).unwrap(), [4]);
+        assert!(!temp_path(&path).exists());
+    }
+}
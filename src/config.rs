@@ -1,9 +1,34 @@
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::{Context as ErrorContext, Result};
+use anyhow::{bail, Context as ErrorContext, Result};
 use serde::{Deserialize, Serialize};
 
+use crate::apple_music::{self, MusicKitTokenProvider, StaticTokenProvider, TokenProvider};
+
+/// Where developer tokens for the Apple Music API come from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum AppleMusicTokenProvider {
+    /// Read the token of the music.apple.com web player
+    #[default]
+    WebPlayer,
+    /// A developer token from an Apple Developer account, or the APPLE_MUSIC_DEVELOPER_TOKEN environment variable if
+    /// not set
+    Static {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    /// Sign developer tokens with a MusicKit private key (`.p8` file) from an Apple Developer account
+    #[serde(rename_all = "camelCase")]
+    MusicKit {
+        team_id: String,
+        key_id: String,
+        private_key_path: PathBuf,
+    },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
@@ -11,6 +36,20 @@ pub struct Config {
     pub apple_music_user_token: String,
     /// An iTunes Store territory, specified by an ISO 3166 alpha-2 country code. The possible values are the id attributes of Storefront objects.
     pub apple_music_storefront: String,
+    #[serde(default)]
+    pub apple_music_token_provider: AppleMusicTokenProvider,
+    /// Deprecated, replaced by a `static` token provider when loading the config
+    #[serde(default, skip_serializing)]
+    pub apple_music_developer_token: Option<String>,
+    /// Client ID of an app registered in the Spotify developer dashboard, used to authorize with the Spotify Web API
+    #[serde(default)]
+    pub spotify_client_id: String,
+    /// Base URL of the Apple Music API, if not https://api.music.apple.com
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apple_music_api_base_url: Option<String>,
+    /// Base URL of the Apple Music web player, if not https://music.apple.com
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apple_music_web_base_url: Option<String>,
 }
 
 impl Default for Config {
@@ -18,6 +57,11 @@ impl Default for Config {
         Self {
             apple_music_user_token: String::new(),
             apple_music_storefront: String::from("no"),
+            apple_music_token_provider: AppleMusicTokenProvider::default(),
+            apple_music_developer_token: None,
+            spotify_client_id: String::new(),
+            apple_music_api_base_url: None,
+            apple_music_web_base_url: None,
         }
     }
 }
@@ -25,7 +69,18 @@ impl Default for Config {
 impl Config {
     pub fn load<P: AsRef<Path>>(config_path: P) -> Result<Self> {
         let config_file = File::open(config_path).context("could not open config file")?;
-        Ok(serde_json::from_reader(config_file)?)
+        let mut config: Self = serde_json::from_reader(config_file)?;
+
+        if let Some(token) = config.apple_music_developer_token.take().filter(|token| !token.is_empty()) {
+            if !matches!(config.apple_music_token_provider, AppleMusicTokenProvider::WebPlayer) {
+                bail!("appleMusicDeveloperToken and appleMusicTokenProvider are both set in the config file, remove appleMusicDeveloperToken");
+            }
+            log::warn!(
+                "appleMusicDeveloperToken in the config file is deprecated, use \"appleMusicTokenProvider\": {{\"type\": \"static\", \"token\": \"...\"}} instead"
+            );
+            config.apple_music_token_provider = AppleMusicTokenProvider::Static { token: Some(token) };
+        }
+        Ok(config)
     }
 
     pub fn load_or_init<P: AsRef<Path>>(config_path: P) -> Result<Self> {
@@ -45,8 +100,71 @@ impl Config {
         }
     }
 
+    /// Creates an Apple Music API client for the configured tokens and base URLs.
+    pub fn apple_music_client(&self) -> Result<apple_music::Client> {
+        let token_provider: Option<Arc<dyn TokenProvider>> = match &self.apple_music_token_provider {
+            AppleMusicTokenProvider::WebPlayer => None,
+            AppleMusicTokenProvider::Static { token: Some(token) } => Some(Arc::new(StaticTokenProvider::new(token)?)),
+            AppleMusicTokenProvider::Static { token: None } => Some(Arc::new(StaticTokenProvider::from_env()?)),
+            AppleMusicTokenProvider::MusicKit {
+                team_id,
+                key_id,
+                private_key_path,
+            } => Some(Arc::new(MusicKitTokenProvider::load(
+                team_id,
+                key_id,
+                private_key_path,
+                apple_music::MUSICKIT_TOKEN_LIFETIME,
+            )?)),
+        };
+
+        let defaults = apple_music::ClientConfig::default();
+        Ok(apple_music::Client::new_with_config(apple_music::ClientConfig {
+            user_token: self.apple_music_user_token.clone(),
+            token_provider,
+            api_base_url: self.apple_music_api_base_url.clone().unwrap_or(defaults.api_base_url),
+            web_base_url: self.apple_music_web_base_url.clone().unwrap_or(defaults.web_base_url),
+            ..defaults
+        }))
+    }
+
     pub fn save<P: AsRef<Path>>(&self, config_path: P) -> Result<()> {
         let config_file = File::create(&config_path).context("could not create config file")?;
         Ok(serde_json::to_writer_pretty(config_file, self)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn load(json: &str) -> Result<Config> {
+        let dir = TestDir::new("config");
+        let path = dir.join("config.json");
+        std::fs::write(&path, json).unwrap();
+        Config::load(&path)
+    }
+
+    #[test]
+    fn maps_deprecated_developer_token_to_static_provider() {
+        let config = load(r#"{"appleMusicUserToken": "user", "appleMusicStorefront": "no", "appleMusicDeveloperToken": "dev"}"#).unwrap();
+        assert!(matches!(
+            config.apple_music_token_provider,
+            AppleMusicTokenProvider::Static { token: Some(ref token) } if token == "dev"
+        ));
+        assert_eq!(config.apple_music_developer_token, None);
+        assert!(!serde_json::to_string(&config).unwrap().contains("appleMusicDeveloperToken"));
+
+        // an empty token meant reading the token of the web player
+        let config = load(r#"{"appleMusicUserToken": "user", "appleMusicStorefront": "no", "appleMusicDeveloperToken": ""}"#).unwrap();
+        assert!(matches!(config.apple_music_token_provider, AppleMusicTokenProvider::WebPlayer));
+
+        let e = load(
+            r#"{"appleMusicUserToken": "user", "appleMusicStorefront": "no", "appleMusicDeveloperToken": "dev",
+                "appleMusicTokenProvider": {"type": "static", "token": "other"}}"#,
+        )
+        .unwrap_err();
+        assert!(e.to_string().contains("appleMusicDeveloperToken"), "{}", e);
+    }
+}
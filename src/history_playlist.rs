@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::NaiveDate;
+
+use crate::apple_music::response::PaginatedResponse;
+use crate::config::Config;
+use crate::import_state::{ImportState, MatchedTrack};
+use crate::matching::TrackCandidate;
+use crate::my_spotify_data::extended_streaming_history::ExtendedStreamingHistory;
+use crate::my_spotify_data::streaming_history::StreamingHistory;
+use crate::stats::{self, format_play_time, Play};
+use crate::track_finder::{TrackFinder, TrackFinderOptions};
+use crate::{add_matched_tracks, is_apple_music_auth_error, plural};
+
+/// How tracks in the streaming history are ranked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RankBy {
+    /// Number of streams
+    Plays,
+    /// Total time played
+    PlayTime,
+}
+
+pub struct HistoryPlaylistOptions {
+    /// Name of the playlist to create
+    pub name: String,
+    pub description: Option<String>,
+    /// First day to include, in local time
+    pub from: Option<NaiveDate>,
+    /// Last day to include, in local time
+    pub to: Option<NaiveDate>,
+    pub rank_by: RankBy,
+    /// Number of tracks in the playlist
+    pub top: usize,
+    /// If not empty, only tracks by these artists are included
+    pub artists: Vec<String>,
+    /// Minimum number of streams of a track to include it
+    pub min_plays: usize,
+    /// If set, skipped streams are not counted
+    pub exclude_skips: bool,
+    /// If set, will not create playlist
+    pub dry: bool,
+    pub finder: TrackFinderOptions,
+}
+
+/// A track from the streaming history, with its play count and time.
+#[derive(Debug, Clone)]
+pub struct RankedTrack<'a> {
+    pub artist_name: &'a str,
+    pub album_name: Option<&'a str>,
+    pub track_name: &'a str,
+    pub track_uri: Option<&'a str>,
+    pub plays: usize,
+    pub ms_played: u64,
+}
+
+/// Ranks tracks by play count or play time, best first.
+pub fn rank_tracks<'a, I>(plays: I, options: &HistoryPlaylistOptions) -> Vec<RankedTrack<'a>>
+where
+    I: IntoIterator<Item = Play<'a>>,
+{
+    let mut tracks: HashMap<(&str, &str), RankedTrack> = HashMap::new();
+    for play in plays {
+        if !stats::in_date_range(play.ended_at, options.from, options.to) || (options.exclude_skips && play.skipped) {
+            continue;
+        }
+        if !options.artists.is_empty() && !options.artists.iter().any(|a| a.eq_ignore_ascii_case(play.artist_name)) {
+            continue;
+        }
+
+        let track = tracks
+            .entry((play.artist_name, play.track_name))
+            .or_insert_with(|| RankedTrack {
+                artist_name: play.artist_name,
+                album_name: None,
+                track_name: play.track_name,
+                track_uri: None,
+                plays: 0,
+                ms_played: 0,
+            });
+        track.album_name = track.album_name.or(play.album_name);
+        track.track_uri = track.track_uri.or(play.track_uri);
+        track.plays += 1;
+        track.ms_played += play.ms_played;
+    }
+
+    let mut ranked: Vec<RankedTrack> = tracks.into_values().filter(|t| t.plays >= options.min_plays).collect();
+    ranked.sort_by(|a, b| {
+        let (a_rank, b_rank) = match options.rank_by {
+            RankBy::Plays => ((a.plays as u64, a.ms_played), (b.plays as u64, b.ms_played)),
+            RankBy::PlayTime => ((a.ms_played, a.plays as u64), (b.ms_played, b.plays as u64)),
+        };
+        b_rank
+            .cmp(&a_rank)
+            .then(a.artist_name.cmp(b.artist_name))
+            .then(a.track_name.cmp(b.track_name))
+    });
+    ranked.truncate(options.top);
+    ranked
+}
+
+/// Creates an Apple Music playlist of the top tracks in the streaming history from a Spotify GDPR data dump.
+pub async fn create_history_playlist<P: AsRef<Path>>(
+    config: &Config,
+    data_dir: P,
+    options: &HistoryPlaylistOptions,
+) -> Result<()> {
+    let term = console::Term::stdout();
+    let music_client = config.apple_music_client()?;
+    let track_finder = TrackFinder::new(&music_client, &config.apple_music_storefront, &term, &options.finder);
+
+    if !options.dry {
+        let music_playlists = music_client
+            .get_library_playlists()
+            .await?
+            .all(&music_client)
+            .await?;
+        if music_playlists.iter().any(|p| p.attributes.name == options.name) {
+            term.write_line(&format!(
+                "{} Playlist {} already exists in Apple Music",
+                console::style("✔").green(),
+                &options.name
+            ))?;
+            return Ok(());
+        }
+    }
+
+    // the extended streaming history includes track URIs, album names and skips, so it is preferred
+    let extended_history = ExtendedStreamingHistory::load(&data_dir)?;
+    let history;
+    let ranked_tracks = if !extended_history.streams.is_empty() {
+        rank_tracks(
+            extended_history.streams.iter().filter_map(Play::from_extended_track_stream),
+            options,
+        )
+    } else {
+        history = StreamingHistory::load(&data_dir)?;
+        rank_tracks(history.music.iter().map(Play::from_music_stream), options)
+    };
+
+    term.write_line(&format!(
+        "{} Ranked {} track{} from Spotify streaming history",
+        console::style("✔").green(),
+        ranked_tracks.len(),
+        plural(ranked_tracks.len())
+    ))?;
+
+    let mut matched = Vec::new();
+    for (i, track) in ranked_tracks.iter().enumerate() {
+        term.write_line(&format!(
+            "{:>4}. \"{} - {}\" ({} stream{}, {})",
+            i + 1,
+            track.artist_name,
+            track.track_name,
+            track.plays,
+            plural(track.plays),
+            format_play_time(track.ms_played)
+        ))?;
+
+        let query = TrackCandidate {
+            artist_name: track.artist_name,
+            album_name: track.album_name.unwrap_or_default(),
+            track_name: track.track_name,
+            duration_ms: None,
+        };
+        if let Some(found) = track_finder.find(track.track_uri, query).await?.found() {
+            matched.push(MatchedTrack {
+                track_uri: track.track_uri.unwrap_or_default().to_string(),
+                song_id: found.id.clone(),
+            });
+        }
+    }
+
+    if let Some(match_cache) = &options.finder.match_cache {
+        match_cache.save()?;
+    }
+
+    if options.dry {
+        term.write_line(&format!(
+            "{} Skipped creating playlist {:?} with {} track{} in Apple Music (--dry)",
+            console::style("✱").blue(),
+            &options.name,
+            matched.len(),
+            plural(matched.len())
+        ))?;
+        return Ok(());
+    }
+
+    let track_count = matched.len();
+    let mut state = ImportState::default();
+    state.playlist_mut(&options.name).matched = matched;
+    match add_matched_tracks(&music_client, &options.name, options.description.as_deref(), &mut state, &options.name, None).await {
+        Ok(playlist_id) => term.write_line(&format!(
+            "{} Created playlist {:?} with {} track{} in Apple Music: https://music.apple.com/{}/library/playlist/{}",
+            console::style("✔").green(),
+            &options.name,
+            track_count,
+            plural(track_count),
+            &config.apple_music_storefront,
+            &playlist_id
+        ))?,
+        Err(e) if is_apple_music_auth_error(&e) => return Err(e),
+        Err(e) => term.write_line(&format!(
+            "{} Failed to create playlist {:?} in Apple Music: {}",
+            console::style("✘").red(),
+            &options.name,
+            e
+        ))?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Local, TimeZone, Utc};
+
+    use super::*;
+    use crate::mock_server::{self, MockServer};
+    use crate::test_dir::TestDir;
+
+    fn play<'a>(day: u32, artist_name: &'a str, track_name: &'a str, ms_played: u64) -> Play<'a> {
+        Play {
+            ended_at: noon(day),
+            artist_name,
+            album_name: None,
+            track_name,
+            track_uri: None,
+            ms_played,
+            skipped: false,
+        }
+    }
+
+    fn noon(day: u32) -> DateTime<Utc> {
+        Local.with_ymd_and_hms(2023, 6, day, 12, 0, 0).unwrap().with_timezone(&Utc)
+    }
+
+    fn options(rank_by: RankBy) -> HistoryPlaylistOptions {
+        HistoryPlaylistOptions {
+            name: String::from("Top tracks"),
+            description: None,
+            from: None,
+            to: None,
+            rank_by,
+            top: 10,
+            artists: Vec::new(),
+            min_plays: 1,
+            exclude_skips: false,
+            dry: true,
+            finder: TrackFinderOptions {
+                min_score: 0.8,
+                limit: 10,
+                isrc_resolvers: Vec::new(),
+                track_metadata: None,
+                review: None,
+                match_cache: None,
+            },
+        }
+    }
+
+    fn plays() -> Vec<Play<'static>> {
+        vec![
+            play(1, "a-ha", "Take On Me", 200_000),
+            Play {
+                album_name: Some("Hunting High and Low"),
+                track_uri: Some("spotify:track:2WfaOiMkCvy7F5fcp2zZ8L"),
+                ..play(2, "a-ha", "Take On Me", 200_000)
+            },
+            Play {
+                skipped: true,
+                ..play(3, "a-ha", "Take On Me", 10_000)
+            },
+            play(2, "Rick Astley", "Never Gonna Give You Up", 900_000),
+            play(20, "Rick Astley", "Together Forever", 100_000),
+        ]
+    }
+
+    fn names<'a>(ranked: &[RankedTrack<'a>]) -> Vec<(&'a str, usize)> {
+        ranked.iter().map(|t| (t.track_name, t.plays)).collect()
+    }
+
+    #[test]
+    fn ranks_by_plays_or_play_time() {
+        let ranked = rank_tracks(plays(), &options(RankBy::Plays));
+        assert_eq!(
+            names(&ranked),
+            [("Take On Me", 3), ("Never Gonna Give You Up", 1), ("Together Forever", 1)]
+        );
+        // album and URI are taken from any play which has them
+        assert_eq!(ranked[0].album_name, Some("Hunting High and Low"));
+        assert_eq!(ranked[0].track_uri, Some("spotify:track:2WfaOiMkCvy7F5fcp2zZ8L"));
+        assert_eq!(ranked[0].ms_played, 410_000);
+
+        let ranked = rank_tracks(plays(), &options(RankBy::PlayTime));
+        assert_eq!(names(&ranked)[0], ("Never Gonna Give You Up", 1));
+        let ranked = rank_tracks(plays(), &HistoryPlaylistOptions { top: 1, ..options(RankBy::PlayTime) });
+        assert_eq!(ranked.len(), 1);
+    }
+
+    #[test]
+    fn filters_plays() {
+        let ranked = rank_tracks(plays(), &HistoryPlaylistOptions { exclude_skips: true, min_plays: 2, ..options(RankBy::Plays) });
+        assert_eq!(names(&ranked), [("Take On Me", 2)]);
+
+        let ranked = rank_tracks(plays(), &HistoryPlaylistOptions { artists: vec![String::from("rick astley")], ..options(RankBy::Plays) });
+        assert_eq!(names(&ranked), [("Never Gonna Give You Up", 1), ("Together Forever", 1)]);
+
+        let day = |d| NaiveDate::from_ymd_opt(2023, 6, d);
+        let ranked = rank_tracks(plays(), &HistoryPlaylistOptions { from: day(2), to: day(3), ..options(RankBy::Plays) });
+        assert_eq!(names(&ranked), [("Take On Me", 2), ("Never Gonna Give You Up", 1)]);
+    }
+
+    #[tokio::test]
+    async fn stops_when_creating_playlist_is_not_authorized() {
+        let songs = vec![mock_server::apple_music_song("2", "a-ha", "Hunting High and Low", "Take On Me", "GBAYE8500024")];
+        let apple_music = mock_server::apple_music(songs, Vec::new());
+        let server = MockServer::start(move |request| match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/v1/me/library/playlists") => {
+                mock_server::apple_music_error(403, "Forbidden", "Invalid authentication")
+            }
+            _ => apple_music(request),
+        })
+        .await;
+
+        let dir = TestDir::new("history-playlist");
+        std::fs::write(
+            dir.join("StreamingHistory_music_0.json"),
+            r#"[{ "endTime": "2023-06-01 12:00", "artistName": "a-ha", "trackName": "Take On Me", "msPlayed": 225000 }]"#,
+        )
+        .unwrap();
+        let config = Config {
+            apple_music_user_token: String::from("user-token"),
+            apple_music_api_base_url: Some(server.url()),
+            apple_music_web_base_url: Some(server.url()),
+            ..Default::default()
+        };
+        let options = HistoryPlaylistOptions {
+            dry: false,
+            ..options(RankBy::Plays)
+        };
+
+        let e = create_history_playlist(&config, &dir, &options).await.unwrap_err();
+        assert!(is_apple_music_auth_error(&e), "{:#}", e);
+    }
+}
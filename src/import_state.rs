@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Context as ErrorContext, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::apple_music::request::Objects;
+use crate::apple_music::{ObjectType, ToRequestObject};
+use crate::atomic_file;
+
+/// A Spotify track matched to an Apple Music catalog song.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchedTrack {
+    pub track_uri: String,
+    pub song_id: String,
+}
+
+impl ToRequestObject for MatchedTrack {
+    fn id(&self) -> &str {
+        &self.song_id
+    }
+    fn object_type(&self) -> &ObjectType {
+        &ObjectType::Songs
+    }
+}
+
+/// Import progress of a single playlist.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistState {
+    /// Number of playlist items processed so far
+    pub processed: usize,
+    /// Matched tracks, in playlist order
+    pub matched: Vec<MatchedTrack>,
+    /// URIs of items which could not be matched
+    pub unmatched: Vec<String>,
+    /// ID of the Apple Music library playlist, once created
+    #[serde(default)]
+    pub apple_music_playlist_id: Option<String>,
+    /// Number of matched tracks added to the Apple Music library playlist
+    pub added: usize,
+    pub completed: bool,
+}
+
+impl PlaylistState {
+    /// Up to `size` matched tracks not yet added to the Apple Music library playlist.
+    pub fn next_batch(&self, size: usize) -> Objects {
+        Objects {
+            data: self
+                .matched
+                .iter()
+                .skip(self.added)
+                .take(size)
+                .map(|t| t.to_request_object())
+                .collect(),
+        }
+    }
+}
+
+/// Import progress by [`playlist_key`], saved after every track so an import can be resumed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportState {
+    pub playlists: BTreeMap<String, PlaylistState>,
+}
+
+/// Key of a Spotify playlist in the import state, by its position in the data export and its name, as several
+/// playlists can have the same name.
+pub fn playlist_key(index: usize, name: &str) -> String {
+    format!("{}:{}", index, name)
+}
+
+impl ImportState {
+    pub fn playlist_mut(&mut self, key: &str) -> &mut PlaylistState {
+        self.playlists.entry(key.to_string()).or_default()
+    }
+
+    pub fn load<P: AsRef<Path>>(state_path: P) -> Result<Self> {
+        let state_file = File::open(state_path).context("could not open import state file")?;
+        Ok(serde_json::from_reader(state_file)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, state_path: P) -> Result<()> {
+        atomic_file::write_json(state_path, self).context("could not save import state file")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn matched(song_id: &str) -> MatchedTrack {
+        MatchedTrack {
+            track_uri: format!("spotify:track:{}", song_id),
+            song_id: song_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_state() {
+        let dir = TestDir::new("import-state");
+        let state_path = dir.join("import_state.json");
+
+        let mut state = ImportState::default();
+        let playlist_state = state.playlist_mut(&playlist_key(0, "Road trip"));
+        playlist_state.processed = 3;
+        playlist_state.matched = vec![matched("1"), matched("2")];
+        playlist_state.unmatched = vec![String::from("spotify:episode:abc")];
+        playlist_state.apple_music_playlist_id = Some(String::from("p.created"));
+        playlist_state.added = 1;
+        // playlists with the same name are kept apart
+        state.playlist_mut(&playlist_key(1, "Road trip")).completed = true;
+        state.save(&state_path).unwrap();
+
+        let mut state = ImportState::load(&state_path).unwrap();
+        assert_eq!(state.playlists.len(), 2);
+        assert!(state.playlist_mut(&playlist_key(1, "Road trip")).completed);
+
+        let playlist_state = state.playlist_mut(&playlist_key(0, "Road trip"));
+        assert!(!playlist_state.completed);
+        assert_eq!(playlist_state.processed, 3);
+        assert_eq!(playlist_state.unmatched, ["spotify:episode:abc"]);
+        assert_eq!(playlist_state.apple_music_playlist_id.as_deref(), Some("p.created"));
+
+        // only tracks not added yet are added when resuming
+        let batch = playlist_state.next_batch(100);
+        assert_eq!(batch.data.len(), 1);
+        assert_eq!(batch.data[0].id, "2");
+    }
+
+    #[test]
+    fn fails_to_load_missing_state() {
+        let dir = TestDir::new("import-state-missing");
+        let e = ImportState::load(dir.join("import_state.json")).unwrap_err();
+        assert_eq!(e.to_string(), "could not open import state file");
+    }
+}
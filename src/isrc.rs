@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context as ErrorContext, Result};
+use async_trait::async_trait;
+
+/// Normalizes an ISRC to the form used by the Apple Music API (uppercase, no hyphens or spaces).
+pub fn normalize(isrc: &str) -> String {
+    isrc.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Resolves the ISRC (International Standard Recording Code) of a Spotify track.
+#[async_trait]
+pub trait IsrcResolver: Send + Sync {
+    /// Returns the ISRC of the track with the given Spotify URI, or `None` if it is unknown.
+    async fn resolve(&self, track_uri: &str) -> Result<Option<String>>;
+}
+
+#[async_trait]
+impl<T: IsrcResolver + ?Sized> IsrcResolver for Arc<T> {
+    async fn resolve(&self, track_uri: &str) -> Result<Option<String>> {
+        self.as_ref().resolve(track_uri).await
+    }
+}
+
+/// Resolves ISRCs from a local JSON file mapping Spotify track URIs to ISRCs.
+///
+/// ```json
+/// {
+///     "spotify:track:4uLU6hMCjMI75M1A2tKUQC": "GBARL9300135"
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MappingFileResolver {
+    mapping: HashMap<String, String>,
+}
+
+impl MappingFileResolver {
+    pub fn load<P: AsRef<Path>>(mapping_path: P) -> Result<Self> {
+        let mapping_file = File::open(mapping_path).context("could not open ISRC mapping file")?;
+        let mapping: HashMap<String, String> = serde_json::from_reader(mapping_file)
+            .context("could not parse ISRC mapping file")?;
+
+        Ok(Self {
+            mapping: mapping
+                .into_iter()
+                .map(|(track_uri, isrc)| (track_uri, normalize(&isrc)))
+                .filter(|(_, isrc)| !isrc.is_empty())
+                .collect(),
+        })
+    }
+}
+
+#[async_trait]
+impl IsrcResolver for MappingFileResolver {
+    async fn resolve(&self, track_uri: &str) -> Result<Option<String>> {
+        Ok(self.mapping.get(track_uri).cloned())
+    }
+}
+
+/// Asks each resolver in order, returning the first ISRC found.
+pub async fn resolve(resolvers: &[Box<dyn IsrcResolver>], track_uri: &str) -> Result<Option<String>> {
+    for resolver in resolvers {
+        if let Some(isrc) = resolver.resolve(track_uri).await? {
+            return Ok(Some(isrc));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn normalizes_isrcs() {
+        assert_eq!(normalize("GBARL9300135"), "GBARL9300135");
+        assert_eq!(normalize("gb-arl-93-00135"), "GBARL9300135");
+        assert_eq!(normalize(" GB ARL 93 00135 "), "GBARL9300135");
+        assert_eq!(normalize("-"), "");
+    }
+
+    #[tokio::test]
+    async fn resolves_isrcs_from_mapping_file() {
+        let dir = TestDir::new("isrc");
+        let mapping_path = dir.join("isrc.json");
+        std::fs::write(
+            &mapping_path,
+            r#"{
+                "spotify:track:4uLU6hMCjMI75M1A2tKUQC": "gb-arl-93-00135",
+                "spotify:track:2WfaOiMkCvy7F5fcp2zZ8L": ""
+            }"#,
+        )
+        .unwrap();
+        let resolver = MappingFileResolver::load(&mapping_path).unwrap();
+
+        assert_eq!(
+            resolver.resolve("spotify:track:4uLU6hMCjMI75M1A2tKUQC").await.unwrap().as_deref(),
+            Some("GBARL9300135")
+        );
+        // empty ISRCs are ignored, so that other resolvers are asked
+        assert_eq!(resolver.resolve("spotify:track:2WfaOiMkCvy7F5fcp2zZ8L").await.unwrap(), None);
+        assert_eq!(resolver.resolve("spotify:track:0000000000000000000000").await.unwrap(), None);
+
+        let resolvers: Vec<Box<dyn IsrcResolver>> = vec![Box::new(MappingFileResolver::default()), Box::new(resolver)];
+        assert_eq!(
+            resolve(&resolvers, "spotify:track:4uLU6hMCjMI75M1A2tKUQC").await.unwrap().as_deref(),
+            Some("GBARL9300135")
+        );
+    }
+
+    #[test]
+    fn fails_to_load_invalid_mapping_file() {
+        let dir = TestDir::new("isrc-invalid");
+        let mapping_path = dir.join("isrc.json");
+        std::fs::write(&mapping_path, "[]").unwrap();
+        let e = MappingFileResolver::load(&mapping_path).unwrap_err();
+        assert_eq!(e.to_string(), "could not parse ISRC mapping file");
+    }
+}
@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as ErrorContext, Result};
+use chrono::{DateTime, Utc};
+use console::Term;
+use serde::{Deserialize, Serialize};
+
+use crate::apple_music::request::CatalogSongsQuery;
+use crate::apple_music::response::{Artwork, LibraryPlaylist, LibrarySong, PaginatedResponse, Song};
+use crate::config::Config;
+use crate::matching::{ToTrackCandidate, TrackCandidate};
+use crate::playlist_files::{FileNames, PlaylistFile, PlaylistFileEntry, PlaylistFormat};
+use crate::{apple_music, atomic_file, plural};
+
+/// Version of the snapshot format, increased when it changes in a way older versions can not read.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Maximum number of songs which can be fetched from the catalog by ID at once.
+const CATALOG_SONGS_BATCH_SIZE: usize = 300;
+
+/// Size of artwork linked in snapshots, used when the artwork does not specify its own.
+const ARTWORK_SIZE: u64 = 1200;
+
+/// A track in a backed up playlist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotTrack {
+    /// ID of the song in the library it was backed up from
+    pub library_id: String,
+    /// ID of the catalog song, in the storefront of the snapshot. Songs uploaded to the library have none.
+    pub catalog_id: Option<String>,
+    pub isrc: Option<String>,
+    pub name: String,
+    pub artist_name: String,
+    pub album_name: Option<String>,
+    pub duration_ms: u64,
+    pub artwork_url: Option<String>,
+}
+
+impl SnapshotTrack {
+    fn new(song: &LibrarySong) -> Self {
+        Self {
+            library_id: song.id.clone(),
+            catalog_id: song.catalog_id().map(|id| id.to_string()),
+            isrc: None,
+            name: song.attributes.name.clone(),
+            artist_name: song.attributes.artist_name.clone(),
+            album_name: song.attributes.album_name.clone(),
+            duration_ms: song.attributes.duration_in_millis,
+            artwork_url: Some(artwork_url(&song.attributes.artwork)),
+        }
+    }
+
+    /// Identifies the catalog song in place of a Spotify track URI, when looking it up by ISRC and caching matches.
+    pub fn catalog_uri(&self) -> Option<String> {
+        self.catalog_id.as_ref().map(|id| format!("apple-music:song:{}", id))
+    }
+
+    /// Web page of the catalog song in a storefront.
+    pub fn catalog_url(&self, storefront: &str) -> Option<String> {
+        self.catalog_id
+            .as_ref()
+            .map(|id| format!("https://music.apple.com/{}/song/{}", storefront, id))
+    }
+}
+
+impl ToTrackCandidate for SnapshotTrack {
+    fn to_track_candidate(&self) -> TrackCandidate<'_> {
+        TrackCandidate {
+            artist_name: &self.artist_name,
+            album_name: self.album_name.as_deref().unwrap_or_default(),
+            track_name: &self.name,
+            duration_ms: Some(self.duration_ms),
+        }
+    }
+}
+
+/// A backed up library playlist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotPlaylist {
+    /// ID of the playlist in the library it was backed up from
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub artwork_url: Option<String>,
+    pub is_public: bool,
+    pub can_edit: bool,
+    pub date_added: Option<DateTime<Utc>>,
+    pub last_modified_date: Option<DateTime<Utc>>,
+    pub tracks: Vec<SnapshotTrack>,
+}
+
+impl SnapshotPlaylist {
+    fn new(playlist: &LibraryPlaylist, songs: &[LibrarySong]) -> Self {
+        let attributes = &playlist.attributes;
+        Self {
+            id: playlist.id.clone(),
+            name: attributes.name.clone(),
+            description: attributes.description.as_ref().map(|d| d.standard.clone()),
+            artwork_url: attributes.artwork.as_ref().map(artwork_url),
+            is_public: attributes.is_public,
+            can_edit: attributes.can_edit,
+            date_added: attributes.date_added,
+            last_modified_date: attributes.last_modified_date,
+            tracks: songs.iter().map(SnapshotTrack::new).collect(),
+        }
+    }
+
+    /// Converts the playlist to a playlist file, linking tracks to their catalog songs. Tracks which are not in the
+    /// catalog have no location.
+    pub fn to_playlist_file(&self, storefront: &str) -> PlaylistFile {
+        PlaylistFile {
+            title: self.name.clone(),
+            annotation: self.description.clone(),
+            entries: self
+                .tracks
+                .iter()
+                .map(|track| PlaylistFileEntry {
+                    title: track.name.clone(),
+                    creator: track.artist_name.clone(),
+                    album: track.album_name.clone(),
+                    duration_ms: Some(track.duration_ms),
+                    location: track.catalog_url(storefront),
+                    identifier: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A snapshot of the playlists in an Apple Music library.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibrarySnapshot {
+    pub version: u32,
+    /// Storefront catalog IDs and ISRCs were looked up in
+    pub storefront: String,
+    pub created_at: DateTime<Utc>,
+    pub playlists: Vec<SnapshotPlaylist>,
+}
+
+impl LibrarySnapshot {
+    pub fn load<P: AsRef<Path>>(snapshot_path: P) -> Result<Self> {
+        let snapshot_file = File::open(snapshot_path).context("could not open snapshot file")?;
+        let snapshot: Self = serde_json::from_reader(snapshot_file).context("could not parse snapshot file")?;
+        if snapshot.version > SNAPSHOT_VERSION {
+            bail!(
+                "snapshot file has version {}, but only versions up to {} are supported",
+                snapshot.version,
+                SNAPSHOT_VERSION
+            );
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, snapshot_path: P) -> Result<()> {
+        atomic_file::write_json(snapshot_path, self)
+    }
+
+    /// Writes one row per playlist track.
+    pub fn save_csv<P: AsRef<Path>>(&self, csv_path: P) -> Result<()> {
+        let csv_file = File::create(csv_path).context("could not create CSV file")?;
+        let mut writer = csv::Writer::from_writer(csv_file);
+        writer.write_record([
+            "playlist",
+            "artistName",
+            "albumName",
+            "name",
+            "durationMs",
+            "catalogId",
+            "isrc",
+            "libraryId",
+        ])?;
+        for playlist in &self.playlists {
+            for track in &playlist.tracks {
+                writer.write_record([
+                    playlist.name.as_str(),
+                    &track.artist_name,
+                    track.album_name.as_deref().unwrap_or_default(),
+                    &track.name,
+                    &track.duration_ms.to_string(),
+                    track.catalog_id.as_deref().unwrap_or_default(),
+                    track.isrc.as_deref().unwrap_or_default(),
+                    &track.library_id,
+                ])?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+pub struct BackupOptions {
+    /// If not empty, only playlists with these names are backed up
+    pub playlists: Vec<String>,
+    /// If set, will look up the ISRCs of catalog songs
+    pub resolve_isrcs: bool,
+    /// Directory to write a playlist file of each playlist to
+    pub playlist_dir: Option<PathBuf>,
+    pub playlist_formats: Vec<PlaylistFormat>,
+    /// Path to write a CSV list of the tracks to
+    pub csv: Option<PathBuf>,
+}
+
+fn artwork_url(artwork: &Artwork) -> String {
+    artwork.url_with_dimensions(ARTWORK_SIZE, ARTWORK_SIZE)
+}
+
+async fn get_playlist_songs(music_client: &apple_music::Client, playlist_id: &str) -> Result<Vec<LibrarySong>> {
+    match music_client.get_library_playlist_tracks(playlist_id).await {
+        Ok(res) => Ok(res.all(music_client).await?),
+        // Apple Music responds with 404 Not Found when listing the tracks of an empty playlist
+        Err(apple_music::Error::NotFound(_)) => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Gets catalog songs by their IDs, in batches. Songs which are not available in the storefront are left out.
+pub async fn get_catalog_songs_by_id(
+    music_client: &apple_music::Client,
+    storefront: &str,
+    catalog_ids: &[&str],
+) -> Result<Vec<Song>> {
+    let mut songs = Vec::new();
+    for batch in catalog_ids.chunks(CATALOG_SONGS_BATCH_SIZE) {
+        let ids = batch.join(",");
+        let mut res = music_client
+            .get_catalog_songs(
+                storefront,
+                &CatalogSongsQuery {
+                    ids: Some(&ids),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        songs.append(&mut res.data);
+    }
+    Ok(songs)
+}
+
+impl LibrarySnapshot {
+    /// Takes a snapshot of the playlists in the Apple Music library and their tracks, optionally looking up the ISRCs
+    /// of catalog songs. If `playlist_names` is not empty, only playlists with these names are included.
+    pub async fn take(
+        music_client: &apple_music::Client,
+        storefront: &str,
+        term: &Term,
+        playlist_names: &[String],
+        resolve_isrcs: bool,
+    ) -> Result<Self> {
+        let music_playlists = music_client
+            .get_library_playlists()
+            .await?
+            .all(music_client)
+            .await?;
+        let music_playlists: Vec<_> = music_playlists
+            .into_iter()
+            .filter(|p| playlist_names.is_empty() || playlist_names.contains(&p.attributes.name))
+            .collect();
+
+        term.write_line(&format!(
+            "{} Loaded {} playlist{} from Apple Music",
+            console::style("✔").green(),
+            music_playlists.len(),
+            plural(music_playlists.len())
+        ))?;
+
+        let mut snapshot = Self {
+            version: SNAPSHOT_VERSION,
+            storefront: storefront.to_string(),
+            created_at: Utc::now(),
+            playlists: Vec::new(),
+        };
+        for music_playlist in &music_playlists {
+            let songs = get_playlist_songs(music_client, &music_playlist.id).await?;
+            term.write_line(&format!(
+                "\t{} Loaded playlist {:?} with {} track{}",
+                console::style("✔").green(),
+                &music_playlist.attributes.name,
+                songs.len(),
+                plural(songs.len())
+            ))?;
+            snapshot.playlists.push(SnapshotPlaylist::new(music_playlist, &songs));
+        }
+
+        if resolve_isrcs {
+            let mut catalog_ids: Vec<&str> = snapshot
+                .playlists
+                .iter()
+                .flat_map(|p| &p.tracks)
+                .filter_map(|t| t.catalog_id.as_deref())
+                .collect();
+            catalog_ids.sort_unstable();
+            catalog_ids.dedup();
+
+            term.write_str(&format!(
+                "{} Looking up ISRCs of {} catalog song{}... ",
+                console::style("✱").blue(),
+                catalog_ids.len(),
+                plural(catalog_ids.len())
+            ))?;
+            let isrcs: HashMap<String, String> = get_catalog_songs_by_id(music_client, storefront, &catalog_ids)
+                .await?
+                .into_iter()
+                .filter_map(|song| song.attributes.isrc.map(|isrc| (song.id, isrc)))
+                .collect();
+            term.clear_line()?;
+            term.write_line(&format!(
+                "{} Found ISRCs of {} catalog song{}",
+                console::style("✔").green(),
+                isrcs.len(),
+                plural(isrcs.len())
+            ))?;
+
+            for track in snapshot.playlists.iter_mut().flat_map(|p| &mut p.tracks) {
+                track.isrc = track.catalog_id.as_ref().and_then(|id| isrcs.get(id)).cloned();
+            }
+        }
+
+        Ok(snapshot)
+    }
+}
+
+/// Backs up the playlists in the Apple Music library and their tracks to a JSON snapshot.
+pub async fn backup_apple_music<P: AsRef<Path>>(config: &Config, snapshot_file: P, options: &BackupOptions) -> Result<()> {
+    let term = console::Term::stdout();
+    let music_client = config.apple_music_client()?;
+
+    let snapshot = LibrarySnapshot::take(
+        &music_client,
+        &config.apple_music_storefront,
+        &term,
+        &options.playlists,
+        options.resolve_isrcs,
+    )
+    .await?;
+
+    snapshot.save(&snapshot_file)?;
+    term.write_line(&format!(
+        "{} Wrote snapshot of {} playlist{} to {}",
+        console::style("✔").green(),
+        snapshot.playlists.len(),
+        plural(snapshot.playlists.len()),
+        snapshot_file.as_ref().display()
+    ))?;
+
+    if let Some(playlist_dir) = &options.playlist_dir {
+        std::fs::create_dir_all(playlist_dir)?;
+        let mut file_names = FileNames::default();
+        for playlist in &snapshot.playlists {
+            let playlist_file = playlist.to_playlist_file(&snapshot.storefront);
+            let file_name = file_names.unique(&playlist.name);
+            for format in &options.playlist_formats {
+                let path = playlist_file.save(playlist_dir, &file_name, *format)?;
+                term.write_line(&format!(
+                    "\t{} Wrote playlist {:?} to {}",
+                    console::style("✔").green(),
+                    &playlist.name,
+                    path.display()
+                ))?;
+            }
+        }
+    }
+
+    if let Some(csv_path) = &options.csv {
+        snapshot.save_csv(csv_path)?;
+        term.write_line(&format!(
+            "{} Wrote tracks to {}",
+            console::style("✔").green(),
+            csv_path.display()
+        ))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn snapshot() -> LibrarySnapshot {
+        LibrarySnapshot {
+            version: SNAPSHOT_VERSION,
+            storefront: String::from("no"),
+            created_at: Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap(),
+            playlists: vec![SnapshotPlaylist {
+                id: String::from("p.abc"),
+                name: String::from("Road trip"),
+                description: Some(String::from("Songs for the car")),
+                artwork_url: None,
+                is_public: false,
+                can_edit: true,
+                date_added: Some(Utc.with_ymd_and_hms(2023, 5, 1, 8, 30, 0).unwrap()),
+                last_modified_date: None,
+                tracks: vec![
+                    SnapshotTrack {
+                        library_id: String::from("i.1"),
+                        catalog_id: Some(String::from("1440857781")),
+                        isrc: Some(String::from("GBARL9300135")),
+                        name: String::from("Never Gonna Give You Up"),
+                        artist_name: String::from("Rick Astley"),
+                        album_name: Some(String::from("Whenever You Need Somebody")),
+                        duration_ms: 213_000,
+                        artwork_url: Some(String::from("https://example.com/1200x1200.jpg")),
+                    },
+                    SnapshotTrack {
+                        library_id: String::from("i.2"),
+                        catalog_id: None,
+                        isrc: None,
+                        name: String::from("Demo, take 2"),
+                        artist_name: String::from("Garage \"Band\""),
+                        album_name: None,
+                        duration_ms: 61_500,
+                        artwork_url: None,
+                    },
+                ],
+            }],
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_snapshot() {
+        let dir = TestDir::new("snapshot");
+        let path = dir.join("snapshot.json");
+
+        snapshot().save(&path).unwrap();
+        let loaded = LibrarySnapshot::load(&path).unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(snapshot()).unwrap()
+        );
+        assert_eq!(loaded.playlists[0].tracks[0].catalog_uri().as_deref(), Some("apple-music:song:1440857781"));
+    }
+
+    #[test]
+    fn fails_to_load_newer_snapshot() {
+        let dir = TestDir::new("snapshot-version");
+        let path = dir.join("snapshot.json");
+
+        LibrarySnapshot {
+            version: SNAPSHOT_VERSION + 1,
+            ..snapshot()
+        }
+        .save(&path)
+        .unwrap();
+        let e = LibrarySnapshot::load(&path).unwrap_err();
+        assert!(e.to_string().contains(&format!("version {}", SNAPSHOT_VERSION + 1)), "{}", e);
+    }
+
+    #[test]
+    fn saves_csv() {
+        let dir = TestDir::new("snapshot-csv");
+        let path = dir.join("tracks.csv");
+
+        snapshot().save_csv(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "playlist,artistName,albumName,name,durationMs,catalogId,isrc,libraryId\n\
+             Road trip,Rick Astley,Whenever You Need Somebody,Never Gonna Give You Up,213000,1440857781,GBARL9300135,i.1\n\
+             Road trip,\"Garage \"\"Band\"\"\",,\"Demo, take 2\",61500,,,i.2\n"
+        );
+    }
+
+    #[test]
+    fn converts_to_playlist_file() {
+        let playlist_file = snapshot().playlists[0].to_playlist_file("us");
+        assert_eq!(playlist_file.title, "Road trip");
+        assert_eq!(playlist_file.annotation.as_deref(), Some("Songs for the car"));
+        assert_eq!(
+            playlist_file.entries[0].location.as_deref(),
+            Some("https://music.apple.com/us/song/1440857781")
+        );
+        assert_eq!(playlist_file.entries[0].duration_ms, Some(213_000));
+        assert_eq!(playlist_file.entries[1].location, None);
+        assert_eq!(playlist_file.entries[1].creator, "Garage \"Band\"");
+    }
+}
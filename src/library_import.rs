@@ -0,0 +1,238 @@
+use std::path::Path;
+
+use anyhow::Result;
+use console::Term;
+use dialoguer::theme::ColorfulTheme;
+
+use crate::apple_music::request::LibraryAddQuery;
+use crate::config::Config;
+use crate::matching::{ToAlbumCandidate, ToTrackCandidate};
+use crate::my_spotify_data::your_library::YourLibrary;
+use crate::track_finder::{TrackFinder, TrackFinderOptions};
+use crate::{apple_music, is_apple_music_auth_error, plural, TRACK_BATCH_SIZE};
+
+/// Kinds of saved items which can be imported from `YourLibrary.json`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LibraryItemKind {
+    /// Liked songs
+    Tracks,
+    /// Saved albums
+    Albums,
+}
+
+pub struct LibraryImportOptions {
+    /// Kinds of saved items to import, preselected when run interactively
+    pub include: Vec<LibraryItemKind>,
+    /// If set, will not add songs or albums to the library
+    pub dry: bool,
+    pub finder: TrackFinderOptions,
+}
+
+fn select_kinds(term: &Term, library: &YourLibrary, include: &[LibraryItemKind]) -> Result<Vec<LibraryItemKind>> {
+    if !dialoguer::console::user_attended() {
+        return Ok(include.to_vec());
+    }
+
+    let kinds = [
+        (LibraryItemKind::Tracks, format!("Liked songs ({})", library.tracks.len())),
+        (LibraryItemKind::Albums, format!("Saved albums ({})", library.albums.len())),
+    ];
+    let kinds_checked: Vec<(&str, bool)> = kinds
+        .iter()
+        .map(|(kind, label)| (label.as_str(), include.contains(kind)))
+        .collect();
+
+    let selected_indices = dialoguer::MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Select saved items to import to the Apple Music library")
+        .items_checked(&kinds_checked)
+        .interact_on(term)?;
+
+    Ok(selected_indices.into_iter().map(|i| kinds[i].0).collect())
+}
+
+/// Adds catalog songs or albums to the library, in batches.
+async fn add_to_library(
+    music_client: &apple_music::Client,
+    ids: &[String],
+    to_query: impl Fn(String) -> LibraryAddQuery,
+) -> Result<()> {
+    for batch in ids.chunks(TRACK_BATCH_SIZE) {
+        music_client.add_to_library(&to_query(batch.join(","))).await?;
+    }
+    Ok(())
+}
+
+/// Imports liked songs and saved albums from `YourLibrary.json` to the Apple Music library.
+pub async fn import_spotify_library_to_apple_music<P: AsRef<Path>>(
+    config: &Config,
+    library_file: P,
+    options: &LibraryImportOptions,
+) -> Result<()> {
+    let term = console::Term::stdout();
+    let music_client = config.apple_music_client()?;
+    let track_finder = TrackFinder::new(&music_client, &config.apple_music_storefront, &term, &options.finder);
+
+    let library = tokio::fs::read_to_string(library_file).await?;
+    let library: YourLibrary = serde_json::from_str(&library)?;
+
+    term.write_line(&format!(
+        "{} Loaded {} liked song{} and {} saved album{} from Spotify data export",
+        console::style("✔").green(),
+        library.tracks.len(),
+        plural(library.tracks.len()),
+        library.albums.len(),
+        plural(library.albums.len())
+    ))?;
+
+    let kinds = select_kinds(&term, &library, &options.include)?;
+
+    if kinds.contains(&LibraryItemKind::Tracks) {
+        term.write_line(&format!("{} Processing liked songs", console::style("✱").blue()))?;
+
+        let mut song_ids = Vec::new();
+        for track in &library.tracks {
+            let track_match = track_finder.find(Some(&track.uri), track.to_track_candidate()).await?;
+            if let Some(found) = track_match.found() {
+                song_ids.push(found.id.clone());
+            }
+        }
+
+        if let Some(match_cache) = &options.finder.match_cache {
+            match_cache.save()?;
+        }
+
+        if options.dry {
+            term.write_line(&format!(
+                "{} Skipped adding {} of {} liked song{} to the Apple Music library (--dry)",
+                console::style("✱").blue(),
+                song_ids.len(),
+                library.tracks.len(),
+                plural(library.tracks.len())
+            ))?;
+        } else {
+            let to_query = |ids| LibraryAddQuery {
+                songs: Some(ids),
+                ..Default::default()
+            };
+            match add_to_library(&music_client, &song_ids, to_query).await {
+                Ok(()) => term.write_line(&format!(
+                    "{} Added {} of {} liked song{} to the Apple Music library",
+                    console::style("✔").green(),
+                    song_ids.len(),
+                    library.tracks.len(),
+                    plural(library.tracks.len())
+                ))?,
+                Err(e) if is_apple_music_auth_error(&e) => return Err(e),
+                Err(e) => term.write_line(&format!(
+                    "{} Failed to add liked songs to the Apple Music library: {}",
+                    console::style("✘").red(),
+                    e
+                ))?,
+            }
+        }
+    }
+
+    if kinds.contains(&LibraryItemKind::Albums) {
+        term.write_line(&format!("{} Processing saved albums", console::style("✱").blue()))?;
+
+        let mut album_ids = Vec::new();
+        for album in &library.albums {
+            if let Some(found) = track_finder.find_album(album.to_album_candidate()).await? {
+                album_ids.push(found.id);
+            }
+        }
+
+        if options.dry {
+            term.write_line(&format!(
+                "{} Skipped adding {} of {} saved album{} to the Apple Music library (--dry)",
+                console::style("✱").blue(),
+                album_ids.len(),
+                library.albums.len(),
+                plural(library.albums.len())
+            ))?;
+        } else {
+            let to_query = |ids| LibraryAddQuery {
+                albums: Some(ids),
+                ..Default::default()
+            };
+            match add_to_library(&music_client, &album_ids, to_query).await {
+                Ok(()) => term.write_line(&format!(
+                    "{} Added {} of {} saved album{} to the Apple Music library",
+                    console::style("✔").green(),
+                    album_ids.len(),
+                    library.albums.len(),
+                    plural(library.albums.len())
+                ))?,
+                Err(e) if is_apple_music_auth_error(&e) => return Err(e),
+                Err(e) => term.write_line(&format!(
+                    "{} Failed to add saved albums to the Apple Music library: {}",
+                    console::style("✘").red(),
+                    e
+                ))?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{self, MockServer};
+    use crate::test_dir::TestDir;
+
+    #[tokio::test]
+    async fn stops_when_adding_to_library_is_not_authorized() {
+        let songs = vec![mock_server::apple_music_song(
+            "1",
+            "Rick Astley",
+            "Whenever You Need Somebody",
+            "Never Gonna Give You Up",
+            "GBARL9300135",
+        )];
+        let apple_music = mock_server::apple_music(songs, Vec::new());
+        let server = MockServer::start(move |request| match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/v1/me/library") => mock_server::apple_music_error(403, "Forbidden", "Invalid authentication"),
+            _ => apple_music(request),
+        })
+        .await;
+
+        let dir = TestDir::new("library-import");
+        let library_file = dir.join("YourLibrary.json");
+        std::fs::write(
+            &library_file,
+            r#"{
+                "tracks": [{ "artist": "Rick Astley", "album": "Whenever You Need Somebody", "track": "Never Gonna Give You Up", "uri": "spotify:track:4uLU6hMCjMI75M1A2tKUQC" }],
+                "albums": [{ "artist": "Rick Astley", "album": "Whenever You Need Somebody", "uri": "spotify:album:6XhjNHCyCDyyGJRM5mg40G" }]
+            }"#,
+        )
+        .unwrap();
+        let config = Config {
+            apple_music_user_token: String::from("user-token"),
+            apple_music_api_base_url: Some(server.url()),
+            apple_music_web_base_url: Some(server.url()),
+            ..Default::default()
+        };
+        let options = LibraryImportOptions {
+            include: vec![LibraryItemKind::Tracks, LibraryItemKind::Albums],
+            dry: false,
+            finder: TrackFinderOptions {
+                min_score: 0.8,
+                limit: 10,
+                isrc_resolvers: Vec::new(),
+                track_metadata: None,
+                review: None,
+                match_cache: None,
+            },
+        };
+
+        let e = import_spotify_library_to_apple_music(&config, &library_file, &options).await.unwrap_err();
+        assert!(is_apple_music_auth_error(&e), "{:#}", e);
+        // saved albums are not looked up after liked songs could not be added
+        let requests = server.requests();
+        let last = requests.last().unwrap();
+        assert_eq!((last.method.as_str(), last.path.as_str()), ("POST", "/v1/me/library"));
+        assert_eq!(last.query_param("ids[songs]"), Some("1"));
+    }
+}
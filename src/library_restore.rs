@@ -0,0 +1,311 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::apple_music::response::PaginatedResponse;
+use crate::config::Config;
+use crate::import_state::{ImportState, MatchedTrack};
+use crate::isrc::IsrcResolver;
+use crate::library_backup::{get_catalog_songs_by_id, LibrarySnapshot};
+use crate::matching::ToTrackCandidate;
+use crate::track_finder::{TrackFinder, TrackFinderOptions};
+use crate::{add_matched_tracks, is_apple_music_auth_error, plural};
+
+pub struct RestoreOptions {
+    /// If not empty, only playlists with these names are restored
+    pub playlists: Vec<String>,
+    /// If set, will not create playlists
+    pub dry: bool,
+    pub finder: TrackFinderOptions,
+}
+
+/// Resolves the ISRCs recorded in a snapshot, by the catalog URI of the track.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotIsrcResolver {
+    isrcs: HashMap<String, String>,
+}
+
+impl SnapshotIsrcResolver {
+    pub fn new(snapshot: &LibrarySnapshot) -> Self {
+        Self {
+            isrcs: snapshot
+                .playlists
+                .iter()
+                .flat_map(|p| &p.tracks)
+                .filter_map(|t| Some((t.catalog_uri()?, t.isrc.clone()?)))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl IsrcResolver for SnapshotIsrcResolver {
+    async fn resolve(&self, track_uri: &str) -> Result<Option<String>> {
+        Ok(self.isrcs.get(track_uri).cloned())
+    }
+}
+
+/// Recreates the playlists in a library snapshot in the Apple Music library.
+///
+/// Catalog songs are used as they are if they are available in the configured storefront. Otherwise, for example when
+/// restoring a snapshot taken in another storefront, they are looked up by ISRC, falling back to searching the catalog.
+pub async fn restore_library_snapshot(config: &Config, snapshot: &LibrarySnapshot, options: &RestoreOptions) -> Result<()> {
+    let term = console::Term::stdout();
+    let music_client = config.apple_music_client()?;
+    let track_finder = TrackFinder::new(&music_client, &config.apple_music_storefront, &term, &options.finder);
+
+    let playlists: Vec<_> = snapshot
+        .playlists
+        .iter()
+        .filter(|p| options.playlists.is_empty() || options.playlists.contains(&p.name))
+        .collect();
+
+    term.write_line(&format!(
+        "{} Loaded {} playlist{} from snapshot taken {}",
+        console::style("✔").green(),
+        playlists.len(),
+        plural(playlists.len()),
+        snapshot.created_at.format("%Y-%m-%d %H:%M")
+    ))?;
+    if snapshot.storefront != config.apple_music_storefront {
+        term.write_line(&format!(
+            "{} Snapshot was taken in storefront {:?}, songs will be looked up in storefront {:?}",
+            console::style("✱").yellow(),
+            &snapshot.storefront,
+            &config.apple_music_storefront
+        ))?;
+    }
+
+    let existing_names: HashSet<String> = if options.dry {
+        HashSet::new()
+    } else {
+        music_client
+            .get_library_playlists()
+            .await?
+            .all(&music_client)
+            .await?
+            .into_iter()
+            .map(|p| p.attributes.name)
+            .collect()
+    };
+
+    let mut catalog_ids: Vec<&str> = playlists
+        .iter()
+        .flat_map(|p| &p.tracks)
+        .filter_map(|t| t.catalog_id.as_deref())
+        .collect();
+    catalog_ids.sort_unstable();
+    catalog_ids.dedup();
+
+    term.write_str(&format!(
+        "{} Looking up {} catalog song{} in the Apple Music catalog... ",
+        console::style("✱").blue(),
+        catalog_ids.len(),
+        plural(catalog_ids.len())
+    ))?;
+    let available: HashSet<String> =
+        get_catalog_songs_by_id(&music_client, &config.apple_music_storefront, &catalog_ids)
+            .await?
+            .into_iter()
+            .map(|song| song.id)
+            .collect();
+    term.clear_line()?;
+    term.write_line(&format!(
+        "{} {} of {} catalog song{} are available in storefront {:?}",
+        console::style("✔").green(),
+        available.len(),
+        catalog_ids.len(),
+        plural(catalog_ids.len()),
+        &config.apple_music_storefront
+    ))?;
+
+    for playlist in playlists {
+        if existing_names.contains(&playlist.name) {
+            term.write_line(&format!(
+                "{} Playlist {:?} already exists in Apple Music",
+                console::style("✔").green(),
+                &playlist.name
+            ))?;
+            continue;
+        }
+
+        term.write_line(&format!(
+            "{} Restoring playlist {:?} with {} track{}",
+            console::style("✱").blue(),
+            &playlist.name,
+            playlist.tracks.len(),
+            plural(playlist.tracks.len())
+        ))?;
+
+        let mut matched = Vec::new();
+        for track in &playlist.tracks {
+            let track_uri = track.catalog_uri();
+            if let Some(catalog_id) = track.catalog_id.as_ref().filter(|id| available.contains(*id)) {
+                matched.push(MatchedTrack {
+                    track_uri: track_uri.unwrap_or_default(),
+                    song_id: catalog_id.clone(),
+                });
+                continue;
+            }
+
+            let track_match = track_finder.find(track_uri.as_deref(), track.to_track_candidate()).await?;
+            if let Some(found) = track_match.found() {
+                matched.push(MatchedTrack {
+                    track_uri: track_uri.unwrap_or_default(),
+                    song_id: found.id.clone(),
+                });
+            }
+        }
+
+        if options.dry {
+            term.write_line(&format!(
+                "{} Skipped creating playlist {:?} with {} track{} in Apple Music (--dry)",
+                console::style("✱").blue(),
+                &playlist.name,
+                matched.len(),
+                plural(matched.len())
+            ))?;
+            continue;
+        }
+
+        let track_count = matched.len();
+        let mut state = ImportState::default();
+        state.playlist_mut(&playlist.name).matched = matched;
+        match add_matched_tracks(&music_client, &playlist.name, playlist.description.as_deref(), &mut state, &playlist.name, None).await {
+            Ok(playlist_id) => term.write_line(&format!(
+                "{} Created playlist {:?} with {} of {} track{} in Apple Music: https://music.apple.com/{}/library/playlist/{}",
+                console::style("✔").green(),
+                &playlist.name,
+                track_count,
+                playlist.tracks.len(),
+                plural(playlist.tracks.len()),
+                &config.apple_music_storefront,
+                &playlist_id
+            ))?,
+            // the tokens will not be accepted for the remaining playlists either
+            Err(e) if is_apple_music_auth_error(&e) => return Err(e),
+            Err(e) => term.write_line(&format!(
+                "{} Failed to create playlist {:?} in Apple Music: {}",
+                console::style("✘").red(),
+                &playlist.name,
+                e
+            ))?,
+        }
+    }
+
+    if let Some(match_cache) = &options.finder.match_cache {
+        match_cache.save()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use super::*;
+    use crate::library_backup::{SnapshotPlaylist, SnapshotTrack, SNAPSHOT_VERSION};
+    use crate::mock_server::{self, MockServer};
+
+    fn track(catalog_id: Option<&str>, isrc: Option<&str>, artist_name: &str, album_name: &str, name: &str) -> SnapshotTrack {
+        SnapshotTrack {
+            library_id: format!("i.{}", name),
+            catalog_id: catalog_id.map(String::from),
+            isrc: isrc.map(String::from),
+            name: name.to_string(),
+            artist_name: artist_name.to_string(),
+            album_name: Some(album_name.to_string()),
+            duration_ms: 200_000,
+            artwork_url: None,
+        }
+    }
+
+    fn playlist(name: &str, tracks: Vec<SnapshotTrack>) -> SnapshotPlaylist {
+        SnapshotPlaylist {
+            id: format!("p.{}", name),
+            name: name.to_string(),
+            description: None,
+            artwork_url: None,
+            is_public: false,
+            can_edit: true,
+            date_added: None,
+            last_modified_date: None,
+            tracks,
+        }
+    }
+
+    #[tokio::test]
+    async fn restores_snapshot_from_another_storefront() {
+        let server = MockServer::start(mock_server::apple_music(
+            vec![
+                mock_server::apple_music_song("1", "Rick Astley", "Whenever You Need Somebody", "Never Gonna Give You Up", "GBARL9300135"),
+                mock_server::apple_music_song("2", "a-ha", "Hunting High and Low", "Take On Me", "GBAYE8500024"),
+                mock_server::apple_music_song("3", "New Order", "Power, Corruption & Lies", "Blue Monday", "GBAAP0000003"),
+            ],
+            vec![mock_server::apple_music_library_playlist("p.existing", "Existing")],
+        ))
+        .await;
+        let config = Config {
+            apple_music_user_token: String::from("user-token"),
+            apple_music_api_base_url: Some(server.url()),
+            apple_music_web_base_url: Some(server.url()),
+            ..Default::default()
+        };
+
+        let snapshot = LibrarySnapshot {
+            version: SNAPSHOT_VERSION,
+            storefront: String::from("us"),
+            created_at: Utc.with_ymd_and_hms(2023, 6, 1, 12, 0, 0).unwrap(),
+            playlists: vec![
+                playlist(
+                    "Road trip",
+                    vec![
+                        // available in the storefront
+                        track(Some("1"), Some("GBARL9300135"), "Rick Astley", "Whenever You Need Somebody", "Never Gonna Give You Up"),
+                        // only available in the storefront of the snapshot, found by ISRC
+                        track(Some("500"), Some("GBAYE8500024"), "a-ha", "Hunting High and Low", "Take On Me"),
+                        // uploaded to the library, found by searching
+                        track(None, None, "New Order", "Power, Corruption & Lies", "Blue Monday"),
+                    ],
+                ),
+                playlist("Existing", vec![track(Some("1"), None, "Rick Astley", "Whenever You Need Somebody", "Never Gonna Give You Up")]),
+            ],
+        };
+        let mut options = RestoreOptions {
+            playlists: Vec::new(),
+            dry: false,
+            finder: TrackFinderOptions {
+                min_score: 0.8,
+                limit: 10,
+                isrc_resolvers: Vec::new(),
+                track_metadata: None,
+                review: None,
+                match_cache: None,
+            },
+        };
+        options.finder.isrc_resolvers.push(Box::new(SnapshotIsrcResolver::new(&snapshot)));
+
+        restore_library_snapshot(&config, &snapshot, &options).await.unwrap();
+
+        let requests = server.requests();
+        let id_lookups: Vec<_> = requests.iter().filter_map(|r| r.query_param("ids")).collect();
+        assert_eq!(id_lookups, ["1,500"]);
+        assert!(requests.iter().any(|r| r.query_param("filter[isrc]") == Some("GBAYE8500024")));
+        assert!(requests.iter().any(|r| r.path.ends_with("/search")));
+
+        let created: Vec<_> = requests
+            .iter()
+            .filter(|r| r.method == "POST" && r.path == "/v1/me/library/playlists")
+            .collect();
+        assert_eq!(created.len(), 1, "playlists which already exist are skipped");
+        let body = created[0].json();
+        assert_eq!(body["attributes"]["name"], "Road trip");
+        assert_eq!(
+            body["relationships"]["tracks"]["data"],
+            json!([{ "id": "1", "type": "songs" }, { "id": "2", "type": "songs" }, { "id": "3", "type": "songs" }])
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as ErrorContext, Result};
+use lofty::prelude::*;
+
+use crate::matching::{self, FuzzyMatcher, TrackCandidate, ToTrackCandidate};
+use crate::playlist_files::PlaylistFileEntry;
+
+/// File extensions of audio files with tags which can be read.
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "m4a", "mp4", "aac", "ogg", "oga", "opus"];
+
+/// A tagged audio file in a local music directory.
+#[derive(Debug, Clone)]
+pub struct LocalFile {
+    pub path: PathBuf,
+    pub artist_name: String,
+    pub album_name: String,
+    pub track_name: String,
+    pub duration_ms: u64,
+}
+
+impl ToTrackCandidate for LocalFile {
+    fn to_track_candidate(&self) -> TrackCandidate<'_> {
+        TrackCandidate {
+            artist_name: &self.artist_name,
+            album_name: &self.album_name,
+            track_name: &self.track_name,
+            duration_ms: Some(self.duration_ms),
+        }
+    }
+}
+
+impl LocalFile {
+    pub fn to_playlist_entry(&self) -> PlaylistFileEntry {
+        PlaylistFileEntry {
+            title: self.track_name.clone(),
+            creator: self.artist_name.clone(),
+            album: Some(self.album_name.clone()).filter(|a| !a.is_empty()),
+            duration_ms: Some(self.duration_ms),
+            location: Some(self.path.display().to_string()),
+            identifier: None,
+        }
+    }
+
+    /// Reads the tags of an audio file. Untagged files are named after the file.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let tagged_file = lofty::read_from_path(path)?;
+        let tag = tagged_file.primary_tag().or_else(|| tagged_file.first_tag());
+        let file_stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            artist_name: tag.and_then(|t| t.artist()).map(|s| s.into_owned()).unwrap_or_default(),
+            album_name: tag.and_then(|t| t.album()).map(|s| s.into_owned()).unwrap_or_default(),
+            track_name: tag.and_then(|t| t.title()).map(|s| s.into_owned()).unwrap_or(file_stem),
+            duration_ms: tagged_file.properties().duration().as_millis() as u64,
+        })
+    }
+}
+
+/// Tagged audio files found in a local music directory.
+#[derive(Debug, Clone, Default)]
+pub struct LocalLibrary {
+    pub files: Vec<LocalFile>,
+}
+
+impl LocalLibrary {
+    /// Scans a directory and its subdirectories for audio files. Files which can not be read are skipped.
+    ///
+    /// Symbolic links to directories are not followed, as a link to a parent directory would be scanned forever.
+    pub fn scan<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut files = Vec::new();
+        let mut dirs = vec![dir.as_ref().to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let entries = std::fs::read_dir(&dir).with_context(|| format!("could not read {}", dir.display()))?;
+            for entry in entries {
+                let entry = entry?;
+                let path = entry.path();
+                if entry.file_type()?.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                let is_audio = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| AUDIO_EXTENSIONS.iter().any(|a| e.eq_ignore_ascii_case(a)));
+                if !is_audio {
+                    continue;
+                }
+
+                match LocalFile::read(&path) {
+                    Ok(file) => files.push(file),
+                    Err(e) => log::warn!("Could not read tags of {}: {}", path.display(), e),
+                }
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self { files })
+    }
+
+    /// Finds the file best matching a local track, scored like catalog songs.
+    pub fn find(&self, query: &TrackCandidate, min_score: f64) -> Option<&LocalFile> {
+        matching::rank(&FuzzyMatcher, query, &self.files)
+            .into_iter()
+            .next()
+            .filter(|(_, result)| result.is_match(min_score))
+            .map(|(file, _)| file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lofty::config::WriteOptions;
+    use lofty::tag::{Tag, TagType};
+
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    /// Writes an MP3 file of silent frames, tagged unless no artist is given.
+    fn write_mp3(path: &Path, artist_name: Option<&str>, track_name: &str) {
+        // MPEG-1 Layer III frames at 128 kbit/s and 44.1 kHz, 417 bytes each
+        let mut frame = vec![0; 417];
+        frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+        std::fs::write(path, frame.repeat(100)).unwrap();
+
+        if let Some(artist_name) = artist_name {
+            let mut tag = Tag::new(TagType::Id3v2);
+            tag.set_artist(artist_name.to_string());
+            tag.set_album(String::from("Hunting High and Low"));
+            tag.set_title(track_name.to_string());
+            tag.save_to_path(path, WriteOptions::default()).unwrap();
+        }
+    }
+
+    #[test]
+    fn scans_and_finds_files() {
+        let dir = TestDir::new("local-files");
+        std::fs::create_dir_all(dir.join("a-ha/Hunting High and Low")).unwrap();
+        write_mp3(&dir.join("a-ha/Hunting High and Low/01.mp3"), Some("a-ha"), "Take On Me");
+        write_mp3(&dir.join("a-ha/Hunting High and Low/02.MP3"), Some("a-ha"), "Train of Thought");
+        write_mp3(&dir.join("Untagged.mp3"), None, "");
+        std::fs::write(dir.join("cover.jpg"), "not audio").unwrap();
+        std::fs::write(dir.join("broken.flac"), "not FLAC").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("a-ha/loop")).unwrap();
+
+        let library = LocalLibrary::scan(&dir).unwrap();
+        let track_names: Vec<_> = library.files.iter().map(|f| f.track_name.as_str()).collect();
+        assert_eq!(track_names, ["Untagged", "Take On Me", "Train of Thought"]);
+        assert_eq!(library.files[1].album_name, "Hunting High and Low");
+        assert!(library.files[1].duration_ms > 2000);
+
+        let query = TrackCandidate {
+            artist_name: "a-ha",
+            album_name: "Hunting High and Low",
+            track_name: "Take On Me",
+            duration_ms: None,
+        };
+        let found = library.find(&query, 0.8).unwrap();
+        assert_eq!(found.path, dir.join("a-ha/Hunting High and Low/01.mp3"));
+        let query = TrackCandidate {
+            artist_name: "Rick Astley",
+            album_name: "Whenever You Need Somebody",
+            track_name: "Never Gonna Give You Up",
+            duration_ms: None,
+        };
+        assert!(library.find(&query, 0.8).is_none());
+    }
+}
@@ -1,23 +1,62 @@
 mod apple_music;
 mod args;
+mod atomic_file;
 mod config;
+mod history_playlist;
+mod import_state;
+mod isrc;
+mod library_backup;
+mod library_import;
+mod library_restore;
+mod local_files;
 mod logger;
+mod match_cache;
+mod matching;
+#[cfg(test)]
+mod mock_server;
+#[allow(dead_code)]
 mod my_spotify_data;
+mod playlist_files;
+mod podcasts;
+mod report;
+mod review;
+mod spotify_import;
+mod spotify_web_api;
+mod stats;
+#[cfg(test)]
+mod test_dir;
+mod track_finder;
+mod track_metadata;
 
 use apple_music::request::{
     LibraryPlaylistCreationRequest, LibraryPlaylistCreationRequestAttributes,
-    LibraryPlaylistCreationRequestRelationships, Objects, SearchQuery,
+    LibraryPlaylistCreationRequestRelationships, Objects,
 };
-use apple_music::response::{ListResponse, SearchResponse, PaginatedResponse};
-use apple_music::{ObjectType, ToRequestObject};
+use apple_music::response::{LibraryPlaylist, PaginatedResponse};
+use apple_music::ToRequestObject;
+use import_state::{playlist_key, ImportState, MatchedTrack};
+use isrc::IsrcResolver;
+use match_cache::MatchCache;
+use matching::ToTrackCandidate;
+use my_spotify_data::extended_streaming_history::ExtendedStreamingHistory;
+use my_spotify_data::streaming_history::StreamingHistory;
 use my_spotify_data::{
     Playlist as SpotifyPlaylist, PlaylistItemAbstraction, Playlists as SpotifyPlaylists,
 };
-use strsim::normalized_damerau_levenshtein;
+use local_files::LocalLibrary;
+use playlist_files::{FileNames, PlaylistFile, PlaylistFormat};
+use podcasts::{ApplePodcastsSearch, PodcastExport};
+use report::{PlaylistReport, PlaylistStatus, Report, ReportFormat, TrackReport};
+use review::ReviewOptions;
+use track_finder::{SkipReason, TrackFinder, TrackFinderOptions, TrackMatch};
+use track_metadata::TrackMetadataCache;
 
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
 use console::Term;
 use dialoguer::theme::ColorfulTheme;
 
@@ -37,20 +76,252 @@ async fn main() -> Result<()> {
             playlist_file,
             playlists,
             dry,
+            sync,
+            show_extras,
+            state_file,
+            resume,
+            report,
+            report_format,
+            podcasts,
+            local,
+            match_args,
+        } => {
+            let options = ImportOptions {
+                dry,
+                interactive: dialoguer::console::user_attended(),
+                sync,
+                show_extras,
+                state_file: state_file.or_else(|| resume.then(|| PathBuf::from(DEFAULT_STATE_FILE))),
+                resume,
+                report: report.map(|path| {
+                    let format = report_format.unwrap_or_else(|| ReportFormat::from_path(&path));
+                    (path, format)
+                }),
+                podcasts,
+                local,
+                finder: track_finder_options(match_args)?,
+            };
+            import_spotify_playlists_to_apple_music(&config, playlist_file, playlists, &options).await?
+        }
+        args::Command::ImportSpotifyGdprLibraryToAppleMusicApi {
+            library_file,
+            include,
+            dry,
+            match_args,
+        } => {
+            let options = library_import::LibraryImportOptions {
+                include,
+                dry,
+                finder: track_finder_options(match_args)?,
+            };
+            library_import::import_spotify_library_to_apple_music(&config, library_file, &options).await?
+        }
+        args::Command::CreateStreamingHistoryPlaylistInAppleMusicApi {
+            data_dir,
+            name,
+            description,
+            year,
+            from,
+            to,
+            rank_by,
+            top,
+            artists,
+            min_plays,
+            exclude_skips,
+            dry,
+            match_args,
+        } => {
+            let (from, to) = match year {
+                Some(year) => (
+                    Some(NaiveDate::from_ymd_opt(year, 1, 1).ok_or(anyhow!("invalid year {}", year))?),
+                    Some(NaiveDate::from_ymd_opt(year, 12, 31).ok_or(anyhow!("invalid year {}", year))?),
+                ),
+                None => (from, to),
+            };
+            let name = name.unwrap_or_else(|| match (year, from, to) {
+                (Some(year), _, _) => format!("Top {} of {}", top, year),
+                (None, Some(from), Some(to)) => format!("Top {} from {} to {}", top, from, to),
+                (None, Some(from), None) => format!("Top {} since {}", top, from),
+                (None, None, Some(to)) => format!("Top {} until {}", top, to),
+                (None, None, None) => format!("Top {} of all time", top),
+            });
+            let options = history_playlist::HistoryPlaylistOptions {
+                name,
+                description,
+                from,
+                to,
+                rank_by,
+                top,
+                artists: artists.unwrap_or_default(),
+                min_plays,
+                exclude_skips,
+                dry,
+                finder: track_finder_options(match_args)?,
+            };
+            history_playlist::create_history_playlist(&config, data_dir, &options).await?
+        }
+        args::Command::ImportAppleMusicPlaylistsToSpotifyApi {
+            playlists,
+            dry,
+            public,
             min_score,
             limit,
-        } => import_spotify_playlists_to_apple_music(&config, playlist_file, playlists, dry, min_score, limit).await?,
+            spotify_client_id,
+            spotify_access_token,
+            redirect_port,
+        } => {
+            let options = spotify_import::SpotifyImportOptions {
+                playlists: playlists.unwrap_or_default(),
+                dry,
+                public,
+                min_score,
+                limit,
+                access_token: spotify_access_token,
+                client_id: spotify_client_id,
+                redirect_port,
+            };
+            spotify_import::import_apple_music_playlists_to_spotify(&config, &options).await?
+        }
+        args::Command::Export {
+            playlist_file,
+            playlists,
+            format,
+            output_dir,
+        } => export_spotify_playlists(playlist_file, playlists, &format, output_dir).await?,
+        args::Command::BackupAppleMusic {
+            snapshot_file,
+            playlists,
+            skip_isrcs,
+            playlist_dir,
+            playlist_format,
+            csv,
+        } => {
+            let options = library_backup::BackupOptions {
+                playlists: playlists.unwrap_or_default(),
+                resolve_isrcs: !skip_isrcs,
+                playlist_dir,
+                playlist_formats: playlist_format,
+                csv,
+            };
+            library_backup::backup_apple_music(&config, snapshot_file, &options).await?
+        }
+        args::Command::Restore {
+            snapshot_file,
+            playlists,
+            dry,
+            match_args,
+        } => {
+            let snapshot = library_backup::LibrarySnapshot::load(snapshot_file)?;
+            let mut finder = track_finder_options(match_args)?;
+            // ISRCs in the snapshot take precedence, as they were looked up for the exact catalog song
+            finder
+                .isrc_resolvers
+                .insert(0, Box::new(library_restore::SnapshotIsrcResolver::new(&snapshot)));
+            let options = library_restore::RestoreOptions {
+                playlists: playlists.unwrap_or_default(),
+                dry,
+                finder,
+            };
+            library_restore::restore_library_snapshot(&config, &snapshot, &options).await?
+        }
+        args::Command::ApplyMapping {
+            mapping_file,
+            playlists,
+            dry,
+        } => apply_mapping(&config, mapping_file, playlists, dry).await?,
+        args::Command::Stats {
+            data_dir,
+            from,
+            to,
+            top,
+            min_streams,
+        } => {
+            let term = console::Term::stdout();
+            let options = stats::StatsOptions {
+                from,
+                to,
+                top,
+                min_streams,
+            };
+
+            let extended_history = ExtendedStreamingHistory::load(&data_dir)?;
+            if !extended_history.streams.is_empty() {
+                term.write_line(&format!(
+                    "{} Loaded {} stream{} from Spotify extended streaming history",
+                    console::style("✔").green(),
+                    extended_history.streams.len(),
+                    plural(extended_history.streams.len())
+                ))?;
+                stats::print_extended_streaming_history_stats(&term, &extended_history, &options)?
+            } else {
+                let history = StreamingHistory::load(&data_dir)?;
+                term.write_line(&format!(
+                    "{} Loaded {} music stream{} and {} podcast stream{} from Spotify data export",
+                    console::style("✔").green(),
+                    history.music.len(),
+                    plural(history.music.len()),
+                    history.podcasts.len(),
+                    plural(history.podcasts.len())
+                ))?;
+                stats::print_streaming_history_stats(&term, &history, &options)?
+            }
+        }
     };
 
     Ok(())
 }
 
+fn track_finder_options(match_args: args::MatchArgs) -> Result<TrackFinderOptions> {
+    let track_metadata = match_args
+        .track_metadata
+        .map(TrackMetadataCache::load)
+        .transpose()?
+        .map(Arc::new);
+
+    let mut isrc_resolvers: Vec<Box<dyn IsrcResolver>> = Vec::new();
+    if let Some(isrc_mapping) = match_args.isrc_mapping {
+        isrc_resolvers.push(Box::new(isrc::MappingFileResolver::load(isrc_mapping)?));
+    }
+    if let Some(track_metadata) = &track_metadata {
+        isrc_resolvers.push(Box::new(track_metadata.clone()));
+    }
+
+    Ok(TrackFinderOptions {
+        min_score: match_args.min_score,
+        limit: match_args.limit,
+        isrc_resolvers,
+        track_metadata,
+        review: match_args.review.then_some(ReviewOptions {
+            uncertain_below: match_args.review_below,
+            close_margin: match_args.review_margin,
+        }),
+        match_cache: match_args.match_cache.map(MatchCache::load_or_init).transpose()?,
+    })
+}
+
+/// Whether an error means the Apple Music tokens were not accepted, so every following request would fail too.
+fn is_apple_music_auth_error(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<apple_music::Error>().is_some_and(apple_music::Error::is_auth)
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+/// Lets the user select playlists, returning them with their position in the data export. Playlists selected by name
+/// are preselected in the prompt, or used as they are when not `interactive`.
 fn select_spotify_playlists<'a>(
     term: &Term,
     playlists: &'a [SpotifyPlaylist],
     selected_names: &[String],
-) -> Result<Vec<&'a SpotifyPlaylist>> {
-    if dialoguer::console::user_attended() {
+    prompt: &str,
+    interactive: bool,
+) -> Result<Vec<(usize, &'a SpotifyPlaylist)>> {
+    if interactive {
         let playlists_checked: Vec<(&SpotifyPlaylist, bool)> = playlists
             .iter()
             .map(|p| (p, selected_names.contains(&p.name)))
@@ -58,7 +329,7 @@ fn select_spotify_playlists<'a>(
 
         let selected_playlists_indices =
             dialoguer::MultiSelect::with_theme(&ColorfulTheme::default())
-                .with_prompt("Select Spotify playlists to import to Apple Music")
+                .with_prompt(prompt)
                 .items_checked(&playlists_checked)
                 .max_length(10)
                 .interact_on(term)?;
@@ -67,27 +338,435 @@ fn select_spotify_playlists<'a>(
             .iter()
             .enumerate()
             .filter(|(i, _)| selected_playlists_indices.contains(i))
-            .map(|(_, p)| p)
             .collect())
     } else {
         Ok(playlists
             .iter()
-            .filter(|p| selected_names.contains(&p.name))
+            .enumerate()
+            .filter(|(_, p)| selected_names.contains(&p.name))
             .collect())
     }
 }
 
+struct ImportOptions {
+    /// If set, will not create playlist or add tracks
+    dry: bool,
+    /// If set, playlists are selected in a prompt
+    interactive: bool,
+    /// If set, will add missing tracks to existing playlists instead of skipping them
+    sync: bool,
+    /// If set, will list tracks in existing playlists which are not in the Spotify playlist
+    show_extras: bool,
+    /// Path to file recording import progress, if progress should be recorded
+    state_file: Option<PathBuf>,
+    /// If set, will continue the import recorded in the state file
+    resume: bool,
+    /// Path and format of the report to write, if any
+    report: Option<(PathBuf, ReportFormat)>,
+    podcasts: args::PodcastArgs,
+    local: args::LocalArgs,
+    finder: TrackFinderOptions,
+}
+
+/// Default path of the import state file, used by --resume
+const DEFAULT_STATE_FILE: &str = "import_state.json";
+
+impl ImportOptions {
+    /// Saves the import state if progress is recorded.
+    fn save_state(&self, state: &ImportState) -> Result<()> {
+        match &self.state_file {
+            Some(state_file) => state.save(state_file),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds a playlist to the report and saves it, if a report is written.
+    fn add_to_report(&self, report: &mut Report, playlist_report: PlaylistReport) -> Result<()> {
+        match &self.report {
+            Some((report_path, report_format)) => {
+                report.playlists.push(playlist_report);
+                report.save(report_path, *report_format)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+fn skip_non_track(term: &Term, item: &PlaylistItemAbstraction, reason: SkipReason) -> Result<TrackMatch> {
+    term.clear_line()?;
+    term.write_line(&format!(
+        "\t{} Skipping {:?}: {}",
+        console::style("✘").red(),
+        item,
+        match reason {
+            SkipReason::Episode => "Podcast episode",
+            _ => "Not a track",
+        }
+    ))?;
+    Ok(TrackMatch::skipped(reason))
+}
+
+/// Writes the podcast episodes found in playlists to the OPML and episode list files, if set.
+async fn export_podcasts(term: &Term, podcast_export: &PodcastExport, podcast_args: &args::PodcastArgs) -> Result<()> {
+    if podcast_args.podcast_opml.is_none() && podcast_args.podcast_episodes.is_none() {
+        return Ok(());
+    }
+
+    let shows = if podcast_args.search_apple_podcasts {
+        term.write_str(&format!(
+            "{} Looking up {} podcast show{} in Apple Podcasts... ",
+            console::style("✱").blue(),
+            podcast_export.show_names().len(),
+            plural(podcast_export.show_names().len())
+        ))?;
+        let shows = podcast_export.find_shows(&ApplePodcastsSearch::new()).await;
+        term.clear_line()?;
+        shows
+    } else {
+        Default::default()
+    };
+
+    if let Some(podcast_opml) = &podcast_args.podcast_opml {
+        podcast_export.save_opml(podcast_opml, &shows)?;
+    }
+    if let Some(podcast_episodes) = &podcast_args.podcast_episodes {
+        podcast_export.save_episodes(podcast_episodes, &shows)?;
+    }
+
+    term.write_line(&format!(
+        "{} Exported {} podcast episode{} from {} show{} ({} with RSS feed)",
+        console::style("✔").green(),
+        podcast_export.episodes.len(),
+        plural(podcast_export.episodes.len()),
+        podcast_export.show_names().len(),
+        plural(podcast_export.show_names().len()),
+        shows.values().filter(|s| s.feed_url.is_some()).count()
+    ))?;
+
+    Ok(())
+}
+
+/// Adds matched tracks which are missing from an existing Apple Music library playlist, in batches.
+async fn sync_playlist_tracks(
+    music_client: &apple_music::Client,
+    term: &Term,
+    music_playlist: &LibraryPlaylist,
+    matched: &[MatchedTrack],
+    options: &ImportOptions,
+) -> Result<()> {
+    let existing_tracks = music_client
+        .get_library_playlist_tracks(&music_playlist.id)
+        .await?
+        .all(music_client)
+        .await?;
+
+    // count catalog songs already in the playlist, so tracks appearing several times are added the right number of times
+    let mut existing_counts: HashMap<&str, usize> = HashMap::new();
+    for track in &existing_tracks {
+        if let Some(catalog_id) = track.catalog_id() {
+            *existing_counts.entry(catalog_id).or_default() += 1;
+        }
+    }
+
+    let mut missing = Vec::new();
+    for track in matched {
+        match existing_counts.get_mut(track.song_id.as_str()) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => missing.push(track.to_request_object()),
+        }
+    }
+
+    if options.show_extras {
+        for track in &existing_tracks {
+            let is_extra = match track.catalog_id().and_then(|id| existing_counts.get_mut(id)) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    true
+                }
+                Some(_) => false,
+                None => true,
+            };
+            if is_extra {
+                term.write_line(&format!(
+                    "\t{} Not in Spotify playlist: \"{} - {}\"",
+                    console::style("✱").yellow(),
+                    &track.attributes.artist_name,
+                    &track.attributes.name
+                ))?;
+            }
+        }
+    }
+
+    if options.dry {
+        term.write_line(&format!(
+            "{} Skipped adding {} missing track{} to playlist {:?} in Apple Music (--dry)",
+            console::style("✱").blue(),
+            missing.len(),
+            plural(missing.len()),
+            &music_playlist.attributes.name
+        ))?;
+        return Ok(());
+    }
+
+    for batch in missing.chunks(TRACK_BATCH_SIZE) {
+        music_client
+            .add_library_playlist_tracks(&music_playlist.id, &Objects { data: batch.to_vec() })
+            .await?;
+    }
+
+    term.write_line(&format!(
+        "{} Added {} missing track{} to playlist {:?} in Apple Music",
+        console::style("✔").green(),
+        missing.len(),
+        plural(missing.len()),
+        &music_playlist.attributes.name
+    ))?;
+
+    Ok(())
+}
+
+/// Maximum number of tracks to add to a playlist per request
+const TRACK_BATCH_SIZE: usize = 100;
+
+/// Creates the Apple Music library playlist if it has not been created yet, then adds the matched tracks which
+/// have not been added yet, in batches. Progress is saved to the state file after every batch, if set.
+async fn add_matched_tracks(
+    music_client: &apple_music::Client,
+    name: &str,
+    description: Option<&str>,
+    state: &mut ImportState,
+    state_key: &str,
+    state_file: Option<&Path>,
+) -> Result<String> {
+    loop {
+        let playlist_state = state.playlist_mut(state_key);
+        let batch = playlist_state.next_batch(TRACK_BATCH_SIZE);
+        let batch_len = batch.data.len();
+
+        match &playlist_state.apple_music_playlist_id {
+            None => {
+                let res = music_client
+                    .create_library_playlist(&LibraryPlaylistCreationRequest {
+                        attributes: LibraryPlaylistCreationRequestAttributes {
+                            name: name.to_string(),
+                            description: description.map(str::to_string),
+                        },
+                        relationships: Some(LibraryPlaylistCreationRequestRelationships {
+                            tracks: Some(batch).filter(|batch| !batch.data.is_empty()),
+                            parent: None,
+                        }),
+                    })
+                    .await?;
+                let playlist_id = res
+                    .data
+                    .first()
+                    .map(|p| p.id.clone())
+                    .ok_or(anyhow!("no playlist in response"))?;
+                playlist_state.apple_music_playlist_id = Some(playlist_id);
+            }
+            Some(_) if batch_len == 0 => break,
+            Some(playlist_id) => {
+                music_client
+                    .add_library_playlist_tracks(playlist_id, &batch)
+                    .await?
+            }
+        }
+
+        playlist_state.added += batch_len;
+        if let Some(state_file) = state_file {
+            state.save(state_file)?;
+        }
+    }
+
+    Ok(state
+        .playlist_mut(state_key)
+        .apple_music_playlist_id
+        .clone()
+        .unwrap_or_default())
+}
+
+async fn export_spotify_playlists<P: AsRef<Path>, Q: AsRef<Path>>(
+    playlist_file: P,
+    playlists: Option<Vec<String>>,
+    formats: &[PlaylistFormat],
+    output_dir: Q,
+) -> Result<()> {
+    let playlists = playlists.unwrap_or_default();
+    let term = console::Term::stdout();
+
+    let spotify_playlists = tokio::fs::read_to_string(playlist_file).await?;
+    let spotify_playlists: SpotifyPlaylists = serde_json::from_str(&spotify_playlists)?;
+
+    term.write_line(&format!(
+        "{} Loaded {} playlist{} from Spotify data export",
+        console::style("✔").green(),
+        spotify_playlists.playlists.len(),
+        plural(spotify_playlists.playlists.len())
+    ))?;
+
+    let selected_spotify_playlists = select_spotify_playlists(
+        &term,
+        &spotify_playlists.playlists,
+        &playlists,
+        "Select Spotify playlists to export",
+        dialoguer::console::user_attended(),
+    )?;
+
+    tokio::fs::create_dir_all(&output_dir).await?;
+    let mut file_names = FileNames::default();
+    for (_, playlist) in selected_spotify_playlists {
+        let playlist_file = PlaylistFile::from(playlist);
+        let file_name = file_names.unique(&playlist.name);
+        for format in formats {
+            let path = playlist_file.save(&output_dir, &file_name, *format)?;
+            term.write_line(&format!(
+                "{} Exported playlist {:?} with {} item{} to {}",
+                console::style("✔").green(),
+                &playlist.name,
+                playlist_file.entries.len(),
+                plural(playlist_file.entries.len()),
+                path.display()
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn apply_mapping<P: AsRef<Path>>(
+    config: &config::Config,
+    mapping_file: P,
+    playlists: Option<Vec<String>>,
+    dry: bool,
+) -> Result<()> {
+    let term = console::Term::stdout();
+    let music_client = config.apple_music_client()?;
+
+    let mapping = Report::load(mapping_file)?;
+    term.write_line(&format!(
+        "{} Loaded {} playlist{} from mapping file",
+        console::style("✔").green(),
+        mapping.playlists.len(),
+        plural(mapping.playlists.len())
+    ))?;
+
+    if mapping.storefront != config.apple_music_storefront {
+        term.write_line(&format!(
+            "{} Mapping file was generated for storefront {:?}, but storefront {:?} is configured. Some songs may not be available.",
+            console::style("✱").yellow(),
+            &mapping.storefront,
+            &config.apple_music_storefront
+        ))?;
+    }
+
+    term.write_str(&format!(
+        "{} Loading playlists from Apple Music... ",
+        console::style("✱").blue()
+    ))?;
+
+    let music_playlists = music_client
+        .get_library_playlists()
+        .await?
+        .all(&music_client)
+        .await?;
+
+    term.clear_line()?;
+    term.write_line(&format!(
+        "{} Loaded {} playlist{} from Apple Music",
+        console::style("✔").green(),
+        music_playlists.len(),
+        plural(music_playlists.len())
+    ))?;
+
+    for playlist in &mapping.playlists {
+        if let Some(playlists) = &playlists {
+            if !playlists.contains(&playlist.name) {
+                continue;
+            }
+        }
+
+        if music_playlists.iter().any(|p| p.attributes.name == playlist.name) {
+            term.write_line(&format!(
+                "{} Playlist {:?} already exists in Apple Music",
+                console::style("✔").green(),
+                &playlist.name
+            ))?;
+            continue;
+        }
+
+        if playlist.status == PlaylistStatus::AlreadyExists {
+            term.write_line(&format!(
+                "{} Skipping playlist {:?}: Its tracks were not matched, as it already existed in Apple Music when the report was written",
+                console::style("✘").red(),
+                &playlist.name
+            ))?;
+            continue;
+        }
+
+        // tracks are added exactly as listed, so song IDs edited by hand are used as-is
+        let tracks: Vec<MatchedTrack> = playlist
+            .tracks
+            .iter()
+            .filter_map(|t| {
+                let song_id = t.song_id.as_deref().filter(|id| !id.is_empty())?;
+                Some(MatchedTrack {
+                    track_uri: t.uri.clone(),
+                    song_id: song_id.to_string(),
+                })
+            })
+            .collect();
+        let skipped = playlist.tracks.len() - tracks.len();
+
+        if dry {
+            term.write_line(&format!(
+                "{} Skipped creating playlist {:?} with {} track{} ({} skipped) in Apple Music (--dry)",
+                console::style("✱").blue(),
+                &playlist.name,
+                tracks.len(),
+                plural(tracks.len()),
+                skipped
+            ))?;
+            continue;
+        }
+
+        let track_count = tracks.len();
+        let mut state = ImportState::default();
+        state.playlist_mut(&playlist.name).matched = tracks;
+        match add_matched_tracks(&music_client, &playlist.name, playlist.description.as_deref(), &mut state, &playlist.name, None).await {
+            Ok(playlist_id) => term.write_line(&format!(
+                "{} Created playlist {:?} with {} track{} ({} skipped) in Apple Music: https://music.apple.com/{}/library/playlist/{}",
+                console::style("✔").green(),
+                &playlist.name,
+                track_count,
+                plural(track_count),
+                skipped,
+                &config.apple_music_storefront,
+                &playlist_id
+            ))?,
+            // the tokens will not be accepted for the remaining playlists either
+            Err(e) if is_apple_music_auth_error(&e) => return Err(e),
+            Err(e) => term.write_line(&format!(
+                "{} Failed to create playlist {:?} in Apple Music: {}",
+                console::style("✘").red(),
+                &playlist.name,
+                e
+            ))?,
+        }
+    }
+
+    Ok(())
+}
+
 async fn import_spotify_playlists_to_apple_music<P: AsRef<Path>>(
     config: &config::Config,
     playlist_file: P,
     playlists: Option<Vec<String>>,
-    dry: bool,
-    min_score: f64,
-    limit: usize,
+    options: &ImportOptions,
 ) -> anyhow::Result<()> {
     let playlists = playlists.unwrap_or_default();
     let term = console::Term::stdout();
-    let music_client = apple_music::Client::new(&config.apple_music_user_token);
+    let music_client = config.apple_music_client()?;
+    let track_finder = TrackFinder::new(&music_client, &config.apple_music_storefront, &term, &options.finder);
 
     term.write_str(&format!(
         "{} Loading playlists from Spotify data export... ",
@@ -102,16 +781,16 @@ async fn import_spotify_playlists_to_apple_music<P: AsRef<Path>>(
         "{} Loaded {} playlist{} from Spotify data export",
         console::style("✔").green(),
         spotify_playlists.playlists.len(),
-        spotify_playlists
-            .playlists
-            .len()
-            .gt(&1)
-            .then_some("s")
-            .unwrap_or_default()
+        plural(spotify_playlists.playlists.len())
     ))?;
 
-    let selected_spotify_playlists =
-        select_spotify_playlists(&term, &spotify_playlists.playlists, &playlists)?;
+    let selected_spotify_playlists = select_spotify_playlists(
+        &term,
+        &spotify_playlists.playlists,
+        &playlists,
+        "Select Spotify playlists to import to Apple Music",
+        options.interactive,
+    )?;
 
     term.write_str(&format!(
         "{} Loading playlists from Apple Music... ",
@@ -126,9 +805,6 @@ async fn import_spotify_playlists_to_apple_music<P: AsRef<Path>>(
 
     for playlist in &music_playlists {
         log::debug!("Playlist {}: {}", playlist.attributes.name, playlist.id);
-        music_client
-            .get_library_playlist_tracks(&playlist.id)
-            .await?;
     }
 
     term.clear_line()?;
@@ -136,154 +812,545 @@ async fn import_spotify_playlists_to_apple_music<P: AsRef<Path>>(
         "{} Loaded {} playlist{} from Apple Music",
         console::style("✔").green(),
         music_playlists.len(),
-        music_playlists
-            .len()
-            .gt(&1)
-            .then_some("s")
-            .unwrap_or_default()
+        plural(music_playlists.len())
     ))?;
 
-    for playlist in &selected_spotify_playlists {
-        if !dry && music_playlists
+    let mut state = if let (true, Some(state_file)) = (options.resume, &options.state_file) {
+        ImportState::load(state_file)?
+    } else {
+        ImportState::default()
+    };
+    let mut report = Report::new(&config.apple_music_storefront);
+    let mut podcast_export = PodcastExport::default();
+
+    let mut local_file_names = FileNames::default();
+    let local_library = match &options.local.music_dir {
+        Some(music_dir) => {
+            term.write_str(&format!(
+                "{} Scanning local music directory... ",
+                console::style("✱").blue()
+            ))?;
+            let local_library = LocalLibrary::scan(music_dir)?;
+            term.clear_line()?;
+            term.write_line(&format!(
+                "{} Found {} audio file{} in local music directory",
+                console::style("✔").green(),
+                local_library.files.len(),
+                plural(local_library.files.len())
+            ))?;
+            Some(local_library)
+        }
+        None => None,
+    };
+
+    for (index, playlist) in selected_spotify_playlists {
+        // episodes are exported from every selected playlist, including ones skipped below
+        podcast_export.add_playlist(playlist);
+
+        let state_key = playlist_key(index, &playlist.name);
+        let playlist_state = state.playlist_mut(&state_key);
+        if playlist_state.completed {
+            term.write_line(&format!(
+                "{} Playlist {} was already imported (--resume)",
+                console::style("✔").green(),
+                playlist.name
+            ))?;
+            let mut playlist_report =
+                PlaylistReport::skipped(playlist, PlaylistStatus::AlreadyImported, playlist_state.apple_music_playlist_id.clone());
+            playlist_report.tracks = TrackReport::from_state(playlist, playlist_state);
+            options.add_to_report(&mut report, playlist_report)?;
+            continue;
+        }
+
+        // playlists created by an earlier run are continued rather than synced
+        let existing_playlist = music_playlists
             .iter()
             .find(|p| p.attributes.name == playlist.name)
-            .is_some()
-        {
+            .filter(|_| playlist_state.apple_music_playlist_id.is_none());
+
+        if let (false, false, Some(existing_playlist)) = (options.dry, options.sync, existing_playlist) {
             term.write_line(&format!(
                 "{} Playlist {} already exists in Apple Music",
                 console::style("✔").green(),
                 playlist.name
             ))?;
+            let playlist_report =
+                PlaylistReport::skipped(playlist, PlaylistStatus::AlreadyExists, Some(existing_playlist.id.clone()));
+            options.add_to_report(&mut report, playlist_report)?;
             continue;
         }
 
-        term.write_line(&format!(
-            "{} Processing playlist {:?}",
-            console::style("✱").blue(),
-            playlist.name,
-        ))?;
-
-        // [Object { id: String::from("a.1247588952"), object_type: apple_music::ObjectType::LibrarySongs }]
-        let mut music_track_objects = Vec::new();
-
-        for item in &playlist.items {
-            if let PlaylistItemAbstraction::Track(track) = item.item() {
-                let search_term = format!("{} {}", track.artist_name, track.track_name);
-
-                term.write_str(&format!(
-                    "\t{} Searching for {:?} in the Apple Music catalog... ",
-                    console::style("✱").blue(),
-                    search_term
-                ))?;
+        let processed = playlist_state.processed;
+        if processed > 0 {
+            term.write_line(&format!(
+                "{} Resuming playlist {:?} at item {} of {}",
+                console::style("✱").blue(),
+                playlist.name,
+                processed + 1,
+                playlist.items.len()
+            ))?;
+        } else {
+            term.write_line(&format!(
+                "{} Processing playlist {:?}",
+                console::style("✱").blue(),
+                playlist.name,
+            ))?;
+        }
 
-                let search_res = music_client
-                    .search_catalog(
-                        &config.apple_music_storefront,
-                        &SearchQuery {
-                            term: &search_term,
-                            types: ObjectType::Songs.as_str(),
-                            limit: Some(limit),
-                            ..Default::default()
-                        },
-                    )
-                    .await?;
+        let mut playlist_report = PlaylistReport {
+            name: playlist.name.clone(),
+            description: playlist.description.clone(),
+            status: PlaylistStatus::Matched,
+            apple_music_playlist_id: playlist_state.apple_music_playlist_id.clone(),
+            tracks: TrackReport::from_state(playlist, playlist_state),
+        };
 
-                if let Some(songs) = &search_res.results.songs {
-                    // score songs by fuzzy match of artist, album and track name
-                    let mut songs_with_score = Vec::with_capacity(songs.data.len());
-                    for song in &songs.data {
-                        let artist_score = normalized_damerau_levenshtein(&track.artist_name, &song.attributes.artist_name);
-                        let album_score = normalized_damerau_levenshtein(&track.album_name, &song.attributes.album_name);
-                        let track_score = normalized_damerau_levenshtein(&track.track_name, &song.attributes.name);
-
-                        let compound_score = artist_score + album_score + track_score;
-                        if compound_score > min_score {
-                            songs_with_score.push((song, compound_score));
+        let mut local_files = Vec::new();
+        for (index, item) in playlist.items.iter().enumerate().skip(processed) {
+            let item = item.item();
+            let track_match = match &item {
+                PlaylistItemAbstraction::Track(track) => {
+                    track_finder
+                        .find(Some(&track.track_uri), track.to_track_candidate())
+                        .await?
+                }
+                PlaylistItemAbstraction::Episode(_) => skip_non_track(&term, &item, SkipReason::Episode)?,
+                PlaylistItemAbstraction::LocalTrack(local_track) => {
+                    let parsed = local_track.parse_uri();
+                    if let (Some(local_library), Some(parsed)) = (&local_library, &parsed) {
+                        match local_library.find(&parsed.to_track_candidate(), options.finder.min_score) {
+                            Some(file) => {
+                                term.write_line(&format!(
+                                    "\t{} Found local track \"{} - {}\" in local music directory: {}",
+                                    console::style("✔").green(),
+                                    &parsed.artist_name,
+                                    &parsed.track_name,
+                                    file.path.display()
+                                ))?;
+                                local_files.push(file);
+                            }
+                            None => term.write_line(&format!(
+                                "\t{} Could not find local track \"{} - {}\" in local music directory",
+                                console::style("✘").red(),
+                                &parsed.artist_name,
+                                &parsed.track_name
+                            ))?,
                         }
                     }
-                    songs_with_score.sort_by(|(_, a_score), (_, b_score)| b_score.total_cmp(a_score));
-
-                    if let Some((song, score)) = songs_with_score.first() {
-                        music_track_objects.push(song.to_request_object());
-
-                        term.clear_line()?;
-                        term.write_line(&format!(
-                            "\t{} Found \"{} - {}\" in the Apple Music catalog (score: {:.6}): {}",
-                            console::style("✔").green(),
-                            &song.attributes.artist_name,
-                            &song.attributes.name,
-                            score,
-                            &song.attributes.url
-                        ))?;
-
-                        continue;
+
+                    match parsed {
+                        Some(parsed) if options.local.match_local_tracks => {
+                            track_finder
+                                .find(Some(&local_track.uri), parsed.to_track_candidate())
+                                .await?
+                        }
+                        _ => skip_non_track(&term, &item, SkipReason::LocalTrack)?,
                     }
                 }
+            };
 
-                term.clear_line()?;
-                term.write_line(&format!(
-                    "\t{} Skipping {:?}: Could not be found in the Apple Music catalog",
-                    console::style("✘").red(),
-                    &search_term
-                ))?;
-            } else {
-                term.clear_line()?;
-                term.write_line(&format!(
-                    "\t{} Skipping {:?}: Not a track",
-                    console::style("✘").red(),
-                    &item
-                ))?;
+            let playlist_state = state.playlist_mut(&state_key);
+            match track_match.found() {
+                Some(found) => playlist_state.matched.push(MatchedTrack {
+                    track_uri: item.uri().to_string(),
+                    song_id: found.id.clone(),
+                }),
+                None => playlist_state.unmatched.push(item.uri().to_string()),
+            }
+            playlist_state.processed = index + 1;
+            playlist_report.tracks.push(TrackReport::new(&item, &track_match));
+
+            if !options.dry {
+                options.save_state(&state)?;
             }
         }
 
-        if dry {
-            term.write_str(&format!(
+        if let Some(match_cache) = &options.finder.match_cache {
+            match_cache.save()?;
+        }
+
+        if let (Some(local_m3u8_dir), false) = (&options.local.local_m3u8_dir, local_files.is_empty()) {
+            let local_playlist = PlaylistFile {
+                title: playlist.name.clone(),
+                annotation: playlist.description.clone(),
+                entries: local_files.iter().map(|f| f.to_playlist_entry()).collect(),
+            };
+            let m3u8_path = local_playlist.save(
+                local_m3u8_dir,
+                &local_file_names.unique(&playlist.name),
+                PlaylistFormat::M3u8,
+            )?;
+            term.write_line(&format!(
+                "{} Wrote {} local track{} of playlist {:?} to {}",
+                console::style("✔").green(),
+                local_files.len(),
+                plural(local_files.len()),
+                &playlist.name,
+                m3u8_path.display()
+            ))?;
+        }
+
+        let apple_music_playlist_id = if let (true, Some(existing_playlist)) = (options.sync, existing_playlist) {
+            match sync_playlist_tracks(&music_client, &term, existing_playlist, &state.playlist_mut(&state_key).matched, options).await {
+                Ok(()) => {
+                    if !options.dry {
+                        let playlist_state = state.playlist_mut(&state_key);
+                        playlist_state.apple_music_playlist_id = Some(existing_playlist.id.clone());
+                        playlist_state.added = playlist_state.matched.len();
+                        playlist_state.completed = true;
+                        options.save_state(&state)?;
+                    }
+                    Some(existing_playlist.id.clone())
+                }
+                Err(e) if is_apple_music_auth_error(&e) => return Err(e),
+                Err(e) => {
+                    term.write_line(&format!(
+                        "{} Failed to sync playlist {:?} in Apple Music: {}",
+                        console::style("✘").red(),
+                        &playlist.name,
+                        e
+                    ))?;
+                    None
+                }
+            }
+        } else if options.dry {
+            term.write_line(&format!(
                 "{} Skipped creating playlist {:?} in Apple Music (--dry)",
                 console::style("✱").blue(),
                 &playlist.name
             ))?;
+            None
+        } else {
+            match add_matched_tracks(
+                &music_client,
+                &playlist.name,
+                playlist.description.as_deref(),
+                &mut state,
+                &state_key,
+                options.state_file.as_deref(),
+            )
+            .await
+            {
+                Ok(playlist_id) => {
+                    state.playlist_mut(&state_key).completed = true;
+                    options.save_state(&state)?;
 
-            continue;
+                    term.write_line(&format!(
+                        "{} Created playlist {:?} in Apple Music: https://music.apple.com/{}/library/playlist/{}",
+                        console::style("✔").green(),
+                        &playlist.name,
+                        &config.apple_music_storefront,
+                        &playlist_id
+                    ))?;
+                    Some(playlist_id)
+                }
+                Err(e) if is_apple_music_auth_error(&e) => return Err(e),
+                Err(e) => {
+                    term.write_line(&format!(
+                        "{} Failed to create playlist {:?} in Apple Music: {}",
+                        console::style("✘").red(),
+                        &playlist.name,
+                        e
+                    ))?;
+                    state.playlist_mut(&state_key).apple_music_playlist_id.clone()
+                }
+            }
+        };
+
+        playlist_report.apple_music_playlist_id = apple_music_playlist_id.or(playlist_report.apple_music_playlist_id);
+        options.add_to_report(&mut report, playlist_report)?;
+    }
+
+    export_podcasts(&term, &podcast_export, &options.podcasts).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock_server::{self, MockServer};
+    use crate::test_dir::TestDir;
+    use crate::track_finder::MatchMethod;
+
+    fn track(artist: &str, album: &str, name: &str, uri: &str) -> serde_json::Value {
+        json!({
+            "track": { "trackName": name, "artistName": artist, "albumName": album, "trackUri": uri },
+            "episode": null,
+            "localTrack": null,
+            "addedDate": "2023-06-01"
+        })
+    }
+
+    fn playlist(name: &str, items: Vec<serde_json::Value>) -> serde_json::Value {
+        json!({
+            "name": name,
+            "lastModifiedDate": "2023-06-01",
+            "description": null,
+            "numberOfFollowers": 0,
+            "items": items
+        })
+    }
+
+    fn import_options() -> ImportOptions {
+        ImportOptions {
+            dry: false,
+            interactive: false,
+            sync: false,
+            show_extras: false,
+            state_file: None,
+            resume: false,
+            report: None,
+            podcasts: args::PodcastArgs {
+                podcast_opml: None,
+                podcast_episodes: None,
+                search_apple_podcasts: false,
+            },
+            local: args::LocalArgs {
+                music_dir: None,
+                local_m3u8_dir: None,
+                match_local_tracks: false,
+            },
+            finder: TrackFinderOptions {
+                min_score: 0.8,
+                limit: 10,
+                isrc_resolvers: Vec::new(),
+                track_metadata: None,
+                review: None,
+                match_cache: None,
+            },
         }
+    }
 
-        let create_playlist_res = music_client
-            .create_library_playlist(&LibraryPlaylistCreationRequest {
-                attributes: LibraryPlaylistCreationRequestAttributes {
-                    name: playlist.name.clone(),
-                    description: playlist.description.clone(),
+    fn apple_music_config(server: &MockServer) -> config::Config {
+        config::Config {
+            apple_music_user_token: String::from("user-token"),
+            apple_music_api_base_url: Some(server.url()),
+            apple_music_web_base_url: Some(server.url()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn imports_spotify_playlists_to_apple_music() {
+        let server = MockServer::start(mock_server::apple_music(
+            vec![
+                mock_server::apple_music_song("1", "Rick Astley", "Whenever You Need Somebody", "Never Gonna Give You Up", "GBARL9300135"),
+                mock_server::apple_music_song("2", "a-ha", "Hunting High and Low", "Take On Me", "GBAYE8500024"),
+            ],
+            vec![mock_server::apple_music_library_playlist("p.existing", "Already imported")],
+        ))
+        .await;
+
+        let dir = TestDir::new("import");
+        let playlist_file = dir.join("Playlist1.json");
+        let state_file = dir.join("import_state.json");
+        let report_file = dir.join("report.json");
+        let episodes_file = dir.join("episodes.csv");
+        let playlists = json!({
+            "playlists": [
+                {
+                    "name": "Road trip",
+                    "lastModifiedDate": "2023-06-01",
+                    "description": "Songs for the car",
+                    "numberOfFollowers": 0,
+                    "items": [
+                        track("Rick Astley", "Whenever You Need Somebody", "Never Gonna Give You Up", "spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+                        track("Nobody", "Nowhere", "Not In The Catalog", "spotify:track:0000000000000000000000"),
+                        track("a-ha", "Hunting High and Low", "Take On Me", "spotify:track:2WfaOiMkCvy7F5fcp2zZ8L"),
+                        {
+                            "track": null,
+                            "episode": { "episodeName": "Episode 1", "showName": "A Podcast", "episodeUri": "spotify:episode:abc" },
+                            "localTrack": null,
+                            "addedDate": "2023-06-01"
+                        }
+                    ]
                 },
-                relationships: Some(LibraryPlaylistCreationRequestRelationships {
-                    tracks: Some(Objects {
-                        data: music_track_objects,
+                playlist("Already imported", vec![
+                    track("a-ha", "Hunting High and Low", "Take On Me", "spotify:track:2WfaOiMkCvy7F5fcp2zZ8L"),
+                    json!({
+                        "track": null,
+                        "episode": { "episodeName": "Episode 2", "showName": "A Podcast", "episodeUri": "spotify:episode:def" },
+                        "localTrack": null,
+                        "addedDate": "2023-06-01"
                     }),
-                    parent: None,
-                }),
-            })
-            .await;
+                ])
+            ]
+        });
+        std::fs::write(&playlist_file, playlists.to_string()).unwrap();
 
-        match create_playlist_res {
-            Ok(res) => term.write_line(&format!(
-                "{} Created playlist {:?} in Apple Music: {}",
-                console::style("✔").green(),
-                &playlist.name,
-                res
-                    .data
-                    .first()
-                    .map(|p| format!(
-                        "https://music.apple.com/{}/library/playlist/{}",
-                        &config.apple_music_storefront, &p.id
-                    ))
-                    .unwrap_or_default()
-            ))?,
+        let config = apple_music_config(&server);
+        let options = ImportOptions {
+            state_file: Some(state_file.clone()),
+            report: Some((report_file.clone(), ReportFormat::Json)),
+            podcasts: args::PodcastArgs {
+                podcast_opml: None,
+                podcast_episodes: Some(episodes_file.clone()),
+                search_apple_podcasts: false,
+            },
+            ..import_options()
+        };
+        let selected = vec![String::from("Road trip"), String::from("Already imported")];
+        import_spotify_playlists_to_apple_music(&config, &playlist_file, Some(selected), &options)
+            .await
+            .unwrap();
 
-            Err(e) => term.write_line(&format!(
-                "{} Failed to create playlist {:?} in Apple Music: {}",
-                console::style("✘").red(),
-                &playlist.name,
-                e
-            ))?,
-        }
+        let requests = server.requests();
+        let created: Vec<_> = requests
+            .iter()
+            .filter(|r| r.method == "POST" && r.path == "/v1/me/library/playlists")
+            .collect();
+        assert_eq!(created.len(), 1, "only the playlist missing from the library is created");
+        let body = created[0].json();
+        assert_eq!(body["attributes"]["name"], "Road trip");
+        assert_eq!(body["attributes"]["description"], "Songs for the car");
+        assert_eq!(
+            body["relationships"]["tracks"]["data"],
+            json!([{ "id": "1", "type": "songs" }, { "id": "2", "type": "songs" }])
+        );
+        assert!(requests
+            .iter()
+            .filter(|r| r.path.starts_with("/v1/"))
+            .all(|r| r.header("music-user-token") == Some("user-token")));
+
+        let state = ImportState::load(&state_file).unwrap();
+        let playlist_state = &state.playlists["0:Road trip"];
+        assert!(playlist_state.completed);
+        assert_eq!(playlist_state.apple_music_playlist_id.as_deref(), Some(mock_server::APPLE_MUSIC_CREATED_PLAYLIST_ID));
+        assert_eq!(
+            playlist_state.unmatched,
+            ["spotify:track:0000000000000000000000", "spotify:episode:abc"]
+        );
+
+        let report = Report::load(&report_file).unwrap();
+        assert_eq!(report.playlists.len(), 2, "skipped playlists are reported too");
+        assert_eq!(report.playlists[0].status, PlaylistStatus::Matched);
+        assert_eq!(report.playlists[0].tracks.len(), 4);
+        assert_eq!(report.playlists[1].name, "Already imported");
+        assert_eq!(report.playlists[1].status, PlaylistStatus::AlreadyExists);
+        assert_eq!(report.playlists[1].apple_music_playlist_id.as_deref(), Some("p.existing"));
+        assert_eq!(report.playlists[1].tracks.len(), 2);
+        assert_eq!(report.playlists[1].tracks[0].song_id, None);
+
+        // episodes are exported from skipped playlists too
+        let episodes = std::fs::read_to_string(&episodes_file).unwrap();
+        assert!(episodes.contains("Road trip,A Podcast,Episode 1,spotify:episode:abc"));
+        assert!(episodes.contains("Already imported,A Podcast,Episode 2,spotify:episode:def"));
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn resumes_interrupted_import() {
+        let server = MockServer::start(mock_server::apple_music(
+            vec![mock_server::apple_music_song("2", "a-ha", "Hunting High and Low", "Take On Me", "GBAYE8500024")],
+            Vec::new(),
+        ))
+        .await;
+
+        let dir = TestDir::new("resume");
+        let playlist_file = dir.join("Playlist1.json");
+        let state_file = dir.join("import_state.json");
+        let items = vec![
+            track("Rick Astley", "Whenever You Need Somebody", "Never Gonna Give You Up", "spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+            track("a-ha", "Hunting High and Low", "Take On Me", "spotify:track:2WfaOiMkCvy7F5fcp2zZ8L"),
+        ];
+        let playlists = json!({ "playlists": [playlist("Road trip", items.clone()), playlist("Road trip", items)] });
+        std::fs::write(&playlist_file, playlists.to_string()).unwrap();
+
+        // the first playlist was interrupted after its first track, the second playlist with the same name was imported
+        let mut state = ImportState::default();
+        let playlist_state = state.playlist_mut(&playlist_key(0, "Road trip"));
+        playlist_state.processed = 1;
+        playlist_state.matched.push(MatchedTrack {
+            track_uri: String::from("spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+            song_id: String::from("1"),
+        });
+        state.playlist_mut(&playlist_key(1, "Road trip")).completed = true;
+        state.save(&state_file).unwrap();
+
+        let report_file = dir.join("report.json");
+        let options = ImportOptions {
+            state_file: Some(state_file.clone()),
+            resume: true,
+            report: Some((report_file.clone(), ReportFormat::Json)),
+            ..import_options()
+        };
+        import_spotify_playlists_to_apple_music(&apple_music_config(&server), &playlist_file, Some(vec![String::from("Road trip")]), &options)
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        let searches: Vec<_> = requests.iter().filter(|r| r.path.ends_with("/search")).collect();
+        assert_eq!(searches.len(), 1, "only the track not processed yet is searched for");
+        let created: Vec<_> = requests
+            .iter()
+            .filter(|r| r.method == "POST" && r.path == "/v1/me/library/playlists")
+            .collect();
+        assert_eq!(created.len(), 1);
+        assert_eq!(
+            created[0].json()["relationships"]["tracks"]["data"],
+            json!([{ "id": "1", "type": "songs" }, { "id": "2", "type": "songs" }])
+        );
+
+        let mut state = ImportState::load(&state_file).unwrap();
+        let playlist_state = state.playlist_mut(&playlist_key(0, "Road trip"));
+        assert!(playlist_state.completed);
+        assert_eq!(playlist_state.processed, 2);
+        assert_eq!(playlist_state.added, 2);
+
+        // tracks matched by the interrupted run and completed playlists are reported too
+        let report = Report::load(&report_file).unwrap();
+        assert_eq!(report.playlists.len(), 2);
+        let tracks = &report.playlists[0].tracks;
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].song_id.as_deref(), Some("1"));
+        assert_eq!(tracks[0].method, Some(MatchMethod::Resumed));
+        assert_eq!(tracks[1].song_id.as_deref(), Some("2"));
+        assert_eq!(report.playlists[1].status, PlaylistStatus::AlreadyImported);
+    }
+
+    #[tokio::test]
+    async fn applies_mapping() {
+        let server = MockServer::start(mock_server::apple_music(
+            Vec::new(),
+            vec![mock_server::apple_music_library_playlist("p.existing", "Already imported")],
+        ))
+        .await;
+
+        let dir = TestDir::new("mapping");
+        let mapping_file = dir.join("mapping.json");
+        let track = |name: &str, song_id: Option<&str>| {
+            json!({ "uri": "spotify:track:0", "artistName": "Artist", "albumName": "Album", "trackName": name, "songId": song_id })
+        };
+        let mapping = json!({
+            "storefront": "no",
+            "generatedAt": "2023-06-01T00:00:00Z",
+            "playlists": [
+                {
+                    "name": "Road trip",
+                    "status": "matched",
+                    "tracks": [track("Matched", Some("1")), track("Left out", None), track("Resumed", Some("2"))]
+                },
+                { "name": "Skipped", "status": "already-exists", "tracks": [track("Not matched", None)] },
+                { "name": "Already imported", "status": "matched", "tracks": [track("Matched", Some("1"))] }
+            ]
+        });
+        std::fs::write(&mapping_file, mapping.to_string()).unwrap();
+
+        apply_mapping(&apple_music_config(&server), &mapping_file, None, false).await.unwrap();
+
+        let requests = server.requests();
+        let created: Vec<_> = requests
+            .iter()
+            .filter(|r| r.method == "POST" && r.path == "/v1/me/library/playlists")
+            .collect();
+        assert_eq!(created.len(), 1, "playlists which exist or were not matched are not created");
+        let body = created[0].json();
+        assert_eq!(body["attributes"]["name"], "Road trip");
+        assert_eq!(
+            body["relationships"]["tracks"]["data"],
+            json!([{ "id": "1", "type": "songs" }, { "id": "2", "type": "songs" }])
+        );
+    }
 }
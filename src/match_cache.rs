@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{Context as ErrorContext, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file;
+
+/// Number of new matches after which the cache is saved, so that an interrupted import loses little work.
+const SAVE_INTERVAL: usize = 20;
+
+/// A catalog song previously matched to a Spotify track.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedMatch {
+    pub song_id: String,
+    pub score: f64,
+    pub matched_at: DateTime<Utc>,
+    /// Informational, to make the cache file easier to read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MatchCacheFile {
+    /// Matches by storefront and Spotify track URI
+    #[serde(default)]
+    matches: BTreeMap<String, BTreeMap<String, CachedMatch>>,
+    /// Song IDs set by hand by storefront and Spotify track URI, where `null` means the track is skipped
+    #[serde(default)]
+    overrides: BTreeMap<String, BTreeMap<String, Option<String>>>,
+}
+
+pub enum CacheLookup {
+    /// Song ID set by hand, or `None` if the track should be skipped
+    Override(Option<String>),
+    Match(CachedMatch),
+}
+
+/// On-disk cache of catalog songs matched to Spotify tracks, so they don't have to be searched for again.
+#[derive(Debug)]
+pub struct MatchCache {
+    path: PathBuf,
+    file: Mutex<MatchCacheFile>,
+    /// Number of matches inserted since the cache was last saved
+    unsaved: AtomicUsize,
+}
+
+impl MatchCache {
+    pub fn load_or_init<P: AsRef<Path>>(cache_path: P) -> Result<Self> {
+        let path = cache_path.as_ref().to_path_buf();
+        let file = match File::open(&path) {
+            Ok(cache_file) => serde_json::from_reader(cache_file).context("could not parse match cache file")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => MatchCacheFile::default(),
+            Err(e) => return Err(e).context("could not open match cache file"),
+        };
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            unsaved: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let file = self.file.lock().expect("match cache lock poisoned");
+        atomic_file::write_json(&self.path, &*file).context("could not save match cache file")?;
+        self.unsaved.store(0, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn get(&self, storefront: &str, track_uri: &str) -> Option<CacheLookup> {
+        let file = self.file.lock().expect("match cache lock poisoned");
+        if let Some(song_id) = file.overrides.get(storefront).and_then(|o| o.get(track_uri)) {
+            return Some(CacheLookup::Override(song_id.clone()));
+        }
+        file.matches
+            .get(storefront)
+            .and_then(|m| m.get(track_uri))
+            .cloned()
+            .map(CacheLookup::Match)
+    }
+
+    /// Adds a match, saving the cache every [`SAVE_INTERVAL`] matches.
+    pub fn insert(&self, storefront: &str, track_uri: &str, cached_match: CachedMatch) -> Result<()> {
+        self.file
+            .lock()
+            .expect("match cache lock poisoned")
+            .matches
+            .entry(storefront.to_string())
+            .or_default()
+            .insert(track_uri.to_string(), cached_match);
+
+        if self.unsaved.fetch_add(1, Ordering::Relaxed) + 1 >= SAVE_INTERVAL {
+            self.save()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn cached_match(song_id: &str) -> CachedMatch {
+        CachedMatch {
+            song_id: song_id.to_string(),
+            score: 2.5,
+            matched_at: Utc::now(),
+            description: Some(String::from("Rick Astley - Never Gonna Give You Up")),
+            url: None,
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_matches_and_overrides() {
+        let dir = TestDir::new("match-cache");
+        let cache_path = dir.join("match_cache.json");
+        std::fs::write(
+            &cache_path,
+            r#"{ "overrides": { "no": { "spotify:track:skipped": null, "spotify:track:fixed": "42" } } }"#,
+        )
+        .unwrap();
+
+        let cache = MatchCache::load_or_init(&cache_path).unwrap();
+        cache.insert("no", "spotify:track:4uLU6hMCjMI75M1A2tKUQC", cached_match("1")).unwrap();
+        cache.save().unwrap();
+
+        let cache = MatchCache::load_or_init(&cache_path).unwrap();
+        assert!(matches!(
+            cache.get("no", "spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+            Some(CacheLookup::Match(CachedMatch { song_id, .. })) if song_id == "1"
+        ));
+        assert!(matches!(cache.get("no", "spotify:track:skipped"), Some(CacheLookup::Override(None))));
+        assert!(matches!(
+            cache.get("no", "spotify:track:fixed"),
+            Some(CacheLookup::Override(Some(song_id))) if song_id == "42"
+        ));
+        // matches are kept per storefront
+        assert!(cache.get("us", "spotify:track:4uLU6hMCjMI75M1A2tKUQC").is_none());
+    }
+
+    #[test]
+    fn saves_periodically() {
+        let dir = TestDir::new("match-cache-periodic");
+        let cache_path = dir.join("match_cache.json");
+        let cache = MatchCache::load_or_init(&cache_path).unwrap();
+        for i in 0..SAVE_INTERVAL - 1 {
+            cache.insert("no", &format!("spotify:track:{}", i), cached_match("1")).unwrap();
+        }
+        assert!(!cache_path.exists());
+
+        cache.insert("no", "spotify:track:last", cached_match("1")).unwrap();
+        let saved = MatchCache::load_or_init(&cache_path).unwrap();
+        assert!(saved.get("no", "spotify:track:last").is_some());
+    }
+}
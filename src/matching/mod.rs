@@ -0,0 +1,593 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use strsim::normalized_damerau_levenshtein;
+
+use crate::apple_music::response::{Album, Song};
+use crate::my_spotify_data::your_library::{LibraryAlbum, LibraryTrack};
+use crate::my_spotify_data::{LocalTrackUri, PlaylistTrack};
+
+mod title;
+pub use title::{normalize_title, VersionTag};
+
+/// The fields of a track used for matching, independent of where the track came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackCandidate<'a> {
+    pub artist_name: &'a str,
+    pub album_name: &'a str,
+    pub track_name: &'a str,
+    pub duration_ms: Option<u64>,
+}
+
+pub trait ToTrackCandidate {
+    fn to_track_candidate(&self) -> TrackCandidate<'_>;
+}
+
+impl ToTrackCandidate for PlaylistTrack {
+    fn to_track_candidate(&self) -> TrackCandidate<'_> {
+        TrackCandidate {
+            artist_name: &self.artist_name,
+            album_name: &self.album_name,
+            track_name: &self.track_name,
+            duration_ms: self.duration_ms,
+        }
+    }
+}
+
+impl ToTrackCandidate for LocalTrackUri {
+    fn to_track_candidate(&self) -> TrackCandidate<'_> {
+        TrackCandidate {
+            artist_name: &self.artist_name,
+            album_name: &self.album_name,
+            track_name: &self.track_name,
+            duration_ms: self.duration_ms,
+        }
+    }
+}
+
+impl ToTrackCandidate for LibraryTrack {
+    fn to_track_candidate(&self) -> TrackCandidate<'_> {
+        TrackCandidate {
+            artist_name: &self.artist,
+            album_name: &self.album,
+            track_name: &self.track,
+            duration_ms: None,
+        }
+    }
+}
+
+impl ToTrackCandidate for Song {
+    fn to_track_candidate(&self) -> TrackCandidate<'_> {
+        TrackCandidate {
+            artist_name: &self.attributes.artist_name,
+            album_name: &self.attributes.album_name,
+            track_name: &self.attributes.name,
+            duration_ms: Some(self.attributes.duration_in_millis),
+        }
+    }
+}
+
+/// The fields of an album used for matching, independent of where the album came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlbumCandidate<'a> {
+    pub artist_name: &'a str,
+    pub album_name: &'a str,
+}
+
+pub trait ToAlbumCandidate {
+    fn to_album_candidate(&self) -> AlbumCandidate<'_>;
+}
+
+impl ToAlbumCandidate for LibraryAlbum {
+    fn to_album_candidate(&self) -> AlbumCandidate<'_> {
+        AlbumCandidate {
+            artist_name: &self.artist,
+            album_name: &self.album,
+        }
+    }
+}
+
+impl ToAlbumCandidate for Album {
+    fn to_album_candidate(&self) -> AlbumCandidate<'_> {
+        AlbumCandidate {
+            artist_name: &self.attributes.artist_name,
+            album_name: &self.attributes.name,
+        }
+    }
+}
+
+/// Per-field similarity scores of a candidate, each between 0.0 and 1.0.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchResult {
+    pub artist_score: f64,
+    pub album_score: f64,
+    pub track_score: f64,
+    /// Similarity of track durations, or `None` if either duration is unknown.
+    #[serde(default)]
+    pub duration_score: Option<f64>,
+    /// Version tags (such as "live" or "remaster") present in only one of the track titles.
+    #[serde(default)]
+    pub version_mismatches: Vec<VersionTag>,
+}
+
+impl MatchResult {
+    /// Compound score (between 0.0 and 3.0).
+    ///
+    /// A difference in duration is subtracted from the score, so among candidates with the same name the one with
+    /// the closest length wins.
+    pub fn score(&self) -> f64 {
+        let duration_penalty = self.duration_score.map(|score| 1.0 - score).unwrap_or_default();
+        (self.artist_score + self.album_score + self.track_score - duration_penalty).max(0.0)
+    }
+
+    /// Whether the candidate is a different recording, such as a live version of a studio track.
+    pub fn is_different_recording(&self) -> bool {
+        self.version_mismatches.iter().any(|tag| tag.is_distinct_recording())
+    }
+
+    pub fn is_match(&self, min_score: f64) -> bool {
+        self.score() > min_score && !self.is_different_recording()
+    }
+
+    /// Score used to order candidates, penalizing minor version mismatches (such as a different remaster) so
+    /// that the closest version wins among otherwise equal candidates.
+    fn rank_score(&self) -> f64 {
+        self.score() - 0.1 * self.version_mismatches.len() as f64
+    }
+}
+
+pub trait Matcher {
+    /// Scores how well `candidate` matches the track we are looking for (`query`).
+    fn score(&self, query: &TrackCandidate, candidate: &TrackCandidate) -> MatchResult;
+}
+
+/// Difference in duration at which the duration score reaches 0.0.
+const DURATION_TOLERANCE: Duration = Duration::from_secs(30);
+
+/// Scores the similarity of two track durations, between 0.0 and 1.0.
+pub fn duration_score(a_ms: u64, b_ms: u64) -> f64 {
+    let difference = a_ms.abs_diff(b_ms) as f64;
+    1.0 - (difference / DURATION_TOLERANCE.as_millis() as f64).min(1.0)
+}
+
+/// Scores artist, album and track name by normalized Damerau-Levenshtein similarity, and duration by difference.
+///
+/// Track names are compared by their base titles (see [`normalize_title`]), with version tags compared separately.
+/// An empty album name in the query means the album is unknown, and scores as a full match.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FuzzyMatcher;
+
+impl Matcher for FuzzyMatcher {
+    fn score(&self, query: &TrackCandidate, candidate: &TrackCandidate) -> MatchResult {
+        let query_title = normalize_title(query.track_name);
+        let candidate_title = normalize_title(candidate.track_name);
+
+        MatchResult {
+            artist_score: normalized_damerau_levenshtein(query.artist_name, candidate.artist_name),
+            album_score: if query.album_name.is_empty() {
+                1.0
+            } else {
+                normalized_damerau_levenshtein(query.album_name, candidate.album_name)
+            },
+            track_score: normalized_damerau_levenshtein(
+                &query_title.base.to_lowercase(),
+                &candidate_title.base.to_lowercase(),
+            ),
+            duration_score: query
+                .duration_ms
+                .zip(candidate.duration_ms)
+                .map(|(a, b)| duration_score(a, b)),
+            version_mismatches: query_title.version.differences(&candidate_title.version),
+        }
+    }
+}
+
+/// Similarity scores of an album candidate, each between 0.0 and 1.0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlbumMatchResult {
+    pub artist_score: f64,
+    pub album_score: f64,
+}
+
+impl AlbumMatchResult {
+    /// Compound score, scaled to between 0.0 and 3.0 like track scores so the same minimum score applies.
+    pub fn score(&self) -> f64 {
+        (self.artist_score + self.album_score) * 1.5
+    }
+
+    pub fn is_match(&self, min_score: f64) -> bool {
+        self.score() > min_score
+    }
+}
+
+/// Scores artist and album name by normalized Damerau-Levenshtein similarity.
+///
+/// Unlike track titles, album titles are compared as they are: "Live", "Unplugged" or "Remastered" in an album title
+/// names the album, rather than a different recording of it.
+pub fn score_album(query: &AlbumCandidate, candidate: &AlbumCandidate) -> AlbumMatchResult {
+    AlbumMatchResult {
+        artist_score: normalized_damerau_levenshtein(query.artist_name, candidate.artist_name),
+        album_score: normalized_damerau_levenshtein(
+            &query.album_name.to_lowercase(),
+            &candidate.album_name.to_lowercase(),
+        ),
+    }
+}
+
+/// Scores all album candidates and sorts them best first, keeping search result order for equal scores.
+pub fn rank_albums<'c, T: ToAlbumCandidate>(query: &AlbumCandidate, candidates: &'c [T]) -> Vec<(&'c T, AlbumMatchResult)> {
+    let mut ranked: Vec<(&T, AlbumMatchResult)> = candidates
+        .iter()
+        .map(|candidate| (candidate, score_album(query, &candidate.to_album_candidate())))
+        .collect();
+    ranked.sort_by(|(_, a), (_, b)| b.score().total_cmp(&a.score()));
+    ranked
+}
+
+/// Scores all candidates and sorts them best first.
+///
+/// Different recordings (see [`MatchResult::is_different_recording`]) are always ranked last. Candidates with equal
+/// scores keep their original order, so search result order is used as a tie-breaker.
+pub fn rank<'c, M, T>(matcher: &M, query: &TrackCandidate, candidates: &'c [T]) -> Vec<(&'c T, MatchResult)>
+where
+    M: Matcher + ?Sized,
+    T: ToTrackCandidate,
+{
+    let mut ranked: Vec<(&T, MatchResult)> = candidates
+        .iter()
+        .map(|candidate| (candidate, matcher.score(query, &candidate.to_track_candidate())))
+        .collect();
+    ranked.sort_by(|(_, a), (_, b)| {
+        a.is_different_recording()
+            .cmp(&b.is_different_recording())
+            .then(b.rank_score().total_cmp(&a.rank_score()))
+    });
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULT_MIN_SCORE: f64 = 0.8;
+
+    fn track<'a>(artist_name: &'a str, album_name: &'a str, track_name: &'a str) -> TrackCandidate<'a> {
+        TrackCandidate {
+            artist_name,
+            album_name,
+            track_name,
+            duration_ms: None,
+        }
+    }
+
+    fn timed_track<'a>(artist_name: &'a str, album_name: &'a str, track_name: &'a str, duration_ms: u64) -> TrackCandidate<'a> {
+        TrackCandidate {
+            duration_ms: Some(duration_ms),
+            ..track(artist_name, album_name, track_name)
+        }
+    }
+
+    impl ToTrackCandidate for TrackCandidate<'_> {
+        fn to_track_candidate(&self) -> TrackCandidate<'_> {
+            *self
+        }
+    }
+
+    #[test]
+    fn identical_tracks_score_maximum() {
+        let query = track("Radiohead", "OK Computer", "Paranoid Android");
+        let result = FuzzyMatcher.score(&query, &query);
+        assert_eq!(result.artist_score, 1.0);
+        assert_eq!(result.album_score, 1.0);
+        assert_eq!(result.track_score, 1.0);
+        assert_eq!(result.score(), 3.0);
+    }
+
+    #[test]
+    fn spotify_vs_apple_music_pairs() {
+        // (Spotify track, Apple Music song, expected to match at the default minimum score)
+        let cases = [
+            (
+                track("The Killers", "Hot Fuss", "Mr. Brightside"),
+                track("The Killers", "Hot Fuss", "Mr. Brightside"),
+                true,
+            ),
+            (
+                track("Queen", "A Night At The Opera (2011 Remaster)", "Bohemian Rhapsody - Remastered 2011"),
+                track("Queen", "A Night At the Opera (2011 Remaster)", "Bohemian Rhapsody"),
+                true,
+            ),
+            (
+                track("Beyoncé", "Dangerously In Love", "Crazy In Love (feat. Jay-Z)"),
+                track("Beyoncé", "Dangerously In Love", "Crazy In Love (feat. JAY-Z)"),
+                true,
+            ),
+            (
+                track("Daft Punk", "Random Access Memories", "Get Lucky (feat. Pharrell Williams and Nile Rodgers)"),
+                track("Daft Punk", "Random Access Memories", "Get Lucky (feat. Pharrell Williams & Nile Rodgers)"),
+                true,
+            ),
+            (
+                track("Sigur Rós", "Ágætis byrjun", "Svefn-g-englar"),
+                track("Sigur Rós", "Ágætis byrjun", "Svefn-g-englar"),
+                true,
+            ),
+            (
+                track("The Beatles", "Abbey Road (Remastered)", "Come Together - Remastered 2009"),
+                track("The Beatles", "Abbey Road", "Come Together"),
+                true,
+            ),
+            (
+                track("Nirvana", "Nevermind (Remastered)", "Smells Like Teen Spirit"),
+                track("Tori Amos", "Crucify - EP", "Smells Like Teen Spirit"),
+                true,
+            ),
+            (
+                track("ABBA", "Arrival", "Dancing Queen"),
+                track("Metallica", "Master of Puppets", "Battery"),
+                false,
+            ),
+            (
+                track("a-ha", "Hunting High and Low", "Take On Me"),
+                track("Weezer", "Weezer (Teal Album)", "Sledgehammer"),
+                false,
+            ),
+        ];
+
+        for (spotify, apple_music, expected) in cases {
+            let result = FuzzyMatcher.score(&spotify, &apple_music);
+            assert_eq!(
+                result.is_match(DEFAULT_MIN_SCORE),
+                expected,
+                "{:?} vs {:?} scored {:?} ({})",
+                spotify,
+                apple_music,
+                result,
+                result.score()
+            );
+        }
+    }
+
+    #[test]
+    fn rank_picks_expected_candidate() {
+        // (Spotify track, Apple Music search results, index of expected best result)
+        let cases = [
+            (
+                track("Nirvana", "Nevermind (Remastered)", "Smells Like Teen Spirit"),
+                vec![
+                    track("Tori Amos", "Crucify - EP", "Smells Like Teen Spirit"),
+                    track("Nirvana", "Nevermind (Remastered)", "Smells Like Teen Spirit"),
+                    track("Nirvana", "Nevermind (Remastered)", "Come as You Are"),
+                ],
+                1,
+            ),
+            (
+                track("Johnny Cash", "American IV: The Man Comes Around", "Hurt"),
+                vec![
+                    track("Nine Inch Nails", "The Downward Spiral", "Hurt"),
+                    track("Johnny Cash", "American IV: The Man Comes Around", "Hurt"),
+                ],
+                1,
+            ),
+            (
+                track("Fleetwood Mac", "Rumours", "Dreams - 2004 Remaster"),
+                vec![
+                    track("Fleetwood Mac", "Rumours", "Dreams"),
+                    track("The Cranberries", "Everybody Else Is Doing It, So Why Can't We?", "Dreams"),
+                    track("Fleetwood Mac", "Rumours (Super Deluxe)", "Dreams (Take 2)"),
+                ],
+                0,
+            ),
+        ];
+
+        for (spotify, candidates, expected) in cases {
+            let ranked = rank(&FuzzyMatcher, &spotify, &candidates);
+            assert_eq!(ranked[0].0, &candidates[expected], "ranked {:?} for {:?}", ranked, spotify);
+        }
+    }
+
+    #[test]
+    fn rank_prefers_same_version() {
+        // (Spotify track, Apple Music search results, index of expected best result)
+        let cases = [
+            (
+                track("Eric Clapton", "Unplugged", "Layla - Acoustic; Live at MTV Unplugged"),
+                vec![
+                    track("Derek & The Dominos", "Layla and Other Assorted Love Songs", "Layla"),
+                    track("Eric Clapton", "Unplugged (Deluxe Edition)", "Layla (Acoustic) [Live]"),
+                ],
+                1,
+            ),
+            (
+                track("Queen", "Greatest Hits", "Bohemian Rhapsody"),
+                vec![
+                    track("Queen", "Live Killers", "Bohemian Rhapsody (Live)"),
+                    track("Queen", "Greatest Hits", "Bohemian Rhapsody"),
+                ],
+                1,
+            ),
+            (
+                track("The Beach Boys", "Pet Sounds", "Wouldn't It Be Nice - Mono / Remastered 1996"),
+                vec![
+                    track("The Beach Boys", "Pet Sounds", "Wouldn't It Be Nice (Stereo)"),
+                    track("The Beach Boys", "Pet Sounds", "Wouldn't It Be Nice (Mono) [Remastered 1996]"),
+                ],
+                1,
+            ),
+        ];
+
+        for (spotify, candidates, expected) in cases {
+            let ranked = rank(&FuzzyMatcher, &spotify, &candidates);
+            assert_eq!(ranked[0].0, &candidates[expected], "ranked {:?} for {:?}", ranked, spotify);
+        }
+    }
+
+    #[test]
+    fn rank_prefers_closest_duration() {
+        // (Spotify track, Apple Music search results, index of expected best result)
+        let cases = [
+            (
+                timed_track("Daft Punk", "Random Access Memories", "Get Lucky", 369_000),
+                vec![
+                    timed_track("Daft Punk", "Random Access Memories", "Get Lucky", 248_000),
+                    timed_track("Daft Punk", "Random Access Memories", "Get Lucky", 369_000),
+                ],
+                1,
+            ),
+            (
+                timed_track("Avicii", "True", "Levels", 199_000),
+                vec![
+                    timed_track("Avicii", "True", "Levels", 338_000),
+                    timed_track("Avicii", "True", "Levels", 200_000),
+                ],
+                1,
+            ),
+            (
+                // unknown durations don't affect the ranking
+                track("Radiohead", "OK Computer", "Karma Police"),
+                vec![
+                    timed_track("Radiohead", "OK Computer", "Karma Police", 264_000),
+                    timed_track("Radiohead", "OK Computer", "Karma Police", 400_000),
+                ],
+                0,
+            ),
+        ];
+
+        for (spotify, candidates, expected) in cases {
+            let ranked = rank(&FuzzyMatcher, &spotify, &candidates);
+            assert_eq!(ranked[0].0, &candidates[expected], "ranked {:?} for {:?}", ranked, spotify);
+        }
+    }
+
+    #[test]
+    fn duration_scores() {
+        assert_eq!(duration_score(200_000, 200_000), 1.0);
+        assert_eq!(duration_score(200_000, 215_000), 0.5);
+        assert_eq!(duration_score(215_000, 200_000), 0.5);
+        assert_eq!(duration_score(200_000, 260_000), 0.0);
+    }
+
+    #[test]
+    fn different_recording_is_never_a_match() {
+        let studio = track("Nirvana", "Nevermind", "Come as You Are");
+        let live = track("Nirvana", "Nevermind", "Come as You Are - Live");
+        let result = FuzzyMatcher.score(&studio, &live);
+        assert_eq!(result.track_score, 1.0);
+        assert!(result.is_different_recording());
+        assert!(!result.is_match(DEFAULT_MIN_SCORE));
+    }
+
+    /// (title, expected base title, expected tags, expected remaster year, expected featured artists)
+    type TitleCase = (&'static str, &'static str, &'static [VersionTag], Option<u16>, &'static [&'static str]);
+
+    #[test]
+    fn normalize_titles() {
+        let cases: [TitleCase; 14] = [
+            ("Mr. Brightside", "Mr. Brightside", &[], None, &[]),
+            ("Bohemian Rhapsody - Remastered 2011", "Bohemian Rhapsody", &[VersionTag::Remaster], Some(2011), &[]),
+            ("Dreams - 2004 Remaster", "Dreams", &[VersionTag::Remaster], Some(2004), &[]),
+            ("Let It Be (Remastered 2009)", "Let It Be", &[VersionTag::Remaster], Some(2009), &[]),
+            ("Crazy In Love (feat. Jay-Z)", "Crazy In Love", &[], None, &["Jay-Z"]),
+            ("Señorita (with Camila Cabello)", "Señorita", &[], None, &["Camila Cabello"]),
+            (
+                "Get Lucky (feat. Pharrell Williams and Nile Rodgers) - Radio Edit",
+                "Get Lucky",
+                &[VersionTag::RadioEdit],
+                None,
+                &["Pharrell Williams", "Nile Rodgers"],
+            ),
+            ("Stan ft. Dido", "Stan", &[], None, &["Dido"]),
+            ("Hotel California [Live]", "Hotel California", &[VersionTag::Live], None, &[]),
+            ("Wonderwall - Live at Knebworth, 1996", "Wonderwall", &[VersionTag::Live], None, &[]),
+            ("Layla - Acoustic; Live at MTV Unplugged", "Layla", &[VersionTag::Live, VersionTag::Acoustic], None, &[]),
+            ("God Only Knows - Mono / 1997 Remastered", "God Only Knows", &[VersionTag::Remaster, VersionTag::Mono], Some(1997), &[]),
+            ("(I Can't Get No) Satisfaction - Mono Version", "(I Can't Get No) Satisfaction", &[VersionTag::Mono], None, &[]),
+            ("Without Me [Explicit]", "Without Me", &[VersionTag::Explicit], None, &[]),
+        ];
+
+        for (title, base, tags, remaster_year, featured_artists) in cases {
+            let normalized = normalize_title(title);
+            assert_eq!(normalized.base, base, "{:?}", title);
+            assert_eq!(normalized.version.tags, tags, "{:?}", title);
+            assert_eq!(normalized.version.remaster_year, remaster_year, "{:?}", title);
+            assert_eq!(normalized.version.featured_artists, featured_artists, "{:?}", title);
+        }
+    }
+
+    #[test]
+    fn rank_keeps_search_order_on_ties() {
+        let query = track("Adele", "25", "Hello");
+        let candidates = [track("Adele", "25", "Hello"), track("Adele", "25", "Hello")];
+        let ranked = rank(&FuzzyMatcher, &query, &candidates);
+        assert!(std::ptr::eq(ranked[0].0, &candidates[0]));
+    }
+
+    fn album<'a>(artist_name: &'a str, album_name: &'a str) -> AlbumCandidate<'a> {
+        AlbumCandidate { artist_name, album_name }
+    }
+
+    impl ToAlbumCandidate for AlbumCandidate<'_> {
+        fn to_album_candidate(&self) -> AlbumCandidate<'_> {
+            *self
+        }
+    }
+
+    #[test]
+    fn identical_albums_score_maximum() {
+        let query = album("Radiohead", "OK Computer");
+        let result = score_album(&query, &query);
+        assert_eq!(result, AlbumMatchResult { artist_score: 1.0, album_score: 1.0 });
+        assert_eq!(result.score(), 3.0);
+    }
+
+    #[test]
+    fn matches_live_and_remastered_albums_by_title() {
+        // version words in album titles name the album, and are not penalized
+        let cases = [
+            (album("Nirvana", "MTV Unplugged In New York"), album("Nirvana", "MTV Unplugged in New York")),
+            (album("Queen", "Live At Wembley '86"), album("Queen", "Live At Wembley '86")),
+            (album("The Beatles", "Abbey Road (Remastered)"), album("The Beatles", "Abbey Road (Remastered)")),
+        ];
+        for (query, candidate) in cases {
+            let result = score_album(&query, &candidate);
+            assert_eq!(result.score(), 3.0, "{:?} vs {:?}", query, candidate);
+            assert!(result.is_match(DEFAULT_MIN_SCORE));
+        }
+
+        assert!(!score_album(&album("Metallica", "Master of Puppets"), &album("ABBA", "Arrival")).is_match(DEFAULT_MIN_SCORE));
+    }
+
+    #[test]
+    fn ranks_album_with_closest_title_first() {
+        let query = album("Nirvana", "MTV Unplugged In New York");
+        let candidates = [
+            album("Nirvana", "Nevermind"),
+            album("Nirvana", "MTV Unplugged In New York (25th Anniversary – Live)"),
+            album("Nirvana", "MTV Unplugged In New York"),
+            album("Nirvana", "Live at Reading"),
+        ];
+        let ranked: Vec<_> = rank_albums(&query, &candidates).into_iter().map(|(a, _)| a.album_name).collect();
+        assert_eq!(ranked[0], "MTV Unplugged In New York");
+        assert_eq!(ranked[1], "MTV Unplugged In New York (25th Anniversary – Live)");
+
+        // a studio album is not preferred over the live album it was asked for
+        let query = album("Nirvana", "Live at Reading");
+        assert_eq!(rank_albums(&query, &candidates)[0].0.album_name, "Live at Reading");
+    }
+
+    #[test]
+    fn unknown_album_scores_as_full_match() {
+        // the streaming history and some local tracks do not include the album, which should not count against a match
+        let candidate = track("a-ha", "Hunting High and Low", "Take On Me");
+        let result = FuzzyMatcher.score(&track("a-ha", "", "Take On Me"), &candidate);
+        assert_eq!(result.album_score, 1.0);
+        assert_eq!(result.score(), 3.0);
+
+        // a known album is still compared, also against candidates without an album
+        let result = FuzzyMatcher.score(&track("a-ha", "Headlines and Deadlines", "Take On Me"), &candidate);
+        assert!(result.album_score < 1.0);
+        let result = FuzzyMatcher.score(&candidate, &track("a-ha", "", "Take On Me"));
+        assert_eq!(result.album_score, 0.0);
+    }
+}
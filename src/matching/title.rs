@@ -0,0 +1,235 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// A version tag found in a track title, such as "Live" or "Radio Edit".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionTag {
+    Remaster,
+    Live,
+    Acoustic,
+    RadioEdit,
+    Mono,
+    Stereo,
+    Explicit,
+    Clean,
+}
+
+impl VersionTag {
+    /// Whether tracks that differ by this tag are different recordings (as opposed to different masters or mixes of
+    /// the same recording).
+    pub fn is_distinct_recording(&self) -> bool {
+        matches!(self, Self::Live | Self::Acoustic | Self::RadioEdit)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Remaster => "remaster",
+            Self::Live => "live",
+            Self::Acoustic => "acoustic",
+            Self::RadioEdit => "radio edit",
+            Self::Mono => "mono",
+            Self::Stereo => "stereo",
+            Self::Explicit => "explicit",
+            Self::Clean => "clean",
+        }
+    }
+}
+
+impl fmt::Display for VersionTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Version information parsed from a track title.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersionTags {
+    pub tags: Vec<VersionTag>,
+    pub remaster_year: Option<u16>,
+    pub featured_artists: Vec<String>,
+}
+
+impl VersionTags {
+    pub fn has(&self, tag: VersionTag) -> bool {
+        self.tags.contains(&tag)
+    }
+
+    fn insert(&mut self, tag: VersionTag) {
+        if !self.has(tag) {
+            self.tags.push(tag);
+        }
+    }
+
+    /// Returns the tags present in only one of `self` and `other`.
+    pub fn differences(&self, other: &Self) -> Vec<VersionTag> {
+        self.tags
+            .iter()
+            .filter(|tag| !other.has(**tag))
+            .chain(other.tags.iter().filter(|tag| !self.has(**tag)))
+            .copied()
+            .collect()
+    }
+
+    /// Parses a title segment (the contents of a bracket, or the part after " - "), returning whether it was
+    /// recognized as version information.
+    fn parse_segment(&mut self, segment: &str) -> bool {
+        let segment = segment.trim();
+        for prefix in ["feat.", "feat ", "ft.", "ft ", "featuring ", "with "] {
+            if starts_with_ignore_ascii_case(segment, prefix) {
+                self.featured_artists.extend(split_artists(&segment[prefix.len()..]));
+                return true;
+            }
+        }
+
+        let lower = segment.to_lowercase();
+        let words: Vec<&str> = lower
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect();
+        let has_word = |word: &str| words.contains(&word);
+        let has_words = |a: &str, b: &str| words.windows(2).any(|w| w[0] == a && w[1] == b);
+
+        let mut recognized = false;
+        if words.iter().any(|w| w.starts_with("remaster")) {
+            self.insert(VersionTag::Remaster);
+            self.remaster_year = self.remaster_year.or_else(|| {
+                words
+                    .iter()
+                    .filter(|w| w.len() == 4)
+                    .find_map(|w| w.parse::<u16>().ok().filter(|year| (1900..2100).contains(year)))
+            });
+            recognized = true;
+        }
+        if has_word("live") {
+            self.insert(VersionTag::Live);
+            recognized = true;
+        }
+        if has_word("acoustic") || has_word("unplugged") {
+            self.insert(VersionTag::Acoustic);
+            recognized = true;
+        }
+        if has_words("radio", "edit") || has_words("radio", "version") || has_words("radio", "mix") || has_words("single", "edit") {
+            self.insert(VersionTag::RadioEdit);
+            recognized = true;
+        }
+        if has_word("mono") {
+            self.insert(VersionTag::Mono);
+            recognized = true;
+        }
+        if has_word("stereo") {
+            self.insert(VersionTag::Stereo);
+            recognized = true;
+        }
+        if has_word("explicit") {
+            self.insert(VersionTag::Explicit);
+            recognized = true;
+        }
+        if has_word("clean") {
+            self.insert(VersionTag::Clean);
+            recognized = true;
+        }
+        recognized
+    }
+}
+
+fn starts_with_ignore_ascii_case(haystack: &str, prefix: &str) -> bool {
+    haystack
+        .as_bytes()
+        .get(..prefix.len())
+        .is_some_and(|start| start.eq_ignore_ascii_case(prefix.as_bytes()))
+}
+
+fn find_ignore_ascii_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .char_indices()
+        .find_map(|(pos, _)| starts_with_ignore_ascii_case(&haystack[pos..], needle).then_some(pos))
+}
+
+fn split_artists(artists: &str) -> impl Iterator<Item = String> + '_ {
+    artists
+        .split([',', '&'])
+        .flat_map(|artist| artist.split(" and "))
+        .map(|artist| artist.trim().to_string())
+        .filter(|artist| !artist.is_empty())
+}
+
+/// A track title split into its base title and version information.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NormalizedTitle {
+    pub base: String,
+    pub version: VersionTags,
+}
+
+/// Splits version information such as "- 2011 Remaster", "(feat. X)" and "[Live]" off a track title.
+///
+/// Bracketed or dash-separated segments that are not recognized as version information are kept in the base
+/// title, as they are usually part of the actual title (for example "(I Can't Get No) Satisfaction").
+pub fn normalize_title(title: &str) -> NormalizedTitle {
+    let mut version = VersionTags::default();
+
+    // remove recognized bracketed segments
+    let mut outside = String::with_capacity(title.len());
+    let mut inside = String::new();
+    let mut open = None;
+    let mut depth = 0;
+    for c in title.chars() {
+        match c {
+            '(' | '[' if depth == 0 => {
+                open = Some(c);
+                depth = 1;
+            }
+            '(' | '[' => {
+                depth += 1;
+                inside.push(c);
+            }
+            ')' | ']' if depth == 1 => {
+                depth = 0;
+                if !version.parse_segment(&inside) {
+                    let (open, close) = if open == Some('[') { ('[', ']') } else { ('(', ')') };
+                    outside.push(open);
+                    outside.push_str(&inside);
+                    outside.push(close);
+                }
+                inside.clear();
+            }
+            ')' | ']' if depth > 1 => {
+                depth -= 1;
+                inside.push(c);
+            }
+            _ if depth > 0 => inside.push(c),
+            _ => outside.push(c),
+        }
+    }
+    // keep unbalanced brackets as-is
+    if depth > 0 {
+        outside.push(open.unwrap_or('('));
+        outside.push_str(&inside);
+    }
+
+    // remove recognized dash-separated segments
+    let outside = outside.replace(" – ", " - ").replace(" — ", " - ");
+    let mut segments = outside.split(" - ");
+    let mut base = segments.next().unwrap_or_default().to_string();
+    for segment in segments {
+        if !version.parse_segment(segment) {
+            base.push_str(" - ");
+            base.push_str(segment);
+        }
+    }
+
+    // remove unbracketed featured artists
+    if let Some(pos) = [" feat. ", " ft. ", " featuring "]
+        .iter()
+        .find_map(|needle| find_ignore_ascii_case(&base, needle))
+    {
+        version.parse_segment(&base[pos..]);
+        base.truncate(pos);
+    }
+
+    NormalizedTitle {
+        base: base.split_whitespace().collect::<Vec<_>>().join(" "),
+        version,
+    }
+}
@@ -0,0 +1,341 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// A request received by the mock server.
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    /// Path of the request, without the query string
+    pub path: String,
+    /// Decoded query parameters, in order
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockRequest {
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap_or_default()
+    }
+}
+
+/// A response sent by the mock server.
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: serde_json::Value) -> Self {
+        Self {
+            status,
+            headers: vec![(String::from("Content-Type"), String::from("application/json"))],
+            body: body.to_string(),
+        }
+    }
+
+    pub fn text(status: u16, content_type: &str, body: &str) -> Self {
+        Self {
+            status,
+            headers: vec![(String::from("Content-Type"), content_type.to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    pub fn no_content() -> Self {
+        Self {
+            status: 204,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&MockRequest) -> MockResponse + Send + Sync;
+
+/// A minimal HTTP server for testing API clients without network access. Each request is answered by a handler
+/// function, and recorded so tests can check what was sent.
+pub struct MockServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Starts a server on a free local port, answering requests with the handler.
+    pub async fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.expect("could not bind mock server");
+        let addr = listener.local_addr().expect("mock server has no address");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let task = {
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let requests = requests.clone();
+                    let handler = handler.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = serve(stream, &requests, handler.as_ref()).await {
+                            log::debug!("Mock server: {}", e);
+                        }
+                    });
+                }
+            })
+        };
+
+        Self { addr, requests, task }
+    }
+
+    /// Base URL of the server, without a trailing slash.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Requests received so far, in order.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Developer token served by [`apple_music`], in the form it is embedded in the web player's JS.
+pub const APPLE_MUSIC_TOKEN: &str = "eyJhbGciOiJFUzI1NiIsInR5cCI6IkpXVCIsImtpZCI6IldlYlBsYXlLaWQifQ.eyJpc3MiOiJBTVBXZWJQbGF5In0.mock";
+
+/// A developer token with the given claims, in the form it is embedded in the web player's JS.
+pub fn apple_music_web_token(claims: Value) -> String {
+    format!(
+        "eyJhbGciOiJFUzI1NiIsInR5cCI6IkpXVCIsImtpZCI6IldlYlBsYXlLaWQifQ.{}.mock",
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    )
+}
+
+/// Answers requests for the HTML and JS of the web player, with the developer token embedded in the JS.
+pub fn apple_music_web_player(request: &MockRequest, token: &str) -> Option<MockResponse> {
+    match request.path.as_str() {
+        "/" => Some(MockResponse::text(
+            200,
+            "text/html",
+            r#"<html><head><script type="module" crossorigin src="/assets/index-mock.js"></script></head></html>"#,
+        )),
+        "/assets/index-mock.js" => Some(MockResponse::text(
+            200,
+            "text/javascript",
+            &format!(r#"const config={{token:"{}"}};"#, token),
+        )),
+        _ => None,
+    }
+}
+
+/// ID of the playlist created by [`apple_music`].
+pub const APPLE_MUSIC_CREATED_PLAYLIST_ID: &str = "p.created";
+
+/// A catalog song, as returned by the Apple Music API.
+pub fn apple_music_song(id: &str, artist_name: &str, album_name: &str, name: &str, isrc: &str) -> Value {
+    json!({
+        "id": id,
+        "type": "songs",
+        "href": format!("/v1/catalog/no/songs/{}", id),
+        "attributes": {
+            "albumName": album_name,
+            "artistName": artist_name,
+            "artwork": { "width": 1200, "height": 1200, "url": "https://example.com/{w}x{h}bb.jpg" },
+            "durationInMillis": 200000,
+            "hasLyrics": true,
+            "isAppleDigitalMaster": false,
+            "isrc": isrc,
+            "name": name,
+            "url": format!("https://music.apple.com/no/song/{}", id)
+        }
+    })
+}
+
+/// A library playlist, as returned by the Apple Music API.
+pub fn apple_music_library_playlist(id: &str, name: &str) -> Value {
+    json!({
+        "id": id,
+        "type": "library-playlists",
+        "href": format!("/v1/me/library/playlists/{}", id),
+        "attributes": {
+            "canEdit": true,
+            "hasCatalog": true,
+            "isPublic": false,
+            "name": name
+        }
+    })
+}
+
+/// Answers requests like the Apple Music web player and API, for a catalog of `songs` and a library of `playlists`.
+///
+/// The web player serves [`APPLE_MUSIC_TOKEN`] the way the client scrapes it. API requests without it are rejected
+/// as unauthorized, and requests without a Music-User-Token as forbidden. Searches return the songs whose name is in the search term, ISRC and ID lookups the songs with that ISRC or
+/// ID, and
+/// created playlists get the ID [`APPLE_MUSIC_CREATED_PLAYLIST_ID`].
+pub fn apple_music(songs: Vec<Value>, playlists: Vec<Value>) -> impl Fn(&MockRequest) -> MockResponse + Send + Sync {
+    move |request| {
+        if let Some(response) = apple_music_web_player(request, APPLE_MUSIC_TOKEN) {
+            return response;
+        }
+
+        if request.header("authorization") != Some(&format!("Bearer {}", APPLE_MUSIC_TOKEN)) {
+            return apple_music_error(401, "Unauthorized", "Authentication Failed");
+        }
+        if request.header("music-user-token").is_none_or(str::is_empty) {
+            return apple_music_error(403, "Forbidden", "Invalid authentication");
+        }
+
+        let segments: Vec<&str> = request.path.trim_start_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["v1", "catalog", _, "search"]) => {
+                let term = request.query_param("term").unwrap_or_default().to_lowercase();
+                let found: Vec<&Value> = songs
+                    .iter()
+                    .filter(|song| {
+                        let name = song["attributes"]["name"].as_str().unwrap_or_default();
+                        term.contains(&name.to_lowercase())
+                    })
+                    .collect();
+                MockResponse::json(
+                    200,
+                    json!({
+                        "results": { "songs": { "href": request.path, "data": found } },
+                        "meta": { "results": { "order": ["songs"], "rawOrder": ["songs"] } }
+                    }),
+                )
+            }
+            ("GET", ["v1", "catalog", _, "songs"]) => {
+                let isrc = request.query_param("filter[isrc]");
+                let ids: Vec<&str> = request.query_param("ids").map(|ids| ids.split(',').collect()).unwrap_or_default();
+                let found: Vec<&Value> = songs
+                    .iter()
+                    .filter(|song| {
+                        (isrc.is_some() && song["attributes"]["isrc"].as_str() == isrc)
+                            || song["id"].as_str().is_some_and(|id| ids.contains(&id))
+                    })
+                    .collect();
+                MockResponse::json(200, json!({ "data": found }))
+            }
+            ("GET", ["v1", "me", "library", "playlists"]) => MockResponse::json(200, json!({ "data": playlists })),
+            ("POST", ["v1", "me", "library", "playlists"]) => {
+                let name = request.json()["attributes"]["name"].as_str().unwrap_or_default().to_string();
+                MockResponse::json(
+                    201,
+                    json!({ "data": [apple_music_library_playlist(APPLE_MUSIC_CREATED_PLAYLIST_ID, &name)] }),
+                )
+            }
+            ("POST", ["v1", "me", "library", "playlists", _, "tracks"]) => MockResponse::no_content(),
+            _ => apple_music_error(404, "Resource Not Found", "Resource Not Found"),
+        }
+    }
+}
+
+pub fn apple_music_error(status: u16, title: &str, detail: &str) -> MockResponse {
+    MockResponse::json(
+        status,
+        json!({
+            "errors": [{
+                "id": "MOCK",
+                "title": title,
+                "detail": detail,
+                "status": status.to_string(),
+                "code": format!("{}00", status)
+            }]
+        }),
+    )
+}
+
+/// Reads one request from the connection and answers it. Connections are not kept alive.
+async fn serve(mut stream: TcpStream, requests: &Mutex<Vec<MockRequest>>, handler: &Handler) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    let header_end = loop {
+        let len = stream.read(&mut chunk).await?;
+        if len == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..len]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+    while buf.len() < header_end + content_length {
+        let len = stream.read(&mut chunk).await?;
+        if len == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..len]);
+    }
+
+    let url = reqwest::Url::parse(&format!("http://localhost{}", target)).expect("invalid request target");
+    let request = MockRequest {
+        method,
+        path: url.path().to_string(),
+        query: url.query_pairs().map(|(k, v)| (k.into_owned(), v.into_owned())).collect(),
+        headers,
+        body: String::from_utf8_lossy(&buf[header_end..]).into_owned(),
+    };
+    let response = handler(&request);
+    requests.lock().unwrap().push(request);
+
+    let mut head = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,259 @@
+use std::path::Path;
+
+use anyhow::{Context as ErrorContext, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use super::streaming_history::load_files;
+
+/// A stream from the extended streaming history, in `Streaming_History_Audio_*.json` (`endsong_*.json` in older exports).
+/// Fields not set for the kind of item played (track, episode) are `None`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExtendedStream {
+	/// Time the stream ended, in UTC
+	pub ts: DateTime<Utc>,
+	#[serde(default)]
+	pub platform: Option<String>,
+	pub ms_played: u64,
+	/// Country the stream was played in, as an ISO 3166 alpha-2 country code
+	#[serde(default)]
+	pub conn_country: Option<String>,
+	#[serde(default)]
+	pub master_metadata_track_name: Option<String>,
+	#[serde(default)]
+	pub master_metadata_album_artist_name: Option<String>,
+	#[serde(default)]
+	pub master_metadata_album_album_name: Option<String>,
+	#[serde(default)]
+	pub spotify_track_uri: Option<String>,
+	#[serde(default)]
+	pub episode_name: Option<String>,
+	#[serde(default)]
+	pub episode_show_name: Option<String>,
+	#[serde(default)]
+	pub spotify_episode_uri: Option<String>,
+	/// Why the stream started, such as "trackdone", "clickrow" or "fwdbtn"
+	#[serde(default)]
+	pub reason_start: Option<String>,
+	/// Why the stream ended, such as "trackdone", "endplay" or "fwdbtn"
+	#[serde(default)]
+	pub reason_end: Option<String>,
+	#[serde(default)]
+	pub shuffle: Option<bool>,
+	#[serde(default)]
+	pub skipped: Option<bool>,
+	#[serde(default)]
+	pub offline: Option<bool>,
+	#[serde(default)]
+	pub incognito_mode: Option<bool>,
+}
+
+impl ExtendedStream {
+	/// Artist and track name, if a track was played.
+	pub fn track(&self) -> Option<(&str, &str)> {
+		Some((
+			self.master_metadata_album_artist_name.as_deref()?,
+			self.master_metadata_track_name.as_deref()?,
+		))
+	}
+
+	/// Show and episode name, if a podcast episode was played.
+	pub fn episode(&self) -> Option<(&str, &str)> {
+		Some((self.episode_show_name.as_deref()?, self.episode_name.as_deref()?))
+	}
+
+	/// Whether the stream was skipped. Older exports do not set `skipped`, so streams ended with the forward button
+	/// are counted as skipped.
+	pub fn is_skipped(&self) -> bool {
+		self.skipped
+			.unwrap_or_else(|| self.reason_end.as_deref() == Some("fwdbtn"))
+	}
+
+	/// Whether the stream was played to the end.
+	pub fn is_completed(&self) -> bool {
+		self.reason_end.as_deref() == Some("trackdone")
+	}
+
+	/// Platform family, such as "Android" or "Windows", as the platform string includes OS and device versions.
+	pub fn platform_family(&self) -> &str {
+		let Some(platform) = self.platform.as_deref() else {
+			return "Unknown";
+		};
+		let lowercase = platform.to_ascii_lowercase();
+		// short names are compared by word, so that for example "Studios" is not taken for iOS
+		let has_word = |word: &str| lowercase.split(|c: char| !c.is_ascii_alphanumeric()).any(|w| w == word);
+		// web player platform strings include the OS, so check for them first
+		if lowercase.contains("web_player") || lowercase.contains("webplayer") {
+			"Web player"
+		} else if lowercase.contains("android") {
+			"Android"
+		} else if has_word("ios") || lowercase.contains("iphone") || lowercase.contains("ipad") {
+			"iOS"
+		} else if lowercase.contains("windows") {
+			"Windows"
+		} else if lowercase.contains("os x") || has_word("osx") || has_word("macos") {
+			"macOS"
+		} else if has_word("linux") {
+			"Linux"
+		} else if has_word("cast") {
+			"Cast"
+		} else {
+			platform
+		}
+	}
+}
+
+/// Extended streaming history, ordered by end time.
+#[derive(Debug, Clone, Default)]
+pub struct ExtendedStreamingHistory {
+	pub streams: Vec<ExtendedStream>,
+}
+
+impl ExtendedStreamingHistory {
+	/// Loads and merges all `Streaming_History_Audio_*.json` and `endsong_*.json` files in the directory.
+	pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+		let mut paths = Vec::new();
+		for entry in std::fs::read_dir(&dir).context("could not read Spotify data directory")? {
+			let path = entry?.path();
+			let is_part = path
+				.file_name()
+				.and_then(|n| n.to_str())
+				.is_some_and(|n| {
+					(n.starts_with("Streaming_History_Audio_") || n.starts_with("endsong_")) && n.ends_with(".json")
+				});
+			if is_part {
+				paths.push(path);
+			}
+		}
+		paths.sort();
+
+		let mut streams: Vec<ExtendedStream> = load_files(&paths)?;
+		streams.sort_by_key(|s| s.ts);
+		Ok(Self { streams })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::TimeZone;
+
+	use super::*;
+	use crate::test_dir::TestDir;
+
+	#[test]
+	fn loads_current_and_older_exports() {
+		let dir = TestDir::new("extended-history");
+		std::fs::write(
+			dir.join("Streaming_History_Audio_2023_1.json"),
+			r#"[
+				{
+					"ts": "2023-06-01T12:00:00Z",
+					"platform": "android",
+					"ms_played": 225000,
+					"conn_country": "NO",
+					"ip_addr": "192.0.2.1",
+					"master_metadata_track_name": "Take On Me",
+					"master_metadata_album_artist_name": "a-ha",
+					"master_metadata_album_album_name": "Hunting High and Low",
+					"spotify_track_uri": "spotify:track:2WfaOiMkCvy7F5fcp2zZ8L",
+					"episode_name": null,
+					"episode_show_name": null,
+					"spotify_episode_uri": null,
+					"reason_start": "clickrow",
+					"reason_end": "trackdone",
+					"shuffle": true,
+					"skipped": false,
+					"offline": false,
+					"offline_timestamp": 1685620575,
+					"incognito_mode": false
+				},
+				{
+					"ts": "2023-06-01T13:00:00Z",
+					"platform": "ios",
+					"ms_played": 60000,
+					"master_metadata_track_name": null,
+					"master_metadata_album_artist_name": null,
+					"master_metadata_album_album_name": null,
+					"spotify_track_uri": null,
+					"episode_name": "Episode 1",
+					"episode_show_name": "A Podcast",
+					"reason_end": "endplay",
+					"skipped": true
+				}
+			]"#,
+		)
+		.unwrap();
+		// older exports name the files endsong_*.json, and set neither `skipped` nor `offline`
+		std::fs::write(
+			dir.join("endsong_0.json"),
+			r#"[{
+				"ts": "2019-01-01T00:00:00Z",
+				"username": "user",
+				"platform": "OS X 10.14.1 [x86 8]",
+				"ms_played": 5000,
+				"conn_country": "SE",
+				"user_agent_decrypted": "unknown",
+				"master_metadata_track_name": "Never Gonna Give You Up",
+				"master_metadata_album_artist_name": "Rick Astley",
+				"master_metadata_album_album_name": "Whenever You Need Somebody",
+				"spotify_track_uri": "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+				"reason_start": "clickrow",
+				"reason_end": "fwdbtn",
+				"shuffle": false,
+				"skipped": null,
+				"offline": false
+			}]"#,
+		)
+		.unwrap();
+		std::fs::write(dir.join("Streaming_History_Video_2023.json"), "[]").unwrap();
+
+		let history = ExtendedStreamingHistory::load(&dir).unwrap();
+		assert_eq!(history.streams.len(), 3);
+
+		let [older, track, episode] = &history.streams[..] else {
+			unreachable!()
+		};
+		assert_eq!(older.ts, Utc.with_ymd_and_hms(2019, 1, 1, 0, 0, 0).unwrap());
+		assert_eq!(older.track(), Some(("Rick Astley", "Never Gonna Give You Up")));
+		assert!(older.is_skipped(), "streams ended with the forward button are skipped in older exports");
+		assert!(!older.is_completed());
+		assert_eq!(older.platform_family(), "macOS");
+
+		assert_eq!(track.track(), Some(("a-ha", "Take On Me")));
+		assert_eq!(track.episode(), None);
+		assert_eq!(track.conn_country.as_deref(), Some("NO"));
+		assert_eq!(track.shuffle, Some(true));
+		assert!(!track.is_skipped());
+		assert!(track.is_completed());
+
+		assert_eq!(episode.track(), None);
+		assert_eq!(episode.episode(), Some(("A Podcast", "Episode 1")));
+		assert!(episode.is_skipped());
+		assert_eq!(episode.platform_family(), "iOS");
+	}
+
+	#[test]
+	fn groups_platforms_by_family() {
+		let platform_family = |platform: Option<&str>| {
+			let stream: ExtendedStream = serde_json::from_value(serde_json::json!({
+				"ts": "2023-06-01T12:00:00Z",
+				"platform": platform,
+				"ms_played": 0
+			}))
+			.unwrap();
+			stream.platform_family().to_string()
+		};
+		assert_eq!(platform_family(None), "Unknown");
+		assert_eq!(platform_family(Some("Android OS 13 API 33 (samsung, SM-G991B)")), "Android");
+		assert_eq!(platform_family(Some("iOS 16.1.1 (iPhone14,5)")), "iOS");
+		assert_eq!(platform_family(Some("Windows 10 (10.0.19045; x64)")), "Windows");
+		assert_eq!(platform_family(Some("OS X 10.15.7 [x86 8]")), "macOS");
+		assert_eq!(platform_family(Some("Linux [x86-0]")), "Linux");
+		assert_eq!(platform_family(Some("web_player windows 10;chrome 108.0.0.0;desktop")), "Web player");
+		assert_eq!(platform_family(Some("WebPlayer (websocket RFC6455)")), "Web player");
+		assert_eq!(platform_family(Some("Partner google cast_tv;Chromecast")), "Cast");
+		// unknown platforms, and words merely containing a known name, are kept as they are
+		assert_eq!(platform_family(Some("Partner sonos_one Sonos One SL")), "Partner sonos_one Sonos One SL");
+		assert_eq!(platform_family(Some("Partner Bose Studios")), "Partner Bose Studios");
+	}
+}
@@ -3,6 +3,10 @@ use std::fmt;
 use chrono::NaiveDate;
 use serde::Deserialize;
 
+pub mod extended_streaming_history;
+pub mod streaming_history;
+pub mod your_library;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Playlists {
 	pub playlists: Vec<Playlist>,
@@ -15,6 +19,9 @@ pub struct PlaylistTrack {
 	pub artist_name: String,
 	pub album_name: String,
 	pub track_uri: String,
+	/// Only present in extended exports, not in the standard GDPR data dump
+	#[serde(default)]
+	pub duration_ms: Option<u64>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -30,6 +37,56 @@ pub struct PlaylistLocalTrack {
 	pub uri: String,
 }
 
+/// Fields of a `spotify:local:artist:album:title:duration` URI, where the duration is in seconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalTrackUri {
+	pub artist_name: String,
+	pub album_name: String,
+	pub track_name: String,
+	pub duration_ms: Option<u64>,
+}
+
+/// Decodes a URI component, where spaces are encoded as `+`.
+fn decode_uri_component(s: &str) -> String {
+	let input = s.as_bytes();
+	let mut bytes = Vec::with_capacity(input.len());
+	let mut i = 0;
+	while i < input.len() {
+		match input[i] {
+			b'+' => bytes.push(b' '),
+			b'%' if input.get(i + 1..i + 3).is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit)) => {
+				bytes.push(u8::from_str_radix(&s[i + 1..i + 3], 16).unwrap_or_default());
+				i += 2;
+			}
+			// malformed escapes are kept as they are
+			b => bytes.push(b),
+		}
+		i += 1;
+	}
+	String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl PlaylistLocalTrack {
+	/// Parses the artist, album, title and duration from the URI. Fields may be empty if they were not tagged.
+	pub fn parse_uri(&self) -> Option<LocalTrackUri> {
+		let mut fields = self.uri.strip_prefix("spotify:local:")?.split(':');
+		let artist_name = decode_uri_component(fields.next()?);
+		let album_name = decode_uri_component(fields.next()?);
+		let track_name = decode_uri_component(fields.next()?);
+		let duration_ms = fields
+			.next()
+			.and_then(|d| d.parse::<u64>().ok())
+			.filter(|d| *d > 0)
+			.map(|d| d * 1000);
+		Some(LocalTrackUri {
+			artist_name,
+			album_name,
+			track_name,
+			duration_ms,
+		})
+	}
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(untagged, rename_all = "camelCase")]
 pub enum PlaylistItemInner {
@@ -52,6 +109,16 @@ pub enum PlaylistItemAbstraction<'a> {
 	LocalTrack(&'a PlaylistLocalTrack),
 }
 
+impl PlaylistItemAbstraction<'_> {
+	pub fn uri(&self) -> &str {
+		match self {
+			Self::Track(track) => &track.track_uri,
+			Self::Episode(episode) => &episode.episode_uri,
+			Self::LocalTrack(local_track) => &local_track.uri,
+		}
+	}
+}
+
 impl<'a> From<&'a PlaylistItemInner> for PlaylistItemAbstraction<'a> {
 	fn from(value: &'a PlaylistItemInner) -> Self {
 		match value {
@@ -71,7 +138,7 @@ pub struct PlaylistItem {
 }
 
 impl PlaylistItem {
-	pub fn item(&self) -> PlaylistItemAbstraction {
+	pub fn item(&self) -> PlaylistItemAbstraction<'_> {
 		(&self.item).into()
 	}
 }
@@ -90,4 +157,55 @@ impl fmt::Display for Playlist {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		f.write_str(&self.name)
 	}
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn local_track(uri: &str) -> PlaylistLocalTrack {
+		PlaylistLocalTrack { uri: uri.to_string() }
+	}
+
+	#[test]
+	fn decodes_uri_components() {
+		assert_eq!(decode_uri_component("Rick+Astley"), "Rick Astley");
+		assert_eq!(decode_uri_component("AC%2FDC+%2B+friends"), "AC/DC + friends");
+		assert_eq!(decode_uri_component("Bj%C3%B6rk"), "Björk");
+		assert_eq!(decode_uri_component("%E3%83%A8%E3%83%AB%E3%82%B7%E3%82%AB"), "ヨルシカ");
+		assert_eq!(decode_uri_component("Sigur+Rós"), "Sigur Rós");
+	}
+
+	#[test]
+	fn keeps_malformed_escapes() {
+		assert_eq!(decode_uri_component("100%"), "100%");
+		assert_eq!(decode_uri_component("100%2"), "100%2");
+		assert_eq!(decode_uri_component("%zz+top"), "%zz top");
+		assert_eq!(decode_uri_component("%+1"), "% 1");
+		// invalid UTF-8 is replaced rather than dropped
+		assert_eq!(decode_uri_component("a%FFb"), "a\u{fffd}b");
+	}
+
+	#[test]
+	fn parses_local_track_uris() {
+		assert_eq!(
+			local_track("spotify:local:Bj%C3%B6rk:Debut:Human+Behaviour:252").parse_uri(),
+			Some(LocalTrackUri {
+				artist_name: String::from("Björk"),
+				album_name: String::from("Debut"),
+				track_name: String::from("Human Behaviour"),
+				duration_ms: Some(252_000),
+			})
+		);
+		assert_eq!(
+			local_track("spotify:local:::Untitled+%231:0").parse_uri(),
+			Some(LocalTrackUri {
+				artist_name: String::new(),
+				album_name: String::new(),
+				track_name: String::from("Untitled #1"),
+				duration_ms: None,
+			})
+		);
+		assert_eq!(local_track("spotify:local:Artist:Album").parse_uri(), None);
+		assert_eq!(local_track("spotify:track:4uLU6hMCjMI75M1A2tKUQC").parse_uri(), None);
+	}
+}
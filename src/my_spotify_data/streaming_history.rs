@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as ErrorContext, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+
+/// Deserializes `endTime` values such as "2023-01-31 23:59", which are in UTC.
+fn deserialize_end_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+	let s = String::deserialize(deserializer)?;
+	NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M")
+		.map(|t| t.and_utc())
+		.map_err(serde::de::Error::custom)
+}
+
+/// A track played, from `StreamingHistory_music_*.json` (`StreamingHistory*.json` in older exports).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MusicStream {
+	#[serde(deserialize_with = "deserialize_end_time")]
+	pub end_time: DateTime<Utc>,
+	pub artist_name: String,
+	pub track_name: String,
+	pub ms_played: u64,
+}
+
+/// A podcast episode played, from `StreamingHistory_podcast_*.json`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodcastStream {
+	#[serde(deserialize_with = "deserialize_end_time")]
+	pub end_time: DateTime<Utc>,
+	pub podcast_name: String,
+	pub episode_name: String,
+	pub ms_played: u64,
+}
+
+/// Listening history from the streaming history files of a Spotify GDPR data dump, ordered by end time.
+#[derive(Debug, Clone, Default)]
+pub struct StreamingHistory {
+	pub music: Vec<MusicStream>,
+	pub podcasts: Vec<PodcastStream>,
+}
+
+impl StreamingHistory {
+	/// Loads and merges all numbered streaming history files in the MyData directory.
+	pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self> {
+		let mut music: Vec<MusicStream> = load_parts(&dir, &["StreamingHistory_music_", "StreamingHistory"])?;
+		let mut podcasts: Vec<PodcastStream> = load_parts(&dir, &["StreamingHistory_podcast_"])?;
+		music.sort_by_key(|s| s.end_time);
+		podcasts.sort_by_key(|s| s.end_time);
+		Ok(Self { music, podcasts })
+	}
+}
+
+/// Paths of files named `{prefix}{n}.json` in `dir`, ordered by `n`.
+fn numbered_parts<P: AsRef<Path>>(dir: P, prefixes: &[&str]) -> Result<Vec<PathBuf>> {
+	let mut parts = Vec::new();
+	for entry in std::fs::read_dir(&dir).context("could not read Spotify data directory")? {
+		let path = entry?.path();
+		let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+			continue;
+		};
+		let number = prefixes.iter().find_map(|prefix| {
+			file_name
+				.strip_prefix(prefix)
+				.and_then(|rest| rest.strip_suffix(".json"))
+				.and_then(|n| n.parse::<usize>().ok())
+		});
+		if let Some(number) = number {
+			parts.push((number, path));
+		}
+	}
+	parts.sort();
+	Ok(parts.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Loads and concatenates the JSON arrays in all files named `{prefix}{n}.json` in `dir`.
+fn load_parts<T: DeserializeOwned, P: AsRef<Path>>(dir: P, prefixes: &[&str]) -> Result<Vec<T>> {
+	load_files(&numbered_parts(dir, prefixes)?)
+}
+
+/// Loads and concatenates the JSON arrays in the files.
+pub(super) fn load_files<T: DeserializeOwned>(paths: &[PathBuf]) -> Result<Vec<T>> {
+	let mut items = Vec::new();
+	for path in paths {
+		let part_file = File::open(path).with_context(|| format!("could not open {}", path.display()))?;
+		let part: Vec<T> =
+			serde_json::from_reader(part_file).with_context(|| format!("could not parse {}", path.display()))?;
+		items.extend(part);
+	}
+	Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::TimeZone;
+
+	use super::*;
+	use crate::test_dir::TestDir;
+
+	#[test]
+	fn loads_numbered_parts_in_order() {
+		let dir = TestDir::new("streaming-history");
+		let music_stream = |end_time: &str, track_name: &str| {
+			format!(
+				r#"{{ "endTime": "{}", "artistName": "a-ha", "trackName": "{}", "msPlayed": 225000 }}"#,
+				end_time, track_name
+			)
+		};
+		std::fs::write(dir.join("StreamingHistory_music_1.json"), format!("[{}]", music_stream("2023-02-01 08:30", "Take On Me"))).unwrap();
+		std::fs::write(dir.join("StreamingHistory_music_0.json"), format!("[{}]", music_stream("2023-01-31 23:59", "The Sun Always Shines on T.V."))).unwrap();
+		// older exports name the parts without "_music_"
+		std::fs::write(dir.join("StreamingHistory2.json"), format!("[{}]", music_stream("2023-01-01 00:00", "Hunting High and Low"))).unwrap();
+		std::fs::write(
+			dir.join("StreamingHistory_podcast_0.json"),
+			r#"[{ "endTime": "2023-01-15 12:00", "podcastName": "A Podcast", "episodeName": "Episode 1", "msPlayed": 1800000 }]"#,
+		)
+		.unwrap();
+		std::fs::write(dir.join("StreamingHistory_music_notes.json"), "not JSON").unwrap();
+
+		let history = StreamingHistory::load(&dir).unwrap();
+		let track_names: Vec<_> = history.music.iter().map(|s| s.track_name.as_str()).collect();
+		assert_eq!(track_names, ["Hunting High and Low", "The Sun Always Shines on T.V.", "Take On Me"]);
+		assert_eq!(history.music[1].end_time, Utc.with_ymd_and_hms(2023, 1, 31, 23, 59, 0).unwrap());
+		assert_eq!(history.music[1].ms_played, 225000);
+		assert_eq!(history.podcasts.len(), 1);
+		assert_eq!(history.podcasts[0].podcast_name, "A Podcast");
+		assert_eq!(history.podcasts[0].episode_name, "Episode 1");
+	}
+
+	#[test]
+	fn rejects_invalid_end_times() {
+		let json = r#"[{ "endTime": "2023-01-31T23:59:00Z", "artistName": "a-ha", "trackName": "Take On Me", "msPlayed": 1 }]"#;
+		assert!(serde_json::from_str::<Vec<MusicStream>>(json).is_err());
+	}
+}
@@ -0,0 +1,62 @@
+use serde::Deserialize;
+
+/// A liked song, from `YourLibrary.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LibraryTrack {
+	pub artist: String,
+	pub album: String,
+	pub track: String,
+	pub uri: String,
+}
+
+/// A saved album, from `YourLibrary.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LibraryAlbum {
+	pub artist: String,
+	pub album: String,
+	pub uri: String,
+}
+
+/// A followed artist, from `YourLibrary.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LibraryArtist {
+	pub name: String,
+	pub uri: String,
+}
+
+/// A saved podcast show, from `YourLibrary.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LibraryShow {
+	pub name: String,
+	pub publisher: String,
+	pub uri: String,
+}
+
+/// A saved podcast episode, from `YourLibrary.json`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LibraryEpisode {
+	pub name: String,
+	pub show: String,
+	pub uri: String,
+}
+
+/// Saved items from `YourLibrary.json` in a Spotify GDPR data dump.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YourLibrary {
+	/// Liked songs
+	#[serde(default)]
+	pub tracks: Vec<LibraryTrack>,
+	#[serde(default)]
+	pub albums: Vec<LibraryAlbum>,
+	#[serde(default)]
+	pub artists: Vec<LibraryArtist>,
+	#[serde(default)]
+	pub shows: Vec<LibraryShow>,
+	#[serde(default)]
+	pub episodes: Vec<LibraryEpisode>,
+	#[serde(default)]
+	pub banned_tracks: Vec<LibraryTrack>,
+	#[serde(default)]
+	pub banned_artists: Vec<LibraryArtist>,
+}
@@ -0,0 +1,409 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as ErrorContext, Result};
+use serde::Serialize;
+
+use crate::my_spotify_data::{Playlist as SpotifyPlaylist, PlaylistItemAbstraction};
+
+/// Playlist file formats which can be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PlaylistFormat {
+    /// Extended M3U, UTF-8 encoded
+    M3u8,
+    /// XML Shareable Playlist Format
+    Xspf,
+    /// JSON Shareable Playlist Format, as used by ListenBrainz
+    Jspf,
+}
+
+impl PlaylistFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            Self::M3u8 => "m3u8",
+            Self::Xspf => "xspf",
+            Self::Jspf => "jspf",
+        }
+    }
+}
+
+/// A track in a playlist file.
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistFileEntry {
+    pub title: String,
+    /// Artist name, or show name for podcast episodes
+    pub creator: String,
+    pub album: Option<String>,
+    pub duration_ms: Option<u64>,
+    /// URI or path of the track
+    pub location: Option<String>,
+    /// Web page identifying the track
+    pub identifier: Option<String>,
+}
+
+/// A playlist, independent of the file format it is written in.
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistFile {
+    pub title: String,
+    pub annotation: Option<String>,
+    pub entries: Vec<PlaylistFileEntry>,
+}
+
+#[derive(Serialize)]
+struct JspfTrack<'a> {
+    title: &'a str,
+    creator: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    album: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    location: Vec<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    identifier: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct JspfPlaylist<'a> {
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotation: Option<&'a str>,
+    track: Vec<JspfTrack<'a>>,
+}
+
+#[derive(Serialize)]
+struct Jspf<'a> {
+    playlist: JspfPlaylist<'a>,
+}
+
+impl PlaylistFile {
+    pub fn write<W: Write>(&self, writer: W, format: PlaylistFormat) -> Result<()> {
+        match format {
+            PlaylistFormat::M3u8 => self.write_m3u8(writer),
+            PlaylistFormat::Xspf => self.write_xspf(writer),
+            PlaylistFormat::Jspf => self.write_jspf(writer),
+        }
+    }
+
+    /// Writes the playlist to `{dir}/{name}.{extension}`, returning the path written to.
+    pub fn save<P: AsRef<Path>>(&self, dir: P, name: &str, format: PlaylistFormat) -> Result<PathBuf> {
+        let path = dir.as_ref().join(format!("{}.{}", name, format.extension()));
+        let playlist_file = File::create(&path).with_context(|| format!("could not create {}", path.display()))?;
+        self.write(playlist_file, format)?;
+        Ok(path)
+    }
+
+    /// Writes extended M3U, with the duration, artist and title of each entry. Entries without a location are left
+    /// out, as M3U players can not play them.
+    pub fn write_m3u8<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "#EXTM3U")?;
+        writeln!(writer, "#PLAYLIST:{}", single_line(&self.title))?;
+        for entry in &self.entries {
+            let Some(location) = &entry.location else {
+                continue;
+            };
+            // -1 means the duration is unknown
+            let duration = entry.duration_ms.map(|ms| (ms / 1000) as i64).unwrap_or(-1);
+            writeln!(
+                writer,
+                "#EXTINF:{},{} - {}",
+                duration,
+                single_line(&entry.creator),
+                single_line(&entry.title)
+            )?;
+            if let Some(album) = &entry.album {
+                writeln!(writer, "#EXTALB:{}", single_line(album))?;
+            }
+            writeln!(writer, "{}", location)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_xspf<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, r#"<playlist version="1" xmlns="http://xspf.org/ns/0/">"#)?;
+        writeln!(writer, "  <title>{}</title>", escape_xml(&self.title))?;
+        if let Some(annotation) = &self.annotation {
+            writeln!(writer, "  <annotation>{}</annotation>", escape_xml(annotation))?;
+        }
+        writeln!(writer, "  <trackList>")?;
+        for entry in &self.entries {
+            writeln!(writer, "    <track>")?;
+            if let Some(location) = &entry.location {
+                writeln!(writer, "      <location>{}</location>", escape_xml(location))?;
+            }
+            if let Some(identifier) = &entry.identifier {
+                writeln!(writer, "      <identifier>{}</identifier>", escape_xml(identifier))?;
+            }
+            writeln!(writer, "      <title>{}</title>", escape_xml(&entry.title))?;
+            writeln!(writer, "      <creator>{}</creator>", escape_xml(&entry.creator))?;
+            if let Some(album) = &entry.album {
+                writeln!(writer, "      <album>{}</album>", escape_xml(album))?;
+            }
+            if let Some(duration_ms) = entry.duration_ms {
+                writeln!(writer, "      <duration>{}</duration>", duration_ms)?;
+            }
+            writeln!(writer, "    </track>")?;
+        }
+        writeln!(writer, "  </trackList>")?;
+        writeln!(writer, "</playlist>")?;
+        Ok(())
+    }
+
+    pub fn write_jspf<W: Write>(&self, writer: W) -> Result<()> {
+        let jspf = Jspf {
+            playlist: JspfPlaylist {
+                title: &self.title,
+                annotation: self.annotation.as_deref(),
+                track: self
+                    .entries
+                    .iter()
+                    .map(|entry| JspfTrack {
+                        title: &entry.title,
+                        creator: &entry.creator,
+                        album: entry.album.as_deref(),
+                        duration: entry.duration_ms,
+                        location: entry.location.iter().map(String::as_str).collect(),
+                        identifier: entry.identifier.iter().map(String::as_str).collect(),
+                    })
+                    .collect(),
+            },
+        };
+        Ok(serde_json::to_writer_pretty(writer, &jspf)?)
+    }
+}
+
+/// Web page of a Spotify track or episode, from its URI.
+fn spotify_web_url(uri: &str) -> Option<String> {
+    let mut parts = uri.strip_prefix("spotify:")?.splitn(2, ':');
+    match (parts.next()?, parts.next()?) {
+        (kind @ ("track" | "episode"), id) => Some(format!("https://open.spotify.com/{}/{}", kind, id)),
+        _ => None,
+    }
+}
+
+impl From<&SpotifyPlaylist> for PlaylistFile {
+    fn from(playlist: &SpotifyPlaylist) -> Self {
+        let entries = playlist
+            .items
+            .iter()
+            .map(|item| match item.item() {
+                PlaylistItemAbstraction::Track(track) => PlaylistFileEntry {
+                    title: track.track_name.clone(),
+                    creator: track.artist_name.clone(),
+                    album: Some(track.album_name.clone()),
+                    duration_ms: track.duration_ms,
+                    location: Some(track.track_uri.clone()),
+                    identifier: spotify_web_url(&track.track_uri),
+                },
+                PlaylistItemAbstraction::Episode(episode) => PlaylistFileEntry {
+                    title: episode.episode_name.clone(),
+                    creator: episode.show_name.clone(),
+                    album: None,
+                    duration_ms: None,
+                    location: Some(episode.episode_uri.clone()),
+                    identifier: spotify_web_url(&episode.episode_uri),
+                },
+                PlaylistItemAbstraction::LocalTrack(local_track) => {
+                    let parsed = local_track.parse_uri();
+                    PlaylistFileEntry {
+                        title: parsed.as_ref().map(|p| p.track_name.clone()).unwrap_or_default(),
+                        creator: parsed.as_ref().map(|p| p.artist_name.clone()).unwrap_or_default(),
+                        album: parsed.as_ref().map(|p| p.album_name.clone()).filter(|a| !a.is_empty()),
+                        duration_ms: parsed.as_ref().and_then(|p| p.duration_ms),
+                        location: Some(local_track.uri.clone()),
+                        identifier: None,
+                    }
+                }
+            })
+            .collect();
+
+        Self {
+            title: playlist.name.clone(),
+            annotation: playlist.description.clone().filter(|d| !d.is_empty()),
+            entries,
+        }
+    }
+}
+
+/// Replaces line breaks, which would end an M3U directive.
+fn single_line(s: &str) -> String {
+    s.replace(['\r', '\n'], " ")
+}
+
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Replaces characters which are not allowed in file names on common file systems.
+pub fn safe_file_name(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // names such as "." or ".." would refer to directories, and trailing dots and spaces are not allowed on Windows
+    let safe = safe.trim_end_matches(['.', ' ']).trim_start();
+    if safe.is_empty() {
+        String::from("_")
+    } else {
+        safe.to_string()
+    }
+}
+
+/// Generates safe file names which are unique, even if playlists have the same name or differ only in characters
+/// which are replaced.
+#[derive(Debug, Default)]
+pub struct FileNames {
+    used: HashSet<String>,
+}
+
+impl FileNames {
+    pub fn unique(&mut self, name: &str) -> String {
+        let safe = safe_file_name(name);
+        let mut unique = safe.clone();
+        let mut n = 2;
+        // file systems may be case-insensitive
+        while !self.used.insert(unique.to_lowercase()) {
+            unique = format!("{} ({})", safe, n);
+            n += 1;
+        }
+        unique
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist() -> PlaylistFile {
+        PlaylistFile {
+            title: String::from("Rock & <Roll>"),
+            annotation: Some(String::from("\"Best\" of\n2023")),
+            entries: vec![
+                PlaylistFileEntry {
+                    title: String::from("Back in Black"),
+                    creator: String::from("AC/DC"),
+                    album: Some(String::from("Back in Black")),
+                    duration_ms: Some(255_000),
+                    location: Some(String::from("spotify:track:08mG3Y1vljYA6bvDt4Wqkj")),
+                    identifier: Some(String::from("https://open.spotify.com/track/08mG3Y1vljYA6bvDt4Wqkj")),
+                },
+                PlaylistFileEntry {
+                    title: String::from("Rock & Roll"),
+                    creator: String::from("Led Zeppelin"),
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+
+    fn write(playlist: &PlaylistFile, format: PlaylistFormat) -> String {
+        let mut output = Vec::new();
+        playlist.write(&mut output, format).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn writes_m3u8() {
+        assert_eq!(
+            write(&playlist(), PlaylistFormat::M3u8),
+            "#EXTM3U\n\
+             #PLAYLIST:Rock & <Roll>\n\
+             #EXTINF:255,AC/DC - Back in Black\n\
+             #EXTALB:Back in Black\n\
+             spotify:track:08mG3Y1vljYA6bvDt4Wqkj\n"
+        );
+
+        let mut playlist = playlist();
+        playlist.title = String::from("Line\nbreak");
+        playlist.entries[1].location = Some(String::from("/music/rock.mp3"));
+        assert!(write(&playlist, PlaylistFormat::M3u8).ends_with("#EXTINF:-1,Led Zeppelin - Rock & Roll\n/music/rock.mp3\n"));
+        assert!(write(&playlist, PlaylistFormat::M3u8).contains("#PLAYLIST:Line break\n"));
+    }
+
+    #[test]
+    fn writes_xspf() {
+        assert_eq!(
+            write(&playlist(), PlaylistFormat::Xspf),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/">
+  <title>Rock &amp; &lt;Roll&gt;</title>
+  <annotation>&quot;Best&quot; of
+2023</annotation>
+  <trackList>
+    <track>
+      <location>spotify:track:08mG3Y1vljYA6bvDt4Wqkj</location>
+      <identifier>https://open.spotify.com/track/08mG3Y1vljYA6bvDt4Wqkj</identifier>
+      <title>Back in Black</title>
+      <creator>AC/DC</creator>
+      <album>Back in Black</album>
+      <duration>255000</duration>
+    </track>
+    <track>
+      <title>Rock &amp; Roll</title>
+      <creator>Led Zeppelin</creator>
+    </track>
+  </trackList>
+</playlist>
+"#
+        );
+    }
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(escape_xml(r#"<a href="x">Tom & Jerry's</a>"#), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;");
+    }
+
+    #[test]
+    fn writes_jspf() {
+        let jspf: serde_json::Value = serde_json::from_str(&write(&playlist(), PlaylistFormat::Jspf)).unwrap();
+        assert_eq!(
+            jspf,
+            serde_json::json!({
+                "playlist": {
+                    "title": "Rock & <Roll>",
+                    "annotation": "\"Best\" of\n2023",
+                    "track": [
+                        {
+                            "title": "Back in Black",
+                            "creator": "AC/DC",
+                            "album": "Back in Black",
+                            "duration": 255000,
+                            "location": ["spotify:track:08mG3Y1vljYA6bvDt4Wqkj"],
+                            "identifier": ["https://open.spotify.com/track/08mG3Y1vljYA6bvDt4Wqkj"]
+                        },
+                        { "title": "Rock & Roll", "creator": "Led Zeppelin" }
+                    ]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn safe_file_names() {
+        assert_eq!(safe_file_name("Road trip: 2023/24"), "Road trip_ 2023_24");
+        assert_eq!(safe_file_name("Mix..."), "Mix");
+        assert_eq!(safe_file_name(".."), "_");
+        assert_eq!(safe_file_name("  "), "_");
+        assert_eq!(safe_file_name("Æ ø å 🎵"), "Æ ø å 🎵");
+    }
+
+    #[test]
+    fn unique_file_names() {
+        let mut file_names = FileNames::default();
+        assert_eq!(file_names.unique("Chill"), "Chill");
+        assert_eq!(file_names.unique("chill"), "chill (2)");
+        assert_eq!(file_names.unique("Chill?"), "Chill_");
+        assert_eq!(file_names.unique("Chill"), "Chill (3)");
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context as ErrorContext, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use strsim::normalized_damerau_levenshtein;
+
+use crate::my_spotify_data::{Playlist as SpotifyPlaylist, PlaylistEpisode, PlaylistItemAbstraction};
+use crate::playlist_files::escape_xml;
+
+/// Minimum similarity of show names for a search result to be used.
+const MIN_SHOW_NAME_SCORE: f64 = 0.8;
+
+/// A podcast show found in a podcast directory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PodcastShow {
+    pub name: String,
+    pub author: Option<String>,
+    /// RSS feed URL, which podcast apps subscribe to
+    pub feed_url: Option<String>,
+    /// Web page of the show in the directory
+    pub url: Option<String>,
+}
+
+/// Looks up podcast shows by name, to find their RSS feeds.
+#[async_trait]
+pub trait PodcastDirectory: Send + Sync {
+    async fn find_show(&self, show_name: &str) -> Result<Option<PodcastShow>>;
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ItunesSearchResult {
+    collection_name: String,
+    #[serde(default)]
+    artist_name: Option<String>,
+    #[serde(default)]
+    feed_url: Option<String>,
+    #[serde(default)]
+    collection_view_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ItunesSearchResponse {
+    results: Vec<ItunesSearchResult>,
+}
+
+/// Best-effort search of the Apple Podcasts directory through the iTunes Search API.
+pub struct ApplePodcastsSearch {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl ApplePodcastsSearch {
+    pub fn new() -> Self {
+        Self::with_base_url("https://itunes.apple.com")
+    }
+
+    pub fn with_base_url(base_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl PodcastDirectory for ApplePodcastsSearch {
+    async fn find_show(&self, show_name: &str) -> Result<Option<PodcastShow>> {
+        let url = format!("{}/search", self.base_url);
+        log::debug!("Apple Podcasts: GET {} ({:?})", &url, show_name);
+        let res: ItunesSearchResponse = self
+            .client
+            .get(&url)
+            .query(&[("term", show_name), ("media", "podcast"), ("entity", "podcast"), ("limit", "5")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // search results also include shows with similar names, so pick the closest one
+        let show_name = show_name.to_lowercase();
+        let best = res
+            .results
+            .into_iter()
+            .map(|r| (normalized_damerau_levenshtein(&show_name, &r.collection_name.to_lowercase()), r))
+            .filter(|(score, _)| *score >= MIN_SHOW_NAME_SCORE)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        Ok(best.map(|(_, r)| PodcastShow {
+            name: r.collection_name,
+            author: r.artist_name,
+            feed_url: r.feed_url,
+            url: r.collection_view_url,
+        }))
+    }
+}
+
+/// A podcast episode found in a Spotify playlist.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpisodeEntry {
+    pub playlist: String,
+    pub show_name: String,
+    pub episode_name: String,
+    pub uri: String,
+}
+
+/// Podcast episodes collected from Spotify playlists, exported as an OPML file of their shows and a CSV list of
+/// the episodes.
+#[derive(Debug, Clone, Default)]
+pub struct PodcastExport {
+    pub episodes: Vec<EpisodeEntry>,
+}
+
+impl PodcastExport {
+    pub fn add(&mut self, playlist: &str, episode: &PlaylistEpisode) {
+        self.episodes.push(EpisodeEntry {
+            playlist: playlist.to_string(),
+            show_name: episode.show_name.clone(),
+            episode_name: episode.episode_name.clone(),
+            uri: episode.episode_uri.clone(),
+        });
+    }
+
+    /// Adds all podcast episodes in a playlist.
+    pub fn add_playlist(&mut self, playlist: &SpotifyPlaylist) {
+        for item in &playlist.items {
+            if let PlaylistItemAbstraction::Episode(episode) = item.item() {
+                self.add(&playlist.name, episode);
+            }
+        }
+    }
+
+    /// Show names of the collected episodes, in alphabetical order.
+    pub fn show_names(&self) -> Vec<&str> {
+        let mut show_names: Vec<&str> = self.episodes.iter().map(|e| e.show_name.as_str()).collect();
+        show_names.sort_unstable();
+        show_names.dedup();
+        show_names
+    }
+
+    /// Looks up the shows of the collected episodes in a podcast directory. Shows which could not be looked up are
+    /// left out.
+    pub async fn find_shows(&self, directory: &dyn PodcastDirectory) -> BTreeMap<String, PodcastShow> {
+        let mut shows = BTreeMap::new();
+        for show_name in self.show_names() {
+            match directory.find_show(show_name).await {
+                Ok(Some(show)) => {
+                    shows.insert(show_name.to_string(), show);
+                }
+                Ok(None) => log::debug!("Podcast show {:?} not found", show_name),
+                Err(e) => log::warn!("Could not look up podcast show {:?}: {}", show_name, e),
+            }
+        }
+        shows
+    }
+
+    /// Writes an OPML outline of the shows. Shows without a known RSS feed are included by name only.
+    pub fn write_opml<W: Write>(&self, mut writer: W, shows: &BTreeMap<String, PodcastShow>) -> Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, r#"<opml version="2.0">"#)?;
+        writeln!(writer, "  <head>")?;
+        writeln!(writer, "    <title>Podcasts from Spotify playlists</title>")?;
+        writeln!(writer, "  </head>")?;
+        writeln!(writer, "  <body>")?;
+        for show_name in self.show_names() {
+            match shows.get(show_name) {
+                Some(PodcastShow {
+                    feed_url: Some(feed_url),
+                    url,
+                    ..
+                }) => {
+                    write!(
+                        writer,
+                        r#"    <outline type="rss" text="{0}" title="{0}" xmlUrl="{1}""#,
+                        escape_xml(show_name),
+                        escape_xml(feed_url)
+                    )?;
+                    if let Some(url) = url {
+                        write!(writer, r#" htmlUrl="{}""#, escape_xml(url))?;
+                    }
+                    writeln!(writer, "/>")?;
+                }
+                _ => writeln!(writer, r#"    <outline text="{0}" title="{0}"/>"#, escape_xml(show_name))?,
+            }
+        }
+        writeln!(writer, "  </body>")?;
+        writeln!(writer, "</opml>")?;
+        Ok(())
+    }
+
+    pub fn save_opml<P: AsRef<Path>>(&self, opml_path: P, shows: &BTreeMap<String, PodcastShow>) -> Result<()> {
+        let opml_file = File::create(opml_path).context("could not create OPML file")?;
+        self.write_opml(opml_file, shows)
+    }
+
+    /// Writes a CSV list of the episodes, with the RSS feed and web page of their show if known.
+    pub fn save_episodes<P: AsRef<Path>>(&self, episodes_path: P, shows: &BTreeMap<String, PodcastShow>) -> Result<()> {
+        let episodes_file = File::create(episodes_path).context("could not create podcast episodes file")?;
+        let mut writer = csv::Writer::from_writer(episodes_file);
+        writer.write_record(["playlist", "showName", "episodeName", "uri", "feedUrl", "showUrl"])?;
+        for episode in &self.episodes {
+            let show = shows.get(&episode.show_name);
+            writer.write_record([
+                episode.playlist.as_str(),
+                &episode.show_name,
+                &episode.episode_name,
+                &episode.uri,
+                show.and_then(|s| s.feed_url.as_deref()).unwrap_or_default(),
+                show.and_then(|s| s.url.as_deref()).unwrap_or_default(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StubDirectory;
+
+    #[async_trait]
+    impl PodcastDirectory for StubDirectory {
+        async fn find_show(&self, show_name: &str) -> Result<Option<PodcastShow>> {
+            Ok((show_name == "Known Show").then(|| PodcastShow {
+                name: show_name.to_string(),
+                author: None,
+                feed_url: Some("https://example.com/feed?a=1&b=2".to_string()),
+                url: None,
+            }))
+        }
+    }
+
+    fn episode(show_name: &str, episode_name: &str) -> PlaylistEpisode {
+        PlaylistEpisode {
+            episode_name: episode_name.to_string(),
+            show_name: show_name.to_string(),
+            episode_uri: format!("spotify:episode:{}", episode_name),
+        }
+    }
+
+    #[tokio::test]
+    async fn opml_lists_each_show_once() {
+        let mut export = PodcastExport::default();
+        export.add("Commute", &episode("Known Show", "1"));
+        export.add("Commute", &episode("Unknown <Show>", "2"));
+        export.add("Workout", &episode("Known Show", "3"));
+
+        let shows = export.find_shows(&StubDirectory).await;
+        let mut opml = Vec::new();
+        export.write_opml(&mut opml, &shows).unwrap();
+        let opml = String::from_utf8(opml).unwrap();
+
+        assert_eq!(opml.matches("<outline").count(), 2);
+        assert!(opml.contains(
+            r#"<outline type="rss" text="Known Show" title="Known Show" xmlUrl="https://example.com/feed?a=1&amp;b=2"/>"#
+        ));
+        assert!(opml.contains(r#"<outline text="Unknown &lt;Show&gt;" title="Unknown &lt;Show&gt;"/>"#));
+    }
+}
@@ -0,0 +1,315 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context as ErrorContext, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file;
+use crate::import_state::PlaylistState;
+use crate::matching::MatchResult;
+use crate::my_spotify_data::{Playlist as SpotifyPlaylist, PlaylistItemAbstraction};
+use crate::track_finder::{MatchMethod, MatchOutcome, ScoredCandidate, SkipReason, TrackMatch};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+}
+
+impl ReportFormat {
+    /// Guesses the report format from the file extension, defaulting to JSON.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => Self::Csv,
+            _ => Self::Json,
+        }
+    }
+}
+
+/// Outcome of a single playlist item.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackReport {
+    /// Spotify URI of the track, episode or local track
+    pub uri: String,
+    pub artist_name: String,
+    pub album_name: String,
+    pub track_name: String,
+    /// ID of the Apple Music catalog song used for the track, or `None` if the track was skipped
+    #[serde(default)]
+    pub song_id: Option<String>,
+    #[serde(default)]
+    pub song_url: Option<String>,
+    #[serde(default)]
+    pub method: Option<MatchMethod>,
+    #[serde(default)]
+    pub score: Option<f64>,
+    #[serde(default)]
+    pub scores: Option<MatchResult>,
+    #[serde(default)]
+    pub skip_reason: Option<SkipReason>,
+    /// Catalog songs considered, best first
+    #[serde(default)]
+    pub candidates: Vec<ScoredCandidate>,
+}
+
+impl TrackReport {
+    /// Report of an item which was not processed, without a song or skip reason.
+    pub fn unprocessed(item: &PlaylistItemAbstraction) -> Self {
+        let (artist_name, album_name, track_name) = match item {
+            PlaylistItemAbstraction::Track(track) => (
+                track.artist_name.clone(),
+                track.album_name.clone(),
+                track.track_name.clone(),
+            ),
+            PlaylistItemAbstraction::Episode(episode) => (
+                episode.show_name.clone(),
+                String::new(),
+                episode.episode_name.clone(),
+            ),
+            PlaylistItemAbstraction::LocalTrack(local_track) => match local_track.parse_uri() {
+                Some(parsed) => (parsed.artist_name, parsed.album_name, parsed.track_name),
+                None => (String::new(), String::new(), String::new()),
+            },
+        };
+
+        Self {
+            uri: item.uri().to_string(),
+            artist_name,
+            album_name,
+            track_name,
+            song_id: None,
+            song_url: None,
+            method: None,
+            score: None,
+            scores: None,
+            skip_reason: None,
+            candidates: Vec::new(),
+        }
+    }
+
+    pub fn new(item: &PlaylistItemAbstraction, track_match: &TrackMatch) -> Self {
+        let mut report = Self::unprocessed(item);
+        report.candidates = track_match.candidates.clone();
+        match &track_match.outcome {
+            MatchOutcome::Found(found) => {
+                report.song_id = Some(found.id.clone());
+                report.song_url = found.url.clone();
+                report.method = Some(found.method);
+                report.score = found.score;
+                report.scores = found.result.clone();
+            }
+            MatchOutcome::Skipped(reason) => report.skip_reason = Some(*reason),
+        }
+        report
+    }
+
+    /// Reports of the items processed by an earlier run, as recorded in the import state.
+    pub fn from_state(playlist: &SpotifyPlaylist, playlist_state: &PlaylistState) -> Vec<Self> {
+        // matched tracks are recorded in playlist order, so items are matched to them in the same order
+        let mut matched = playlist_state.matched.iter().peekable();
+        playlist
+            .items
+            .iter()
+            .take(playlist_state.processed)
+            .map(|item| {
+                let item = item.item();
+                let mut report = Self::unprocessed(&item);
+                match matched.next_if(|t| t.track_uri == item.uri()) {
+                    Some(track) => {
+                        report.song_id = Some(track.song_id.clone());
+                        report.method = Some(MatchMethod::Resumed);
+                    }
+                    None => report.skip_reason = Some(SkipReason::Resumed),
+                }
+                report
+            })
+            .collect()
+    }
+}
+
+/// What happened to a playlist during an import.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlaylistStatus {
+    /// Tracks were matched, some possibly by an earlier run recorded in the import state file
+    #[default]
+    Matched,
+    /// Skipped, as a playlist with the same name already exists in Apple Music
+    AlreadyExists,
+    /// Skipped, as an earlier run recorded in the import state file imported it
+    AlreadyImported,
+}
+
+impl PlaylistStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Matched => "matched",
+            Self::AlreadyExists => "already-exists",
+            Self::AlreadyImported => "already-imported",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaylistReport {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub status: PlaylistStatus,
+    /// ID of the Apple Music library playlist the tracks were added to, if any
+    #[serde(default)]
+    pub apple_music_playlist_id: Option<String>,
+    /// Every playlist item. Items of skipped playlists have neither a song nor a skip reason.
+    pub tracks: Vec<TrackReport>,
+}
+
+impl PlaylistReport {
+    /// Report of a playlist which was skipped without matching its items.
+    pub fn skipped(playlist: &SpotifyPlaylist, status: PlaylistStatus, apple_music_playlist_id: Option<String>) -> Self {
+        Self {
+            name: playlist.name.clone(),
+            description: playlist.description.clone(),
+            status,
+            apple_music_playlist_id,
+            tracks: playlist.items.iter().map(|item| TrackReport::unprocessed(&item.item())).collect(),
+        }
+    }
+}
+
+/// Structured report of an import, listing the outcome of every playlist item.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub storefront: String,
+    pub generated_at: DateTime<Utc>,
+    pub playlists: Vec<PlaylistReport>,
+}
+
+impl Report {
+    pub fn new(storefront: &str) -> Self {
+        Self {
+            storefront: storefront.to_string(),
+            generated_at: Utc::now(),
+            playlists: Vec::new(),
+        }
+    }
+
+    /// Loads a JSON report, such as one written with `--dry --report` and edited by hand.
+    pub fn load<P: AsRef<Path>>(report_path: P) -> Result<Self> {
+        let report_file = File::open(report_path).context("could not open report file")?;
+        serde_json::from_reader(report_file).context("could not parse report file")
+    }
+
+    /// Saves the report atomically, so that a report saved after every playlist is never left truncated.
+    pub fn save<P: AsRef<Path>>(&self, report_path: P, format: ReportFormat) -> Result<()> {
+        match format {
+            ReportFormat::Json => atomic_file::write_json(report_path, self),
+            ReportFormat::Csv => atomic_file::write_with(report_path, |writer| self.write_csv(writer)),
+        }
+        .context("could not save report file")
+    }
+
+    /// Writes one row per playlist item. Candidates are summarized by their count.
+    fn write_csv<W: Write>(&self, report_file: W) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(report_file);
+        writer.write_record([
+            "playlist",
+            "playlistStatus",
+            "uri",
+            "artistName",
+            "albumName",
+            "trackName",
+            "songId",
+            "songUrl",
+            "method",
+            "score",
+            "artistScore",
+            "albumScore",
+            "trackScore",
+            "durationScore",
+            "versionMismatches",
+            "skipReason",
+            "candidates",
+        ])?;
+
+        let format_score = |score: Option<f64>| score.map(|s| format!("{:.6}", s)).unwrap_or_default();
+        for playlist in &self.playlists {
+            for track in &playlist.tracks {
+                let scores = track.scores.as_ref();
+                writer.write_record([
+                    playlist.name.as_str(),
+                    playlist.status.as_str(),
+                    &track.uri,
+                    &track.artist_name,
+                    &track.album_name,
+                    &track.track_name,
+                    track.song_id.as_deref().unwrap_or_default(),
+                    track.song_url.as_deref().unwrap_or_default(),
+                    track.method.as_ref().map(|m| m.as_str()).unwrap_or_default(),
+                    &format_score(track.score),
+                    &format_score(scores.map(|s| s.artist_score)),
+                    &format_score(scores.map(|s| s.album_score)),
+                    &format_score(scores.map(|s| s.track_score)),
+                    &format_score(scores.and_then(|s| s.duration_score)),
+                    &scores
+                        .map(|s| {
+                            s.version_mismatches
+                                .iter()
+                                .map(|tag| tag.as_str())
+                                .collect::<Vec<_>>()
+                                .join(";")
+                        })
+                        .unwrap_or_default(),
+                    track.skip_reason.as_ref().map(|r| r.as_str()).unwrap_or_default(),
+                    &track.candidates.len().to_string(),
+                ])?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    fn report() -> Report {
+        let mut report = Report::new("no");
+        report.playlists.push(PlaylistReport {
+            name: String::from("Road trip"),
+            description: None,
+            status: PlaylistStatus::default(),
+            apple_music_playlist_id: Some(String::from("p.created")),
+            tracks: Vec::new(),
+        });
+        report
+    }
+
+    #[test]
+    fn saves_and_replaces_report() {
+        let dir = TestDir::new("report");
+        let path = dir.join("report.json");
+
+        Report::new("us").save(&path, ReportFormat::Json).unwrap();
+        report().save(&path, ReportFormat::Json).unwrap();
+        let loaded = Report::load(&path).unwrap();
+        assert_eq!(loaded.storefront, "no");
+        assert_eq!(loaded.playlists[0].apple_music_playlist_id.as_deref(), Some("p.created"));
+
+        let csv_path = dir.join("report.csv");
+        report().save(&csv_path, ReportFormat::Csv).unwrap();
+        assert!(std::fs::read_to_string(&csv_path).unwrap().starts_with("playlist,playlistStatus,uri,"));
+
+        let mut files: Vec<_> = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+        files.sort();
+        assert_eq!(files, ["report.csv", "report.json"]);
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::Result;
+use console::Term;
+use dialoguer::theme::ColorfulTheme;
+
+use crate::apple_music::response::Song;
+use crate::apple_music::ContentRating;
+use crate::matching::MatchResult;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReviewOptions {
+    /// Matches with a best score below this are reviewed
+    pub uncertain_below: f64,
+    /// Matches where the second best candidate scores within this of the best candidate are reviewed
+    pub close_margin: f64,
+}
+
+impl ReviewOptions {
+    /// Whether ranked candidates (best first) are ambiguous enough to ask the user.
+    pub fn needs_review(&self, ranked: &[(&Song, MatchResult)]) -> bool {
+        let Some((_, best)) = ranked.first() else {
+            return true;
+        };
+        if best.score() < self.uncertain_below || best.is_different_recording() {
+            return true;
+        }
+        ranked.get(1).is_some_and(|(_, second)| {
+            !second.is_different_recording() && best.score() - second.score() < self.close_margin
+        })
+    }
+}
+
+pub enum ReviewDecision {
+    /// Use the candidate at this index
+    Pick(usize),
+    /// Skip the track
+    Skip,
+    /// Search again using this search term
+    Search(String),
+}
+
+pub fn format_duration(duration_ms: u64) -> String {
+    let seconds = duration_ms / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn format_candidate(song: &Song, result: &MatchResult) -> String {
+    let attributes = &song.attributes;
+    let mut details = vec![
+        attributes.album_name.clone(),
+        format_duration(attributes.duration_in_millis),
+    ];
+    if matches!(attributes.content_rating, ContentRating::Explicit) {
+        details.push(String::from("explicit"));
+    }
+    if let Some(release_date) = &attributes.release_date {
+        details.push(release_date.to_string());
+    }
+    details.push(attributes.url.clone());
+
+    format!(
+        "{} - {} ({}) (score: {:.3})",
+        attributes.artist_name,
+        attributes.name,
+        details.join(", "),
+        result.score()
+    )
+}
+
+/// Asks the user to pick one of the ranked candidates, skip the track or search again.
+pub fn review(term: &Term, track_description: &str, search_term: &str, ranked: &[(&Song, MatchResult)]) -> Result<ReviewDecision> {
+    let mut items: Vec<String> = ranked
+        .iter()
+        .map(|(song, result)| format_candidate(song, result))
+        .collect();
+    items.push(String::from("Skip this track"));
+    items.push(String::from("Search manually..."));
+
+    let selected = dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Select Apple Music song for {}", track_description))
+        .items(&items)
+        .default(0)
+        .max_length(10)
+        .interact_on(term)?;
+
+    if selected < ranked.len() {
+        Ok(ReviewDecision::Pick(selected))
+    } else if selected == ranked.len() {
+        Ok(ReviewDecision::Skip)
+    } else {
+        let search_term = dialoguer::Input::<String>::with_theme(&ColorfulTheme::default())
+            .with_prompt("Search term")
+            .with_initial_text(search_term)
+            .interact_text_on(term)?;
+        Ok(ReviewDecision::Search(search_term))
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use console::Term;
+
+use crate::config::Config;
+use crate::library_backup::{LibrarySnapshot, SnapshotPlaylist, SnapshotTrack};
+use crate::matching::{self, FuzzyMatcher, ToTrackCandidate, TrackCandidate};
+use crate::spotify_web_api::request::{PlaylistCreationRequest, SearchQuery};
+use crate::spotify_web_api::response::{Playlist, Track};
+use crate::spotify_web_api::{self, AuthConfig};
+use crate::plural;
+
+pub struct SpotifyImportOptions {
+    /// If not empty, only playlists with these names are imported
+    pub playlists: Vec<String>,
+    /// If set, will not create playlists
+    pub dry: bool,
+    /// If set, created playlists are public
+    pub public: bool,
+    /// Minimum score for a match (between 0.0 and 3.0)
+    pub min_score: f64,
+    /// Limit of possible tracks per search result
+    pub limit: usize,
+    /// Access token to use instead of authorizing
+    pub access_token: Option<String>,
+    /// Client ID to authorize with, in place of the one in the config
+    pub client_id: Option<String>,
+    /// Port of the local redirect listener used when authorizing
+    pub redirect_port: u16,
+}
+
+impl ToTrackCandidate for Track {
+    fn to_track_candidate(&self) -> TrackCandidate<'_> {
+        TrackCandidate {
+            artist_name: self.artist_name(),
+            album_name: &self.album.name,
+            track_name: &self.name,
+            duration_ms: Some(self.duration_ms),
+        }
+    }
+}
+
+/// Finds the Spotify track matching an Apple Music track, by ISRC or by searching.
+async fn find_spotify_track(
+    spotify_client: &spotify_web_api::Client,
+    term: &Term,
+    track: &SnapshotTrack,
+    options: &SpotifyImportOptions,
+) -> Result<Option<Track>> {
+    // look up by ISRC first, as it identifies the exact recording
+    if let Some(isrc) = &track.isrc {
+        if let Some(spotify_track) = spotify_client.find_track_by_isrc(isrc).await? {
+            term.write_line(&format!(
+                "\t{} Found \"{} - {}\" in Spotify by ISRC {}: {}",
+                console::style("✔").green(),
+                spotify_track.artist_name(),
+                &spotify_track.name,
+                isrc,
+                &spotify_track.uri
+            ))?;
+            return Ok(Some(spotify_track));
+        }
+        log::debug!("No Spotify tracks with ISRC {}, falling back to search", isrc);
+    }
+
+    let search_term = format!("{} {}", &track.artist_name, &track.name);
+    term.write_str(&format!(
+        "\t{} Searching for {:?} in Spotify... ",
+        console::style("✱").blue(),
+        &search_term
+    ))?;
+    let res = spotify_client
+        .search(&SearchQuery {
+            q: &search_term,
+            types: "track",
+            limit: Some(options.limit),
+            ..Default::default()
+        })
+        .await?;
+    let spotify_tracks = res.tracks.map(|tracks| tracks.items).unwrap_or_default();
+    term.clear_line()?;
+
+    // score tracks by fuzzy match of artist, album and track name
+    let best = matching::rank(&FuzzyMatcher, &track.to_track_candidate(), &spotify_tracks)
+        .into_iter()
+        .next()
+        .filter(|(_, result)| result.is_match(options.min_score));
+    match best {
+        Some((spotify_track, result)) => {
+            term.write_line(&format!(
+                "\t{} Found \"{} - {}\" in Spotify (score: {:.6}): {}",
+                console::style("✔").green(),
+                spotify_track.artist_name(),
+                &spotify_track.name,
+                result.score(),
+                &spotify_track.uri
+            ))?;
+            Ok(Some(spotify_track.clone()))
+        }
+        None => {
+            term.write_line(&format!(
+                "\t{} Skipping {:?}: Could not be found in Spotify",
+                console::style("✘").red(),
+                &search_term
+            ))?;
+            Ok(None)
+        }
+    }
+}
+
+/// Creates a Spotify playlist with the given tracks, returning the created playlist.
+async fn create_spotify_playlist(
+    spotify_client: &spotify_web_api::Client,
+    user_id: &str,
+    playlist: &SnapshotPlaylist,
+    uris: &[String],
+    public: bool,
+) -> Result<Playlist> {
+    let spotify_playlist = spotify_client
+        .create_playlist(
+            user_id,
+            &PlaylistCreationRequest {
+                name: playlist.name.clone(),
+                description: playlist.description.clone(),
+                public,
+            },
+        )
+        .await?;
+    spotify_client.add_playlist_tracks(&spotify_playlist.id, uris).await?;
+    Ok(spotify_playlist)
+}
+
+/// Imports Apple Music library playlists to Spotify.
+pub async fn import_apple_music_playlists_to_spotify(config: &Config, options: &SpotifyImportOptions) -> Result<()> {
+    let term = console::Term::stdout();
+    let music_client = config.apple_music_client()?;
+
+    let spotify_client = match &options.access_token {
+        Some(access_token) => spotify_web_api::Client::new(access_token),
+        None => {
+            let client_id = options.client_id.as_deref().unwrap_or(&config.spotify_client_id);
+            if client_id.is_empty() {
+                bail!("a Spotify client ID is needed to authorize, set spotifyClientId in the config file or use --spotify-client-id");
+            }
+            let auth_config = AuthConfig {
+                redirect_port: options.redirect_port,
+                ..AuthConfig::new(client_id)
+            };
+            let token = spotify_web_api::authorize(&auth_config, &term).await?;
+            spotify_web_api::Client::new_with_token(token, auth_config)
+        }
+    };
+    let user = spotify_client.get_current_user().await?;
+    term.write_line(&format!(
+        "{} Logged in to Spotify as {}",
+        console::style("✔").green(),
+        user.display_name.as_deref().unwrap_or(&user.id)
+    ))?;
+
+    let snapshot = LibrarySnapshot::take(
+        &music_client,
+        &config.apple_music_storefront,
+        &term,
+        &options.playlists,
+        true,
+    )
+    .await?;
+
+    let existing_names: HashSet<String> = if options.dry {
+        HashSet::new()
+    } else {
+        spotify_client
+            .get_current_user_playlists()
+            .await?
+            .into_iter()
+            .map(|p| p.name)
+            .collect()
+    };
+
+    for playlist in &snapshot.playlists {
+        if existing_names.contains(&playlist.name) {
+            term.write_line(&format!(
+                "{} Playlist {:?} already exists in Spotify",
+                console::style("✔").green(),
+                &playlist.name
+            ))?;
+            continue;
+        }
+
+        term.write_line(&format!(
+            "{} Importing playlist {:?} with {} track{}",
+            console::style("✱").blue(),
+            &playlist.name,
+            playlist.tracks.len(),
+            plural(playlist.tracks.len())
+        ))?;
+
+        let mut uris = Vec::new();
+        for track in &playlist.tracks {
+            if let Some(spotify_track) = find_spotify_track(&spotify_client, &term, track, options).await? {
+                uris.push(spotify_track.uri);
+            }
+        }
+
+        if options.dry {
+            term.write_line(&format!(
+                "{} Skipped creating playlist {:?} with {} track{} in Spotify (--dry)",
+                console::style("✱").blue(),
+                &playlist.name,
+                uris.len(),
+                plural(uris.len())
+            ))?;
+            continue;
+        }
+
+        let created = create_spotify_playlist(&spotify_client, &user.id, playlist, &uris, options.public).await;
+        match created {
+            Ok(spotify_playlist) => term.write_line(&format!(
+                "{} Created playlist {:?} with {} of {} track{} in Spotify: {}",
+                console::style("✔").green(),
+                &playlist.name,
+                uris.len(),
+                playlist.tracks.len(),
+                plural(playlist.tracks.len()),
+                spotify_playlist
+                    .external_urls
+                    .spotify
+                    .as_deref()
+                    .unwrap_or(&spotify_playlist.uri)
+            ))?,
+            Err(e) => term.write_line(&format!(
+                "{} Failed to create playlist {:?} in Spotify: {}",
+                console::style("✘").red(),
+                &playlist.name,
+                e
+            ))?,
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use console::Term;
+use rand::distributions::{Alphanumeric, Distribution, Slice};
+use rand::Rng;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use super::response::{ErrorResponse, Token};
+
+/// Characters allowed in a PKCE code verifier.
+const VERIFIER_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-._~";
+
+/// Length of generated code verifiers, between 43 and 128 characters.
+const VERIFIER_LEN: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    /// Client ID of an app registered in the Spotify developer dashboard
+    pub client_id: String,
+    /// Port of the local redirect listener. `http://127.0.0.1:{port}/callback` must be registered as a redirect URI
+    /// of the app.
+    pub redirect_port: u16,
+    pub scopes: Vec<String>,
+    /// Base URL of the accounts service, changed to run against a local server in tests
+    pub accounts_base_url: String,
+}
+
+impl AuthConfig {
+    pub fn new(client_id: &str) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            redirect_port: 8888,
+            scopes: vec![
+                String::from("playlist-read-private"),
+                String::from("playlist-modify-private"),
+                String::from("playlist-modify-public"),
+            ],
+            accounts_base_url: String::from("https://accounts.spotify.com"),
+        }
+    }
+}
+
+fn redirect_uri(port: u16) -> String {
+    format!("http://127.0.0.1:{}/callback", port)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// A Proof Key for Code Exchange (RFC 7636), which lets apps without a client secret use the authorization code flow.
+#[derive(Debug, Clone)]
+pub struct PkceChallenge {
+    pub verifier: String,
+    /// Base64url encoded SHA-256 hash of the verifier
+    pub challenge: String,
+}
+
+impl PkceChallenge {
+    pub fn new() -> Self {
+        let chars = Slice::new(VERIFIER_CHARS).expect("verifier characters are not empty");
+        let verifier: String = chars
+            .sample_iter(rand::thread_rng())
+            .take(VERIFIER_LEN)
+            .map(|&c| c as char)
+            .collect();
+        Self::from_verifier(verifier)
+    }
+
+    pub fn from_verifier(verifier: String) -> Self {
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Self { verifier, challenge }
+    }
+}
+
+fn authorize_url(config: &AuthConfig, redirect_uri: &str, pkce: &PkceChallenge, state: &str) -> Result<Url> {
+    let mut url = Url::from_str(&config.accounts_base_url)?.join("/authorize")?;
+    url.query_pairs_mut()
+        .append_pair("client_id", &config.client_id)
+        .append_pair("response_type", "code")
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("code_challenge_method", "S256")
+        .append_pair("code_challenge", &pkce.challenge)
+        .append_pair("scope", &config.scopes.join(" "))
+        .append_pair("state", state);
+    Ok(url)
+}
+
+/// Waits for the browser to be redirected to the local listener, returning the authorization code.
+async fn receive_code(listener: &TcpListener, state: &str) -> Result<String> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+
+        // only the request line is needed, which fits in the first read
+        let mut buf = vec![0; 8192];
+        let len = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..len]);
+        let path = request
+            .lines()
+            .next()
+            .and_then(|line| line.split(' ').nth(1))
+            .unwrap_or_default();
+        log::debug!("Spotify: redirected to {}", path);
+
+        // browsers may also ask for things such as /favicon.ico
+        if !path.starts_with("/callback") {
+            stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await?;
+            continue;
+        }
+
+        let url = Url::from_str("http://127.0.0.1")?.join(path)?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+
+        let body = "Authorized. You can close this window and return to music-data-tools.";
+        stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .as_bytes(),
+            )
+            .await?;
+
+        if param("state").as_deref() != Some(state) {
+            bail!("state of authorization response does not match request");
+        }
+        if let Some(error) = param("error") {
+            bail!("authorization failed: {}", error);
+        }
+        return param("code").ok_or(anyhow!("no code in authorization response"));
+    }
+}
+
+async fn exchange_code(config: &AuthConfig, redirect_uri: &str, code: &str, pkce: &PkceChallenge) -> Result<Token> {
+    request_token(
+        config,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", &config.client_id),
+            ("code_verifier", &pkce.verifier),
+        ],
+    )
+    .await
+}
+
+/// Gets a new access token with a refresh token from the authorization code flow with PKCE.
+pub async fn refresh(config: &AuthConfig, refresh_token: &str) -> Result<Token> {
+    request_token(
+        config,
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("client_id", &config.client_id),
+        ],
+    )
+    .await
+}
+
+async fn request_token(config: &AuthConfig, form: &[(&str, &str)]) -> Result<Token> {
+    let url = Url::from_str(&config.accounts_base_url)?.join("/api/token")?;
+    log::debug!("Spotify: POST {}", &url);
+    let res = reqwest::Client::new().post(url).form(form).send().await?;
+
+    let status = res.status();
+    let text = res.text().await?;
+    log::debug!("Spotify: {}", status);
+    if status.is_success() {
+        return Ok(serde_json::from_str(&text)?);
+    }
+
+    match serde_json::from_str::<TokenErrorResponse>(&text) {
+        Ok(e) => bail!(
+            "could not get Spotify access token: {}",
+            e.error_description.unwrap_or(e.error)
+        ),
+        Err(_) => Err(anyhow!(serde_json::from_str::<ErrorResponse>(&text)?)),
+    }
+}
+
+/// Gets an access token with the authorization code flow with PKCE. The user is asked to open the authorization page
+/// in a browser, which redirects to a local listener once access is granted.
+pub async fn authorize(config: &AuthConfig, term: &Term) -> Result<Token> {
+    let listener = TcpListener::bind(("127.0.0.1", config.redirect_port)).await?;
+    let redirect_uri = redirect_uri(listener.local_addr()?.port());
+
+    let pkce = PkceChallenge::new();
+    let state: String = rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect();
+    let url = authorize_url(config, &redirect_uri, &pkce, &state)?;
+
+    term.write_line(&format!(
+        "{} Open this URL in a browser to allow access to your Spotify account:\n\n{}\n",
+        console::style("✱").blue(),
+        url
+    ))?;
+
+    let code = receive_code(&listener, &state).await?;
+    let token = exchange_code(config, &redirect_uri, &code, &pkce).await?;
+
+    term.write_line(&format!("{} Authorized with Spotify", console::style("✔").green()))?;
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkce_challenge() {
+        // example from RFC 7636, appendix B
+        let pkce = PkceChallenge::from_verifier(String::from("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"));
+        assert_eq!(pkce.challenge, "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+
+        let pkce = PkceChallenge::new();
+        assert_eq!(pkce.verifier.len(), VERIFIER_LEN);
+        assert!(pkce.verifier.bytes().all(|c| VERIFIER_CHARS.contains(&c)));
+    }
+
+    #[tokio::test]
+    async fn receives_code_from_redirect() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let browser = tokio::spawn(async move {
+            let favicon = reqwest::get(format!("http://127.0.0.1:{}/favicon.ico", port)).await.unwrap();
+            assert_eq!(favicon.status(), reqwest::StatusCode::NOT_FOUND);
+            let callback = reqwest::get(format!("http://127.0.0.1:{}/callback?code=abc%2B123&state=xyz", port))
+                .await
+                .unwrap();
+            assert!(callback.status().is_success());
+        });
+
+        assert_eq!(receive_code(&listener, "xyz").await.unwrap(), "abc+123");
+        browser.await.unwrap();
+    }
+}
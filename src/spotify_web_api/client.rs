@@ -0,0 +1,446 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context as ErrorContext, Result};
+use reqwest::{Method, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use tokio::sync::Mutex;
+
+use super::request::{PlaylistCreationRequest, PlaylistTracksRequest, SearchQuery};
+use super::auth::{self, AuthConfig};
+use super::response::{ErrorResponse, Paging, Playlist, PlaylistSnapshot, SearchResponse, Token, Track, User};
+
+/// Maximum number of tracks which can be added to a playlist in one request.
+const MAX_TRACKS_PER_REQUEST: usize = 100;
+
+/// How long before it expires an access token is refreshed.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    pub access_token: String,
+    /// Refresh token used to get a new access token when it expires, if `auth` is set
+    pub refresh_token: Option<String>,
+    /// Lifetime of the access token, if known
+    pub expires_in: Option<Duration>,
+    /// Authorization the tokens were granted with, used to refresh them
+    pub auth: Option<AuthConfig>,
+    /// Base URL of the Web API, changed to run against a local server in tests
+    pub base_url: String,
+    pub max_retries: usize,
+    /// Time to wait before retrying after a server error, or after being rate limited without a `Retry-After` header
+    pub retry_interval: Duration,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            access_token: String::new(),
+            refresh_token: None,
+            expires_in: None,
+            auth: None,
+            base_url: String::from("https://api.spotify.com"),
+            max_retries: 10,
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// An access token, and the refresh token to replace it with when it expires.
+#[derive(Debug, Clone)]
+struct AccessToken {
+    token: String,
+    refresh_token: Option<String>,
+    expires_at: Option<Instant>,
+}
+
+#[derive(Clone)]
+pub struct Client {
+    config: Arc<ClientConfig>,
+    /// Access token in use, shared between clones of the client
+    token: Arc<Mutex<AccessToken>>,
+    client: reqwest::Client,
+}
+
+impl Client {
+    pub fn new(access_token: &str) -> Self {
+        Self::new_with_config(ClientConfig {
+            access_token: access_token.to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// Creates a client using a token granted with `auth`, which is refreshed before it expires.
+    pub fn new_with_token(token: Token, auth: AuthConfig) -> Self {
+        Self::new_with_config(ClientConfig {
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            expires_in: Some(Duration::from_secs(token.expires_in)),
+            auth: Some(auth),
+            ..Default::default()
+        })
+    }
+
+    pub fn new_with_config(config: ClientConfig) -> Self {
+        let token = AccessToken {
+            token: config.access_token.clone(),
+            refresh_token: config.refresh_token.clone(),
+            expires_at: config.expires_in.map(|expires_in| Instant::now() + expires_in),
+        };
+        Self {
+            config: Arc::new(config),
+            token: Arc::new(Mutex::new(token)),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Returns the access token in use, refreshing it first if it is about to expire.
+    async fn get_token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if token
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Instant::now() + TOKEN_REFRESH_MARGIN)
+        {
+            self.refresh(&mut token).await?;
+        }
+        Ok(token.token.clone())
+    }
+
+    /// Replaces an access token which was rejected, unless it has been replaced already. Returns whether there is a
+    /// new token to retry with.
+    async fn refresh_rejected_token(&self, rejected: &str) -> Result<bool> {
+        let mut token = self.token.lock().await;
+        if token.token != rejected {
+            return Ok(true);
+        }
+        self.refresh(&mut token).await
+    }
+
+    /// Gets a new access token with the refresh token, returning false if the token can not be refreshed.
+    async fn refresh(&self, token: &mut AccessToken) -> Result<bool> {
+        let (Some(auth), Some(refresh_token)) = (&self.config.auth, &token.refresh_token) else {
+            return Ok(false);
+        };
+        log::debug!("Spotify: refreshing access token");
+        let new_token = auth::refresh(auth, refresh_token).await?;
+        *token = AccessToken {
+            expires_at: Some(Instant::now() + Duration::from_secs(new_token.expires_in)),
+            // a new refresh token is not always issued
+            refresh_token: new_token.refresh_token.or(token.refresh_token.take()),
+            token: new_token.access_token,
+        };
+        Ok(true)
+    }
+
+    async fn request<Q: Serialize + ?Sized, I: Serialize + ?Sized, O: DeserializeOwned>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        query: Option<&Q>,
+        data: Option<&I>,
+    ) -> Result<O> {
+        let base_url = Url::from_str(&self.config.base_url)?;
+        let url = base_url.join(endpoint)?;
+
+        let mut retry = 0;
+        let mut refreshed_token = false;
+        loop {
+            let access_token = self.get_token().await?;
+            log::debug!("Spotify: {} {}", &method, &url);
+            let mut builder = self
+                .client
+                .request(method.clone(), url.clone())
+                .bearer_auth(&access_token);
+
+            if let Some(query) = query {
+                builder = builder.query(query);
+            }
+            if let Some(data) = data {
+                builder = builder.json(data);
+            }
+
+            let res = builder.send().await?;
+            let status = res.status();
+            // the Web API tells how long to wait when rate limiting, in seconds
+            let retry_after = res
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs);
+            let text = res.text().await?;
+            log::debug!("Spotify: {}: {}", status, text);
+
+            if status.is_success() {
+                return serde_json::from_str(&text)
+                    .with_context(|| format!("could not decode Spotify response: {}", text.chars().take(200).collect::<String>()));
+            }
+
+            // access tokens may be revoked before they expire, so retry once with a new one
+            if status == StatusCode::UNAUTHORIZED && !refreshed_token && self.refresh_rejected_token(&access_token).await? {
+                refreshed_token = true;
+                continue;
+            }
+
+            let retry_in = if status == StatusCode::TOO_MANY_REQUESTS {
+                Some(retry_after.unwrap_or(self.config.retry_interval))
+            } else if status.is_server_error() {
+                Some(self.config.retry_interval)
+            } else {
+                None
+            };
+            match retry_in {
+                Some(retry_in) if retry + 1 < self.config.max_retries => {
+                    log::debug!("Spotify: retrying in {:?}", retry_in);
+                    tokio::time::sleep(retry_in).await;
+                    retry += 1;
+                }
+                _ => return Err(anyhow!(ErrorResponse::from_response(status, &text))),
+            }
+        }
+    }
+
+    pub async fn get_with_query<Q: Serialize + ?Sized, O: DeserializeOwned>(&self, endpoint: &str, query: Option<&Q>) -> Result<O> {
+        self.request::<Q, (), O>(Method::GET, endpoint, query, None).await
+    }
+
+    pub async fn get<O: DeserializeOwned>(&self, endpoint: &str) -> Result<O> {
+        self.get_with_query::<(), O>(endpoint, None).await
+    }
+
+    pub async fn post<I: Serialize + ?Sized, O: DeserializeOwned>(&self, endpoint: &str, data: &I) -> Result<O> {
+        self.request::<(), I, O>(Method::POST, endpoint, None, Some(data)).await
+    }
+
+    pub async fn get_current_user(&self) -> Result<User> {
+        self.get("/v1/me").await
+    }
+
+    pub async fn search(&self, query: &SearchQuery<'_>) -> Result<SearchResponse> {
+        self.get_with_query("/v1/search", Some(query)).await
+    }
+
+    /// Finds a track by its ISRC, using the `isrc:` search filter.
+    pub async fn find_track_by_isrc(&self, isrc: &str) -> Result<Option<Track>> {
+        let res = self
+            .search(&SearchQuery {
+                q: &format!("isrc:{}", isrc),
+                types: "track",
+                limit: Some(1),
+                ..Default::default()
+            })
+            .await?;
+        Ok(res.tracks.and_then(|tracks| tracks.items.into_iter().next()))
+    }
+
+    /// Gets all playlists owned or followed by the current user.
+    pub async fn get_current_user_playlists(&self) -> Result<Vec<Playlist>> {
+        let mut playlists = Vec::new();
+        let mut next = Some(String::from("/v1/me/playlists?limit=50"));
+        while let Some(url) = next {
+            let mut page: Paging<Playlist> = self.get(&url).await?;
+            playlists.append(&mut page.items);
+            next = page.next;
+        }
+        Ok(playlists)
+    }
+
+    pub async fn create_playlist(&self, user_id: &str, data: &PlaylistCreationRequest) -> Result<Playlist> {
+        self.post(&format!("/v1/users/{}/playlists", user_id), data).await
+    }
+
+    /// Adds tracks to the end of a playlist, in batches of 100.
+    pub async fn add_playlist_tracks(&self, playlist_id: &str, uris: &[String]) -> Result<()> {
+        for batch in uris.chunks(MAX_TRACKS_PER_REQUEST) {
+            let _: PlaylistSnapshot = self
+                .post(
+                    &format!("/v1/playlists/{}/tracks", playlist_id),
+                    &PlaylistTracksRequest { uris: batch },
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock_server::{MockRequest, MockResponse, MockServer};
+
+    fn client(server: &MockServer) -> Client {
+        Client::new_with_config(ClientConfig {
+            access_token: String::from("token"),
+            base_url: server.url(),
+            retry_interval: Duration::from_millis(1),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn finds_track_by_isrc() {
+        let server = MockServer::start(|_| {
+            MockResponse::json(
+                200,
+                json!({
+                    "tracks": {
+                        "href": "", "limit": 1, "offset": 0, "total": 1,
+                        "items": [{
+                            "id": "4uLU6hMCjMI75M1A2tKUQC",
+                            "uri": "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+                            "name": "Never Gonna Give You Up",
+                            "duration_ms": 213573,
+                            "artists": [{ "name": "Rick Astley" }],
+                            "album": { "name": "Whenever You Need Somebody" },
+                            "external_ids": { "isrc": "GBARL9300135" }
+                        }]
+                    }
+                }),
+            )
+        })
+        .await;
+
+        let track = client(&server).find_track_by_isrc("GBARL9300135").await.unwrap().unwrap();
+        assert_eq!(track.uri, "spotify:track:4uLU6hMCjMI75M1A2tKUQC");
+        assert_eq!(track.artist_name(), "Rick Astley");
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/v1/search");
+        assert_eq!(requests[0].query_param("q"), Some("isrc:GBARL9300135"));
+        assert_eq!(requests[0].query_param("type"), Some("track"));
+        assert_eq!(requests[0].header("authorization"), Some("Bearer token"));
+    }
+
+    #[tokio::test]
+    async fn fails_with_error_response() {
+        let server = MockServer::start(|_| {
+            MockResponse::json(401, json!({ "error": { "status": 401, "message": "The access token expired" } }))
+        })
+        .await;
+
+        let e = client(&server).find_track_by_isrc("GBARL9300135").await.unwrap_err();
+        let response = e.downcast_ref::<ErrorResponse>().expect("error is an error response");
+        assert!(response.is_unauthorized());
+        assert_eq!(e.to_string(), "The access token expired (Status: 401)");
+        assert_eq!(server.requests().len(), 1, "client errors are not retried");
+
+        let server = MockServer::start(|_| MockResponse::text(502, "text/html", "<html>Bad Gateway</html>")).await;
+        let e = client(&server).get_current_user().await.unwrap_err();
+        assert_eq!(e.to_string(), "Bad Gateway (Status: 502)");
+        assert_eq!(server.requests().len(), ClientConfig::default().max_retries);
+    }
+
+    /// Answers token refreshes with the access token `new`, and API requests with the current user if they use it.
+    fn refreshing_server(request: &MockRequest) -> MockResponse {
+        match request.path.as_str() {
+            "/api/token" => MockResponse::json(200, json!({ "access_token": "new", "token_type": "Bearer", "expires_in": 3600 })),
+            _ if request.header("authorization") == Some("Bearer new") => {
+                MockResponse::json(200, json!({ "id": "user", "display_name": "User" }))
+            }
+            _ => MockResponse::json(401, json!({ "error": { "status": 401, "message": "The access token expired" } })),
+        }
+    }
+
+    fn refreshing_client(server: &MockServer, expires_in: Duration) -> Client {
+        Client::new_with_config(ClientConfig {
+            access_token: String::from("old"),
+            refresh_token: Some(String::from("refresh")),
+            expires_in: Some(expires_in),
+            auth: Some(AuthConfig {
+                accounts_base_url: server.url(),
+                ..AuthConfig::new("client")
+            }),
+            base_url: server.url(),
+            retry_interval: Duration::from_millis(1),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn refreshes_token_before_it_expires() {
+        let server = MockServer::start(refreshing_server).await;
+        let client = refreshing_client(&server, Duration::from_secs(10));
+
+        assert_eq!(client.get_current_user().await.unwrap().id, "user");
+        assert_eq!(client.get_current_user().await.unwrap().id, "user");
+
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, ["/api/token", "/v1/me", "/v1/me"]);
+        let refresh = &server.requests()[0];
+        assert!(refresh.body.contains("grant_type=refresh_token"));
+        assert!(refresh.body.contains("refresh_token=refresh"));
+        assert!(refresh.body.contains("client_id=client"));
+    }
+
+    #[tokio::test]
+    async fn refreshes_rejected_token() {
+        let server = MockServer::start(refreshing_server).await;
+        let client = refreshing_client(&server, Duration::from_secs(3600));
+
+        assert_eq!(client.get_current_user().await.unwrap().id, "user");
+
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, ["/v1/me", "/api/token", "/v1/me"]);
+    }
+
+    #[tokio::test]
+    async fn gets_all_playlists() {
+        let server = MockServer::start(|request| {
+            let playlist = |id: &str| json!({ "id": id, "uri": format!("spotify:playlist:{}", id), "name": id });
+            match request.query_param("offset") {
+                None => MockResponse::json(
+                    200,
+                    json!({
+                        "href": request.path, "limit": 2, "offset": 0, "total": 3,
+                        "items": [playlist("a"), playlist("b")],
+                        "next": format!("{}?offset=2", request.path)
+                    }),
+                ),
+                Some(_) => MockResponse::json(
+                    200,
+                    json!({ "href": request.path, "limit": 2, "offset": 2, "total": 3, "items": [playlist("c")], "next": null }),
+                ),
+            }
+        })
+        .await;
+
+        let playlists = client(&server).get_current_user_playlists().await.unwrap();
+        let names: Vec<&str> = playlists.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(server.requests()[0].query_param("limit"), Some("50"));
+    }
+
+    #[tokio::test]
+    async fn adds_tracks_in_batches_and_retries_when_rate_limited() {
+        let count = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| {
+            if count.fetch_add(1, Ordering::SeqCst) == 0 {
+                MockResponse::json(429, json!({ "error": { "status": 429, "message": "API rate limit exceeded" } }))
+                    .with_header("Retry-After", "0")
+            } else {
+                MockResponse::json(201, json!({ "snapshot_id": "abc" }))
+            }
+        })
+        .await;
+
+        let uris: Vec<String> = (0..250).map(|i| format!("spotify:track:{}", i)).collect();
+        client(&server).add_playlist_tracks("playlist", &uris).await.unwrap();
+
+        let batch_sizes: Vec<usize> = server
+            .requests()
+            .iter()
+            .map(|r| r.json()["uris"].as_array().unwrap().len())
+            .collect();
+        assert_eq!(batch_sizes, [100, 100, 100, 50]);
+        assert!(server
+            .requests()
+            .iter()
+            .all(|r| r.method == "POST" && r.path == "/v1/playlists/playlist/tracks"));
+    }
+}
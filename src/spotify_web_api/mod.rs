@@ -0,0 +1,9 @@
+mod auth;
+pub use auth::{authorize, AuthConfig};
+
+mod client;
+pub use client::Client;
+
+pub mod request;
+#[allow(dead_code)]
+pub mod response;
@@ -0,0 +1,42 @@
+use serde::Serialize;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SearchQuery<'a> {
+	/// (Required) Your search query. Field filters such as `isrc:USUM71703861` narrow down the results.
+	pub q: &'a str,
+
+	/// (Required) A comma-separated list of item types to search across.
+	///
+	/// Possible Values: album, artist, playlist, track, show, episode, audiobook
+	#[serde(rename = "type")]
+	pub types: &'a str,
+
+	/// An ISO 3166-1 alpha-2 country code. If specified, only content available in that market is returned.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub market: Option<&'a str>,
+
+	/// The maximum number of results to return in each item type.
+	///
+	/// - Default: 20
+	/// - Range: 0 - 50
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub limit: Option<usize>,
+
+	/// The index of the first result to return.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaylistCreationRequest {
+	pub name: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub description: Option<String>,
+	pub public: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaylistTracksRequest<'a> {
+	/// Spotify URIs of the tracks to add. A maximum of 100 items can be added in one request.
+	pub uris: &'a [String],
+}
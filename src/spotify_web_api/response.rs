@@ -0,0 +1,149 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Error {
+    pub status: u16,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (Status: {})", &self.message, self.status)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ErrorResponse {
+    pub error: Error,
+}
+
+impl ErrorResponse {
+    /// Decodes the error in the body of an unsuccessful response, falling back to the status if it has none.
+    pub fn from_response(status: StatusCode, body: &str) -> Self {
+        serde_json::from_str(body).unwrap_or_else(|_| Self {
+            error: Error {
+                status: status.as_u16(),
+                message: status.canonical_reason().unwrap_or("Unknown error").to_string(),
+            },
+        })
+    }
+
+    /// Whether the access token was not accepted, for example because it has expired.
+    pub fn is_unauthorized(&self) -> bool {
+        self.error.status == StatusCode::UNAUTHORIZED.as_u16()
+    }
+}
+
+impl fmt::Display for ErrorResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &self.error)
+    }
+}
+
+impl std::error::Error for ErrorResponse {}
+
+/// An access token, as returned by the token endpoint.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Token {
+    pub access_token: String,
+    pub token_type: String,
+    /// Lifetime of the access token, in seconds
+    pub expires_in: u64,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExternalUrls {
+    #[serde(default)]
+    pub spotify: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExternalIds {
+    #[serde(default)]
+    pub isrc: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Paging<T> {
+    pub href: String,
+    pub items: Vec<T>,
+    pub limit: usize,
+    #[serde(default)]
+    pub next: Option<String>,
+    pub offset: usize,
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub id: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub external_urls: ExternalUrls,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimplifiedArtist {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimplifiedAlbum {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Track {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub uri: String,
+    pub name: String,
+    pub duration_ms: u64,
+    pub artists: Vec<SimplifiedArtist>,
+    pub album: SimplifiedAlbum,
+    #[serde(default)]
+    pub external_ids: ExternalIds,
+    #[serde(default)]
+    pub external_urls: ExternalUrls,
+}
+
+impl Track {
+    /// Name of the first artist of the track.
+    pub fn artist_name(&self) -> &str {
+        self.artists.first().map(|a| a.name.as_str()).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchResponse {
+    #[serde(default)]
+    pub tracks: Option<Paging<Track>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Playlist {
+    pub id: String,
+    pub uri: String,
+    pub name: String,
+    #[serde(default)]
+    pub external_urls: ExternalUrls,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlaylistSnapshot {
+    pub snapshot_id: String,
+}
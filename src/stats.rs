@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Local, NaiveDate, Timelike, Utc};
+use console::Term;
+
+use crate::my_spotify_data::extended_streaming_history::{ExtendedStream, ExtendedStreamingHistory};
+use crate::my_spotify_data::streaming_history::{MusicStream, PodcastStream, StreamingHistory};
+use crate::plural;
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const BAR_WIDTH: usize = 30;
+
+/// Streams shorter than this are counted as skipped when the export does not say, as Spotify only counts a stream
+/// after 30 seconds.
+const MIN_STREAM_MS: u64 = 30_000;
+
+/// A single stream of a track or episode, regardless of which export it came from.
+#[derive(Debug, Clone, Copy)]
+pub struct Play<'a> {
+    pub ended_at: DateTime<Utc>,
+    /// Artist name, or show name for podcast episodes
+    pub artist_name: &'a str,
+    /// Album name, if known
+    pub album_name: Option<&'a str>,
+    /// Track name, or episode name for podcast episodes
+    pub track_name: &'a str,
+    /// Spotify track URI, if known
+    pub track_uri: Option<&'a str>,
+    pub ms_played: u64,
+    pub skipped: bool,
+}
+
+impl<'a> Play<'a> {
+    pub fn from_music_stream(stream: &'a MusicStream) -> Self {
+        Self {
+            ended_at: stream.end_time,
+            artist_name: &stream.artist_name,
+            album_name: None,
+            track_name: &stream.track_name,
+            track_uri: None,
+            ms_played: stream.ms_played,
+            skipped: stream.ms_played < MIN_STREAM_MS,
+        }
+    }
+
+    pub fn from_podcast_stream(stream: &'a PodcastStream) -> Self {
+        Self {
+            ended_at: stream.end_time,
+            artist_name: &stream.podcast_name,
+            album_name: None,
+            track_name: &stream.episode_name,
+            track_uri: None,
+            ms_played: stream.ms_played,
+            skipped: stream.ms_played < MIN_STREAM_MS,
+        }
+    }
+
+    /// The stream as a track play, if a track was played.
+    pub fn from_extended_track_stream(stream: &'a ExtendedStream) -> Option<Self> {
+        let (artist_name, track_name) = stream.track()?;
+        Some(Self {
+            ended_at: stream.ts,
+            artist_name,
+            album_name: stream.master_metadata_album_album_name.as_deref(),
+            track_name,
+            track_uri: stream.spotify_track_uri.as_deref(),
+            ms_played: stream.ms_played,
+            skipped: stream.is_skipped(),
+        })
+    }
+
+    /// The stream as an episode play, if a podcast episode was played.
+    pub fn from_extended_episode_stream(stream: &'a ExtendedStream) -> Option<Self> {
+        let (show_name, episode_name) = stream.episode()?;
+        Some(Self {
+            ended_at: stream.ts,
+            artist_name: show_name,
+            album_name: None,
+            track_name: episode_name,
+            track_uri: None,
+            ms_played: stream.ms_played,
+            skipped: stream.is_skipped(),
+        })
+    }
+}
+
+pub struct StatsOptions {
+    /// First day to include, in local time
+    pub from: Option<NaiveDate>,
+    /// Last day to include, in local time
+    pub to: Option<NaiveDate>,
+    /// Number of top artists and tracks to list
+    pub top: usize,
+    /// Minimum number of streams of a track to list its skip and completion rates
+    pub min_streams: usize,
+}
+
+impl StatsOptions {
+    pub fn includes(&self, ended_at: DateTime<Utc>) -> bool {
+        in_date_range(ended_at, self.from, self.to)
+    }
+}
+
+/// Whether a stream ended between the first and last day (inclusive), in local time.
+pub fn in_date_range(ended_at: DateTime<Utc>, from: Option<NaiveDate>, to: Option<NaiveDate>) -> bool {
+    let date = ended_at.with_timezone(&Local).date_naive();
+    from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlayTotal {
+    pub ms_played: u64,
+    pub streams: usize,
+}
+
+impl PlayTotal {
+    fn add(&mut self, ms_played: u64) {
+        self.ms_played += ms_played;
+        self.streams += 1;
+    }
+}
+
+/// Play time aggregated by artist, track, hour of day and weekday.
+#[derive(Debug, Default)]
+pub struct ListeningStats<'a> {
+    pub total: PlayTotal,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+    pub by_artist: HashMap<&'a str, PlayTotal>,
+    pub by_track: HashMap<(&'a str, &'a str), PlayTotal>,
+    /// Play time by hour of day, in local time
+    pub by_hour: [u64; 24],
+    /// Play time by weekday, starting on Monday, in local time
+    pub by_weekday: [u64; 7],
+}
+
+impl<'a> ListeningStats<'a> {
+    pub fn new<I: IntoIterator<Item = Play<'a>>>(plays: I) -> Self {
+        let mut stats = Self::default();
+        for play in plays {
+            stats.total.add(play.ms_played);
+            stats.first = Some(stats.first.map_or(play.ended_at, |first| first.min(play.ended_at)));
+            stats.last = Some(stats.last.map_or(play.ended_at, |last| last.max(play.ended_at)));
+            stats.by_artist.entry(play.artist_name).or_default().add(play.ms_played);
+            stats
+                .by_track
+                .entry((play.artist_name, play.track_name))
+                .or_default()
+                .add(play.ms_played);
+
+            let local_time = play.ended_at.with_timezone(&Local);
+            stats.by_hour[local_time.hour() as usize] += play.ms_played;
+            stats.by_weekday[local_time.weekday().num_days_from_monday() as usize] += play.ms_played;
+        }
+        stats
+    }
+
+    pub fn top_artists(&self, n: usize) -> Vec<(&'a str, PlayTotal)> {
+        top_by_play_time(&self.by_artist, n)
+    }
+
+    pub fn top_tracks(&self, n: usize) -> Vec<((&'a str, &'a str), PlayTotal)> {
+        top_by_play_time(&self.by_track, n)
+    }
+}
+
+/// Entries with the most play time, ties broken by key so the output is stable.
+fn top_by_play_time<K: Copy + Ord>(totals: &HashMap<K, PlayTotal>, n: usize) -> Vec<(K, PlayTotal)> {
+    let mut top: Vec<(K, PlayTotal)> = totals.iter().map(|(k, t)| (*k, *t)).collect();
+    top.sort_by(|(a_key, a), (b_key, b)| b.ms_played.cmp(&a.ms_played).then(a_key.cmp(b_key)));
+    top.truncate(n);
+    top
+}
+
+/// Formats play time as hours and minutes, such as "12h 34m".
+pub fn format_play_time(ms: u64) -> String {
+    let minutes = ms / 60_000;
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+fn bar(value: u64, max: u64) -> String {
+    let width = if max == 0 {
+        0
+    } else {
+        (value as f64 / max as f64 * BAR_WIDTH as f64).round() as usize
+    };
+    format!("{:<width$}", "█".repeat(width), width = BAR_WIDTH)
+}
+
+pub fn print_distribution(term: &Term, title: &str, labels: &[&str], values: &[u64]) -> Result<()> {
+    term.write_line(&format!("\n{}", console::style(title).bold()))?;
+    let max = values.iter().copied().max().unwrap_or_default();
+    for (label, value) in labels.iter().zip(values) {
+        term.write_line(&format!(
+            "  {}  {} {}",
+            label,
+            console::style(bar(*value, max)).cyan(),
+            format_play_time(*value)
+        ))?;
+    }
+    Ok(())
+}
+
+pub fn print_listening_stats(term: &Term, stats: &ListeningStats, top: usize) -> Result<()> {
+    term.write_line(&format!("\n{}", console::style("Top artists").bold()))?;
+    for (i, (artist_name, total)) in stats.top_artists(top).iter().enumerate() {
+        term.write_line(&format!(
+            "  {:>3}. {} - {} ({} stream{})",
+            i + 1,
+            artist_name,
+            format_play_time(total.ms_played),
+            total.streams,
+            plural(total.streams)
+        ))?;
+    }
+
+    term.write_line(&format!("\n{}", console::style("Top tracks").bold()))?;
+    for (i, ((artist_name, track_name), total)) in stats.top_tracks(top).iter().enumerate() {
+        term.write_line(&format!(
+            "  {:>3}. \"{} - {}\" - {} ({} stream{})",
+            i + 1,
+            artist_name,
+            track_name,
+            format_play_time(total.ms_played),
+            total.streams,
+            plural(total.streams)
+        ))?;
+    }
+
+    let hours: Vec<String> = (0..24).map(|h| format!("{:02}", h)).collect();
+    let hours: Vec<&str> = hours.iter().map(String::as_str).collect();
+    print_distribution(term, "Listening by hour", &hours, &stats.by_hour)?;
+    print_distribution(term, "Listening by weekday", &WEEKDAYS, &stats.by_weekday)?;
+
+    Ok(())
+}
+
+fn summary_line(label: &str, stats: &ListeningStats, artists: &str, tracks: &str) -> String {
+    format!(
+        "  {}: {} over {} stream{} ({} {}, {} {})",
+        label,
+        format_play_time(stats.total.ms_played),
+        stats.total.streams,
+        plural(stats.total.streams),
+        stats.by_artist.len(),
+        artists,
+        stats.by_track.len(),
+        tracks
+    )
+}
+
+/// Prints the summary, top lists and distributions of music and podcast plays.
+fn print_summary(term: &Term, music: &ListeningStats, podcasts: &ListeningStats, top: usize) -> Result<()> {
+    let first = music.first.into_iter().chain(podcasts.first).min();
+    let last = music.last.into_iter().chain(podcasts.last).max();
+    let (Some(first), Some(last)) = (first, last) else {
+        term.write_line(&format!(
+            "{} No streams in the selected date range",
+            console::style("✘").red()
+        ))?;
+        return Ok(());
+    };
+
+    term.write_line(&format!(
+        "{} Listening from {} to {}",
+        console::style("✱").blue(),
+        first.with_timezone(&Local).date_naive(),
+        last.with_timezone(&Local).date_naive()
+    ))?;
+    term.write_line(&summary_line("Music", music, "artists", "tracks"))?;
+    term.write_line(&summary_line("Podcasts", podcasts, "shows", "episodes"))?;
+
+    if music.total.streams > 0 {
+        print_listening_stats(term, music, top)?;
+    }
+
+    if podcasts.total.streams > 0 {
+        term.write_line(&format!("\n{}", console::style("Top podcasts").bold()))?;
+        for (i, (podcast_name, total)) in podcasts.top_artists(top).iter().enumerate() {
+            term.write_line(&format!(
+                "  {:>3}. {} - {} ({} episode stream{})",
+                i + 1,
+                podcast_name,
+                format_play_time(total.ms_played),
+                total.streams,
+                plural(total.streams)
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a "Wrapped"-style summary of the streaming history from a Spotify GDPR data dump.
+pub fn print_streaming_history_stats(term: &Term, history: &StreamingHistory, options: &StatsOptions) -> Result<()> {
+    let music = ListeningStats::new(
+        history
+            .music
+            .iter()
+            .filter(|s| options.includes(s.end_time))
+            .map(Play::from_music_stream),
+    );
+    let podcasts = ListeningStats::new(
+        history
+            .podcasts
+            .iter()
+            .filter(|s| options.includes(s.end_time))
+            .map(Play::from_podcast_stream),
+    );
+
+    print_summary(term, &music, &podcasts, options.top)
+}
+
+/// How often a track was skipped or played to the end.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrackOutcomes {
+    pub streams: usize,
+    pub skipped: usize,
+    pub completed: usize,
+}
+
+impl TrackOutcomes {
+    pub fn skip_rate(&self) -> f64 {
+        self.skipped as f64 / self.streams as f64
+    }
+
+    pub fn completion_rate(&self) -> f64 {
+        self.completed as f64 / self.streams as f64
+    }
+}
+
+fn percent(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64 * 100.0
+    }
+}
+
+fn print_breakdown(term: &Term, title: &str, totals: &HashMap<&str, PlayTotal>, top: usize, streams: usize) -> Result<()> {
+    term.write_line(&format!("\n{}", console::style(title).bold()))?;
+    for (name, total) in top_by_play_time(totals, top) {
+        term.write_line(&format!(
+            "  {:<12} {:>5.1}% - {} ({} stream{})",
+            name,
+            percent(total.streams, streams),
+            format_play_time(total.ms_played),
+            total.streams,
+            plural(total.streams)
+        ))?;
+    }
+    Ok(())
+}
+
+/// Prints a "Wrapped"-style summary of the extended streaming history, including skip and completion rates and
+/// platform breakdown.
+pub fn print_extended_streaming_history_stats(
+    term: &Term,
+    history: &ExtendedStreamingHistory,
+    options: &StatsOptions,
+) -> Result<()> {
+    let streams: Vec<&ExtendedStream> = history.streams.iter().filter(|s| options.includes(s.ts)).collect();
+
+    let music = ListeningStats::new(streams.iter().filter_map(|s| Play::from_extended_track_stream(s)));
+    let podcasts = ListeningStats::new(streams.iter().filter_map(|s| Play::from_extended_episode_stream(s)));
+
+    print_summary(term, &music, &podcasts, options.top)?;
+    if streams.is_empty() {
+        return Ok(());
+    }
+
+    let mut outcomes: HashMap<(&str, &str), TrackOutcomes> = HashMap::new();
+    let mut by_platform: HashMap<&str, PlayTotal> = HashMap::new();
+    let mut by_country: HashMap<&str, PlayTotal> = HashMap::new();
+    let (mut skipped, mut shuffled, mut offline) = (0, 0, 0);
+    for stream in &streams {
+        if let Some(track) = stream.track() {
+            let track_outcomes = outcomes.entry(track).or_default();
+            track_outcomes.streams += 1;
+            track_outcomes.skipped += stream.is_skipped() as usize;
+            track_outcomes.completed += stream.is_completed() as usize;
+        }
+        by_platform.entry(stream.platform_family()).or_default().add(stream.ms_played);
+        by_country
+            .entry(stream.conn_country.as_deref().unwrap_or("Unknown"))
+            .or_default()
+            .add(stream.ms_played);
+        skipped += stream.is_skipped() as usize;
+        shuffled += (stream.shuffle == Some(true)) as usize;
+        offline += (stream.offline == Some(true)) as usize;
+    }
+
+    term.write_line(&format!(
+        "\n  Skipped {:.1}% of streams, {:.1}% played on shuffle, {:.1}% played offline",
+        percent(skipped, streams.len()),
+        percent(shuffled, streams.len()),
+        percent(offline, streams.len())
+    ))?;
+
+    // rates of tracks played only a few times say little, so they are left out
+    let mut frequent: Vec<((&str, &str), TrackOutcomes)> = outcomes
+        .into_iter()
+        .filter(|(_, o)| o.streams >= options.min_streams)
+        .collect();
+    frequent.sort_by(|(a_key, a), (b_key, b)| {
+        b.skip_rate()
+            .total_cmp(&a.skip_rate())
+            .then(b.streams.cmp(&a.streams))
+            .then(a_key.cmp(b_key))
+    });
+    term.write_line(&format!(
+        "\n{}",
+        console::style(format!("Most skipped tracks (at least {} streams)", options.min_streams)).bold()
+    ))?;
+    for (i, ((artist_name, track_name), o)) in frequent.iter().take(options.top).enumerate() {
+        term.write_line(&format!(
+            "  {:>3}. \"{} - {}\" - skipped {:.0}% ({} of {} streams)",
+            i + 1,
+            artist_name,
+            track_name,
+            o.skip_rate() * 100.0,
+            o.skipped,
+            o.streams
+        ))?;
+    }
+
+    frequent.sort_by(|(a_key, a), (b_key, b)| {
+        b.completed
+            .cmp(&a.completed)
+            .then(b.completion_rate().total_cmp(&a.completion_rate()))
+            .then(a_key.cmp(b_key))
+    });
+    term.write_line(&format!("\n{}", console::style("Most completed tracks").bold()))?;
+    for (i, ((artist_name, track_name), o)) in frequent.iter().take(options.top).enumerate() {
+        term.write_line(&format!(
+            "  {:>3}. \"{} - {}\" - played to the end {} time{} ({:.0}% of {} streams)",
+            i + 1,
+            artist_name,
+            track_name,
+            o.completed,
+            plural(o.completed),
+            o.completion_rate() * 100.0,
+            o.streams
+        ))?;
+    }
+
+    print_breakdown(term, "Platforms", &by_platform, usize::MAX, streams.len())?;
+    print_breakdown(term, "Countries", &by_country, options.top, streams.len())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn music_stream(day: u32, artist_name: &str, track_name: &str, ms_played: u64) -> MusicStream {
+        MusicStream {
+            end_time: Utc.with_ymd_and_hms(2023, 1, day, 12, 0, 0).unwrap(),
+            artist_name: artist_name.to_string(),
+            track_name: track_name.to_string(),
+            ms_played,
+        }
+    }
+
+    #[test]
+    fn aggregates_plays() {
+        let streams = [
+            music_stream(3, "a-ha", "Take On Me", 200_000),
+            music_stream(1, "Rick Astley", "Never Gonna Give You Up", 100_000),
+            music_stream(2, "a-ha", "Take On Me", 10_000),
+            music_stream(4, "a-ha", "Hunting High and Low", 100_000),
+        ];
+        let plays: Vec<_> = streams.iter().map(Play::from_music_stream).collect();
+        assert!(!plays[0].skipped);
+        assert!(plays[2].skipped, "streams shorter than 30 seconds are skipped");
+
+        let stats = ListeningStats::new(plays);
+        assert_eq!(stats.total.streams, 4);
+        assert_eq!(stats.total.ms_played, 410_000);
+        assert_eq!(stats.first, Some(streams[1].end_time));
+        assert_eq!(stats.last, Some(streams[3].end_time));
+        assert_eq!(stats.by_hour.iter().sum::<u64>(), 410_000);
+        assert_eq!(stats.by_weekday.iter().sum::<u64>(), 410_000);
+
+        let top_artists: Vec<_> = stats.top_artists(5).into_iter().map(|(artist, total)| (artist, total.streams)).collect();
+        assert_eq!(top_artists, [("a-ha", 3), ("Rick Astley", 1)]);
+        // ties are ordered by artist and track name
+        let top_tracks: Vec<_> = stats.top_tracks(2).into_iter().map(|(track, _)| track).collect();
+        assert_eq!(top_tracks, [("a-ha", "Take On Me"), ("Rick Astley", "Never Gonna Give You Up")]);
+        assert_eq!(stats.top_tracks(5)[2].0, ("a-ha", "Hunting High and Low"));
+    }
+
+    #[test]
+    fn formats_play_time() {
+        assert_eq!(format_play_time(59_999), "0m");
+        assert_eq!(format_play_time(45 * 60_000), "45m");
+        assert_eq!(format_play_time(125 * 60_000), "2h 05m");
+    }
+
+    #[test]
+    fn filters_by_local_date() {
+        let ended_at = Local.with_ymd_and_hms(2023, 6, 15, 23, 30, 0).unwrap().with_timezone(&Utc);
+        let day = |d| NaiveDate::from_ymd_opt(2023, 6, d);
+        assert!(in_date_range(ended_at, None, None));
+        assert!(in_date_range(ended_at, day(15), day(15)));
+        assert!(!in_date_range(ended_at, day(16), None));
+        assert!(!in_date_range(ended_at, None, day(14)));
+        assert_eq!(percent(1, 4), 25.0);
+        assert_eq!(percent(1, 0), 0.0);
+    }
+}
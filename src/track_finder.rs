@@ -0,0 +1,474 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::Utc;
+use console::Term;
+use serde::{Deserialize, Serialize};
+
+use crate::apple_music::request::{CatalogSongsQuery, SearchQuery};
+use crate::apple_music::response::{Album, Song};
+use crate::apple_music::{Client, ObjectType};
+use crate::isrc::{self, IsrcResolver};
+use crate::match_cache::{CacheLookup, CachedMatch, MatchCache};
+use crate::matching::{self, AlbumCandidate, FuzzyMatcher, MatchResult, TrackCandidate};
+use crate::review::{self, ReviewDecision, ReviewOptions};
+use crate::track_metadata::TrackMetadataCache;
+
+pub struct TrackFinderOptions {
+    /// Minimum score for a match (between 0.0 and 3.0)
+    pub min_score: f64,
+    /// Limit of possible songs per search result
+    pub limit: usize,
+    /// Resolvers used to look up tracks by ISRC before searching
+    pub isrc_resolvers: Vec<Box<dyn IsrcResolver>>,
+    /// Metadata not included in the Spotify GDPR data dump, such as track durations
+    pub track_metadata: Option<Arc<TrackMetadataCache>>,
+    /// If set, ambiguous matches are reviewed by the user
+    pub review: Option<ReviewOptions>,
+    /// If set, matches are looked up in and added to this cache
+    pub match_cache: Option<MatchCache>,
+}
+
+/// How a catalog song was found for a track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchMethod {
+    Isrc,
+    Search,
+    Review,
+    Cache,
+    Override,
+    /// Matched by an earlier run recorded in the import state file
+    Resumed,
+}
+
+impl MatchMethod {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Isrc => "isrc",
+            Self::Search => "search",
+            Self::Review => "review",
+            Self::Cache => "cache",
+            Self::Override => "override",
+            Self::Resumed => "resumed",
+        }
+    }
+}
+
+/// Why no catalog song was used for a playlist item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    NotFound,
+    BelowThreshold,
+    SkippedInReview,
+    SkippedByOverride,
+    Episode,
+    LocalTrack,
+    /// Not matched by an earlier run recorded in the import state file
+    Resumed,
+}
+
+impl SkipReason {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::NotFound => "not-found",
+            Self::BelowThreshold => "below-threshold",
+            Self::SkippedInReview => "skipped-in-review",
+            Self::SkippedByOverride => "skipped-by-override",
+            Self::Episode => "episode",
+            Self::LocalTrack => "local-track",
+            Self::Resumed => "resumed",
+        }
+    }
+}
+
+/// A catalog song considered for a track.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoredCandidate {
+    pub song_id: String,
+    pub artist_name: String,
+    pub album_name: String,
+    pub name: String,
+    pub url: String,
+    pub score: f64,
+    #[serde(flatten)]
+    pub result: MatchResult,
+}
+
+impl ScoredCandidate {
+    fn new(song: &Song, result: &MatchResult) -> Self {
+        Self {
+            song_id: song.id.clone(),
+            artist_name: song.attributes.artist_name.clone(),
+            album_name: song.attributes.album_name.clone(),
+            name: song.attributes.name.clone(),
+            url: song.attributes.url.clone(),
+            score: result.score(),
+            result: result.clone(),
+        }
+    }
+}
+
+/// A catalog song found for a track.
+#[derive(Debug, Clone)]
+pub struct FoundSong {
+    pub id: String,
+    pub url: Option<String>,
+    pub method: MatchMethod,
+    /// Compound score of the match, or `None` if the song was set by hand
+    pub score: Option<f64>,
+    /// Per-field scores of the match, if it was scored in this run
+    pub result: Option<MatchResult>,
+}
+
+impl FoundSong {
+    fn from_song(song: &Song, result: &MatchResult, method: MatchMethod) -> Self {
+        Self {
+            id: song.id.clone(),
+            url: Some(song.attributes.url.clone()),
+            method,
+            score: Some(result.score()),
+            result: Some(result.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MatchOutcome {
+    Found(FoundSong),
+    Skipped(SkipReason),
+}
+
+/// The outcome of finding a catalog song for a track, along with the candidates considered.
+#[derive(Debug, Clone)]
+pub struct TrackMatch {
+    pub outcome: MatchOutcome,
+    /// Catalog songs considered, best first
+    pub candidates: Vec<ScoredCandidate>,
+}
+
+impl TrackMatch {
+    pub fn skipped(reason: SkipReason) -> Self {
+        Self {
+            outcome: MatchOutcome::Skipped(reason),
+            candidates: Vec::new(),
+        }
+    }
+
+    pub fn found(&self) -> Option<&FoundSong> {
+        match &self.outcome {
+            MatchOutcome::Found(found) => Some(found),
+            MatchOutcome::Skipped(_) => None,
+        }
+    }
+}
+
+/// Finds the Apple Music catalog song matching a track, by ISRC or by searching the catalog.
+pub struct TrackFinder<'a> {
+    client: &'a Client,
+    storefront: &'a str,
+    term: &'a Term,
+    options: &'a TrackFinderOptions,
+    matcher: FuzzyMatcher,
+}
+
+impl<'a> TrackFinder<'a> {
+    pub fn new(client: &'a Client, storefront: &'a str, term: &'a Term, options: &'a TrackFinderOptions) -> Self {
+        Self {
+            client,
+            storefront,
+            term,
+            options,
+            matcher: FuzzyMatcher,
+        }
+    }
+
+    /// Finds the catalog song for a track, optionally identified by its Spotify URI.
+    pub async fn find(&self, track_uri: Option<&str>, query: TrackCandidate<'_>) -> Result<TrackMatch> {
+        let mut query = query;
+        if let (None, Some(track_uri), Some(track_metadata)) = (query.duration_ms, track_uri, &self.options.track_metadata) {
+            query.duration_ms = track_metadata.duration_ms(track_uri);
+        }
+
+        let Some(track_uri) = track_uri else {
+            return self.find_by_search(&query).await;
+        };
+
+        if let Some(match_cache) = &self.options.match_cache {
+            match match_cache.get(self.storefront, track_uri) {
+                Some(CacheLookup::Override(Some(song_id))) => {
+                    self.term.write_line(&format!(
+                        "\t{} Using song {} for \"{} - {}\" from match cache override",
+                        console::style("✔").green(),
+                        &song_id,
+                        query.artist_name,
+                        query.track_name
+                    ))?;
+                    return Ok(TrackMatch {
+                        outcome: MatchOutcome::Found(FoundSong {
+                            id: song_id,
+                            url: None,
+                            method: MatchMethod::Override,
+                            score: None,
+                            result: None,
+                        }),
+                        candidates: Vec::new(),
+                    });
+                }
+                Some(CacheLookup::Override(None)) => {
+                    self.term.write_line(&format!(
+                        "\t{} Skipping \"{} - {}\": Skipped by match cache override",
+                        console::style("✘").red(),
+                        query.artist_name,
+                        query.track_name
+                    ))?;
+                    return Ok(TrackMatch::skipped(SkipReason::SkippedByOverride));
+                }
+                Some(CacheLookup::Match(cached)) => {
+                    self.term.write_line(&format!(
+                        "\t{} Found \"{} - {}\" in match cache (score: {:.6}): {}",
+                        console::style("✔").green(),
+                        query.artist_name,
+                        query.track_name,
+                        cached.score,
+                        cached.url.as_deref().unwrap_or(&cached.song_id)
+                    ))?;
+                    return Ok(TrackMatch {
+                        outcome: MatchOutcome::Found(FoundSong {
+                            id: cached.song_id,
+                            url: cached.url,
+                            method: MatchMethod::Cache,
+                            score: Some(cached.score),
+                            result: None,
+                        }),
+                        candidates: Vec::new(),
+                    });
+                }
+                None => {}
+            }
+        }
+
+        let track_match = match self.find_by_isrc(track_uri, &query).await? {
+            Some(track_match) => track_match,
+            None => self.find_by_search(&query).await?,
+        };
+
+        if let (Some(found), Some(match_cache)) = (track_match.found(), &self.options.match_cache) {
+            match_cache.insert(
+                self.storefront,
+                track_uri,
+                CachedMatch {
+                    song_id: found.id.clone(),
+                    score: found.score.unwrap_or_default(),
+                    matched_at: Utc::now(),
+                    description: Some(format!("{} - {}", query.artist_name, query.track_name)),
+                    url: found.url.clone(),
+                },
+            )?;
+        }
+
+        Ok(track_match)
+    }
+
+    async fn find_by_isrc(&self, track_uri: &str, query: &TrackCandidate<'_>) -> Result<Option<TrackMatch>> {
+        // look up by ISRC first, as it identifies the exact recording
+        let Some(isrc) = isrc::resolve(&self.options.isrc_resolvers, track_uri).await? else {
+            return Ok(None);
+        };
+
+        self.term.write_str(&format!(
+            "\t{} Looking up ISRC {} in the Apple Music catalog... ",
+            console::style("✱").blue(),
+            &isrc
+        ))?;
+
+        let songs = self
+            .client
+            .get_catalog_songs(
+                self.storefront,
+                &CatalogSongsQuery {
+                    isrc: Some(&isrc),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        // the same recording may appear on several releases, so prefer the closest one
+        let ranked_songs = matching::rank(&self.matcher, query, &songs.data);
+
+        self.term.clear_line()?;
+        if let Some((song, result)) = ranked_songs.first() {
+            self.term.write_line(&format!(
+                "\t{} Found \"{} - {}\" in the Apple Music catalog by ISRC {} (score: {:.6}): {}",
+                console::style("✔").green(),
+                &song.attributes.artist_name,
+                &song.attributes.name,
+                &isrc,
+                result.score(),
+                &song.attributes.url
+            ))?;
+
+            return Ok(Some(TrackMatch {
+                outcome: MatchOutcome::Found(FoundSong::from_song(song, result, MatchMethod::Isrc)),
+                candidates: ranked_songs
+                    .iter()
+                    .map(|(song, result)| ScoredCandidate::new(song, result))
+                    .collect(),
+            }));
+        }
+
+        log::debug!("No catalog songs with ISRC {}, falling back to search", &isrc);
+        Ok(None)
+    }
+
+    async fn find_by_search(&self, query: &TrackCandidate<'_>) -> Result<TrackMatch> {
+        let mut search_term = format!("{} {}", query.artist_name, query.track_name);
+        let review = self.options.review.filter(|_| dialoguer::console::user_attended());
+
+        loop {
+            self.term.write_str(&format!(
+                "\t{} Searching for {:?} in the Apple Music catalog... ",
+                console::style("✱").blue(),
+                search_term
+            ))?;
+
+            let search_res = self
+                .client
+                .search_catalog(
+                    self.storefront,
+                    &SearchQuery {
+                        term: &search_term,
+                        types: ObjectType::Songs.as_str(),
+                        limit: Some(self.options.limit),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            let songs = search_res.results.songs.map(|songs| songs.data).unwrap_or_default();
+
+            // score songs by fuzzy match of artist, album and track name
+            let ranked_songs = matching::rank(&self.matcher, query, &songs);
+            let candidates = ranked_songs
+                .iter()
+                .map(|(song, result)| ScoredCandidate::new(song, result))
+                .collect();
+
+            self.term.clear_line()?;
+
+            let (picked, method) = match review {
+                Some(review) if review.needs_review(&ranked_songs) => {
+                    let track_description = format!("\"{} - {}\"", query.artist_name, query.track_name);
+                    match review::review(self.term, &track_description, &search_term, &ranked_songs)? {
+                        ReviewDecision::Pick(i) => (ranked_songs.get(i), MatchMethod::Review),
+                        ReviewDecision::Skip => {
+                            self.term.write_line(&format!(
+                                "\t{} Skipping {:?}: Skipped during review",
+                                console::style("✘").red(),
+                                &search_term
+                            ))?;
+                            return Ok(TrackMatch {
+                                outcome: MatchOutcome::Skipped(SkipReason::SkippedInReview),
+                                candidates,
+                            });
+                        }
+                        ReviewDecision::Search(term) => {
+                            search_term = term;
+                            continue;
+                        }
+                    }
+                }
+                _ => (
+                    ranked_songs
+                        .first()
+                        .filter(|(_, result)| result.is_match(self.options.min_score)),
+                    MatchMethod::Search,
+                ),
+            };
+
+            if let Some((song, result)) = picked {
+                self.term.write_line(&format!(
+                    "\t{} Found \"{} - {}\" in the Apple Music catalog (score: {:.6}): {}",
+                    console::style("✔").green(),
+                    &song.attributes.artist_name,
+                    &song.attributes.name,
+                    result.score(),
+                    &song.attributes.url
+                ))?;
+
+                return Ok(TrackMatch {
+                    outcome: MatchOutcome::Found(FoundSong::from_song(song, result, method)),
+                    candidates,
+                });
+            }
+
+            self.term.write_line(&format!(
+                "\t{} Skipping {:?}: Could not be found in the Apple Music catalog",
+                console::style("✘").red(),
+                &search_term
+            ))?;
+
+            let reason = if ranked_songs.is_empty() {
+                SkipReason::NotFound
+            } else {
+                SkipReason::BelowThreshold
+            };
+            return Ok(TrackMatch {
+                outcome: MatchOutcome::Skipped(reason),
+                candidates,
+            });
+        }
+    }
+
+    /// Finds the catalog album matching a saved album, by artist and album name.
+    pub async fn find_album(&self, query: AlbumCandidate<'_>) -> Result<Option<Album>> {
+        let search_term = format!("{} {}", query.artist_name, query.album_name);
+        self.term.write_str(&format!(
+            "\t{} Searching for album {:?} in the Apple Music catalog... ",
+            console::style("✱").blue(),
+            search_term
+        ))?;
+
+        let search_res = self
+            .client
+            .search_catalog(
+                self.storefront,
+                &SearchQuery {
+                    term: &search_term,
+                    types: ObjectType::Albums.as_str(),
+                    limit: Some(self.options.limit),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let albums = search_res.results.albums.map(|albums| albums.data).unwrap_or_default();
+        let ranked_albums = matching::rank_albums(&query, &albums);
+
+        self.term.clear_line()?;
+        match ranked_albums
+            .first()
+            .filter(|(_, result)| result.is_match(self.options.min_score))
+        {
+            Some((album, result)) => {
+                self.term.write_line(&format!(
+                    "\t{} Found album \"{} - {}\" in the Apple Music catalog (score: {:.6}): {}",
+                    console::style("✔").green(),
+                    &album.attributes.artist_name,
+                    &album.attributes.name,
+                    result.score(),
+                    &album.attributes.url
+                ))?;
+                Ok(Some((*album).clone()))
+            }
+            None => {
+                self.term.write_line(&format!(
+                    "\t{} Skipping album {:?}: Could not be found in the Apple Music catalog",
+                    console::style("✘").red(),
+                    &search_term
+                ))?;
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Context as ErrorContext, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::isrc::{self, IsrcResolver};
+
+/// Metadata of a Spotify track which is not included in the GDPR data dump.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackMetadata {
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub isrc: Option<String>,
+}
+
+/// Sidecar file with metadata of Spotify tracks, keyed by track URI.
+///
+/// ```json
+/// {
+///     "spotify:track:4uLU6hMCjMI75M1A2tKUQC": { "durationMs": 212000, "isrc": "GBARL9300135" }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TrackMetadataCache {
+    tracks: HashMap<String, TrackMetadata>,
+}
+
+impl TrackMetadataCache {
+    pub fn load<P: AsRef<Path>>(metadata_path: P) -> Result<Self> {
+        let metadata_file = File::open(metadata_path).context("could not open track metadata file")?;
+        let tracks = serde_json::from_reader(metadata_file).context("could not parse track metadata file")?;
+        Ok(Self { tracks })
+    }
+
+    pub fn duration_ms(&self, track_uri: &str) -> Option<u64> {
+        self.tracks.get(track_uri).and_then(|t| t.duration_ms)
+    }
+}
+
+#[async_trait]
+impl IsrcResolver for TrackMetadataCache {
+    async fn resolve(&self, track_uri: &str) -> Result<Option<String>> {
+        Ok(self
+            .tracks
+            .get(track_uri)
+            .and_then(|t| t.isrc.as_deref())
+            .map(isrc::normalize)
+            .filter(|isrc| !isrc.is_empty()))
+    }
+}